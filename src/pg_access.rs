@@ -7,19 +7,28 @@
 //!
 //! The module-level static `ACQUIRED_PG_BINS` prevents concurrent downloads
 //! of the same binaries when multiple [`crate::postgres::PgEmbed`] instances
-//! start simultaneously.
+//! start simultaneously within one process; a file lock next to the cache
+//! directory does the same across processes.  Binaries are installed into the
+//! cache atomically (see [`crate::pg_cache`]), so an interrupted acquisition
+//! never leaves a half-populated cache directory behind.
 
 use std::cell::Cell;
 use std::collections::HashMap;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, LazyLock};
+use std::time::Duration;
 
 use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 
+use crate::pg_bundle::{self, BundleMetadata};
+use crate::pg_cache::{self, CacheEntry, CacheLock, CacheManifest, UsageLock};
 use crate::pg_enums::{OperationSystem, PgAcquisitionStatus};
 use crate::pg_errors::Error;
-use crate::pg_fetch::PgFetchSettings;
+use crate::pg_fetch::{PgBinarySource, PgFetchSettings, PostgresVersion};
+use crate::pg_install;
+use crate::pg_preflight::{self, PreflightReport, PreflightTarget};
 use crate::pg_types::PgCommandSync;
 use crate::pg_unpack;
 use crate::pg_errors::Result;
@@ -33,6 +42,10 @@ static ACQUIRED_PG_BINS: LazyLock<Arc<Mutex<HashMap<PathBuf, PgAcquisitionStatus
     LazyLock::new(|| Arc::new(Mutex::new(HashMap::with_capacity(5))));
 
 const PG_EMBED_CACHE_DIR_NAME: &str = "pg-embed";
+
+/// Environment variable that overrides the root of the binary cache when
+/// [`PgFetchSettings::cache_dir`] is not set.
+pub const PG_EMBED_CACHE_DIR_ENV: &str = "PG_EMBED_CACHE_DIR";
 const PG_VERSION_FILE_NAME: &str = "PG_VERSION";
 
 /// Manages all file-system paths and I/O operations for a single pg-embed instance.
@@ -44,13 +57,23 @@ const PG_VERSION_FILE_NAME: &str = "PG_VERSION";
 /// # Cache layout
 ///
 /// ```text
-/// {cache_dir}/pg-embed/{os}/{arch}/{version}/
+/// {cache_root}/{os}/{arch}/{version}[-{tag}]/
 ///   bin/pg_ctl
 ///   bin/initdb
 ///   {platform}-{version}.zip
+///   .pg-embed-manifest.json
 /// ```
+///
+/// `{cache_root}` is described at [`Self::cache_root`] and `{tag}` at
+/// [`crate::pg_fetch::CacheKey`].  The version directory only appears once
+/// the binaries are completely installed; see [`crate::pg_cache`].
+///
+/// For an existing installation ([`PgBinarySource::Directory`] or
+/// [`PgBinarySource::PgConfig`]) [`Self::cache_dir`] is the installation
+/// prefix instead and nothing is downloaded.
 pub struct PgAccess {
-    /// Root of the per-version binary cache.
+    /// Root of the per-version binary cache, or the installation prefix of an
+    /// existing installation.
     pub cache_dir: PathBuf,
     /// Directory that holds the PostgreSQL cluster data files.
     pub database_dir: PathBuf,
@@ -58,6 +81,13 @@ pub struct PgAccess {
     pub pg_ctl_exe: PathBuf,
     /// Path to the `initdb` executable inside the cache.
     pub init_db_exe: PathBuf,
+    /// Path to the `postgres` server executable inside the cache.
+    pub postgres_exe: PathBuf,
+    /// Version reported by `postgres --version` of an existing installation.
+    ///
+    /// `None` for downloaded binaries, whose version is
+    /// [`PgFetchSettings::version`].
+    pub detected_version: Option<PostgresVersion>,
     /// Path to the password file used by `initdb`.
     pub pw_file_path: PathBuf,
     /// Path where the downloaded JAR is written before unpacking.
@@ -72,13 +102,15 @@ pub struct PgAccess {
 impl PgAccess {
     /// Creates a new [`PgAccess`] and ensures the required directories exist.
     ///
-    /// Both the per-version binary cache directory and `database_dir` are
-    /// created with [`tokio::fs::create_dir_all`] if they do not already exist.
+    /// Both the parent of the per-version binary cache directory and
+    /// `database_dir` are created with [`tokio::fs::create_dir_all`] if they do
+    /// not already exist.  The version directory itself is created by
+    /// [`Self::maybe_acquire_postgres`].
     ///
     /// # Arguments
     ///
     /// * `fetch_settings` — Determines the OS, architecture, and version used
-    ///   to construct the cache path.
+    ///   to construct the cache path, or the existing installation to use.
     /// * `database_dir` — Where the PostgreSQL cluster data files will live.
     ///
     /// # Errors
@@ -87,16 +119,37 @@ impl PgAccess {
     /// resolved.
     /// Returns [`Error::DirCreationError`] if either directory cannot be
     /// created.
+    /// Returns [`Error::BinariesNotFound`] if an existing installation was
+    /// requested but cannot be located or run.
     pub async fn new(
         fetch_settings: &PgFetchSettings,
         database_dir: &Path,
     ) -> Result<Self> {
-        let cache_dir = Self::create_cache_dir_structure(fetch_settings).await?;
+        let (bin_dir, detected_version) = match &fetch_settings.binary_source {
+            PgBinarySource::Download | PgBinarySource::Embedded(_) => (None, None),
+            PgBinarySource::Directory(dir) => {
+                let bin_dir = pg_install::locate_bin_dir(dir).await?;
+                Self::existing_installation(bin_dir).await?
+            }
+            PgBinarySource::PgConfig => {
+                let bin_dir = pg_install::pg_config_bin_dir().await?;
+                Self::existing_installation(bin_dir).await?
+            }
+        };
+        let cache_dir = match &bin_dir {
+            None => Self::create_cache_dir_structure(fetch_settings).await?,
+            Some(bin_dir) => bin_dir
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| bin_dir.clone()),
+        };
+        let bin_dir = bin_dir.unwrap_or_else(|| cache_dir.join("bin"));
         Self::create_db_dir_structure(database_dir).await?;
         let platform = fetch_settings.platform();
-        let pg_ctl = cache_dir.join("bin/pg_ctl");
-        let init_db = cache_dir.join("bin/initdb");
-        let zip_file_path = cache_dir.join(format!("{}-{}.zip", platform, fetch_settings.version.0));
+        let pg_ctl = bin_dir.join("pg_ctl");
+        let init_db = bin_dir.join("initdb");
+        let postgres_exe = bin_dir.join("postgres");
+        let zip_file_path = cache_dir.join(format!("{}-{}.zip", platform, fetch_settings.version));
         let mut pw_file = database_dir.to_path_buf();
         pw_file.set_extension("pwfile");
         let pg_version_file = database_dir.join(PG_VERSION_FILE_NAME);
@@ -106,6 +159,8 @@ impl PgAccess {
             database_dir: database_dir.to_path_buf(),
             pg_ctl_exe: pg_ctl,
             init_db_exe: init_db,
+            postgres_exe,
+            detected_version,
             pw_file_path: pw_file,
             zip_file_path,
             pg_version_file,
@@ -113,15 +168,65 @@ impl PgAccess {
         })
     }
 
-    /// Creates the OS-specific cache directory tree for this OS/arch/version.
+    /// Detects the version of the installation in `bin_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BinariesNotFound`] if `postgres --version` fails.
+    async fn existing_installation(
+        bin_dir: PathBuf,
+    ) -> Result<(Option<PathBuf>, Option<PostgresVersion>)> {
+        let version =
+            pg_install::detect_version(&bin_dir.join(pg_install::exe_name("postgres"))).await?;
+        log::info!("Using PostgreSQL {} from {}", version, bin_dir.display());
+        Ok((Some(bin_dir), Some(version)))
+    }
+
+    /// Returns the root of the pg-embed cache for `fetch_settings`.
+    ///
+    /// In order of precedence this is [`PgFetchSettings::cache_dir`], the
+    /// [`PG_EMBED_CACHE_DIR_ENV`] environment variable, or `pg-embed` in the
+    /// OS cache directory (e.g. `~/.cache/pg-embed`).  Nothing is created on
+    /// disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPgUrl`] if neither override is set and the OS
+    /// cache directory cannot be resolved.
+    pub fn cache_root(fetch_settings: &PgFetchSettings) -> Result<PathBuf> {
+        Self::resolve_cache_root(
+            fetch_settings.cache_dir.as_deref(),
+            std::env::var_os(PG_EMBED_CACHE_DIR_ENV),
+        )
+    }
+
+    /// Picks the cache root from the configured directory, the value of
+    /// [`PG_EMBED_CACHE_DIR_ENV`], or the OS default.
+    fn resolve_cache_root(configured: Option<&Path>, env: Option<OsString>) -> Result<PathBuf> {
+        if let Some(dir) = configured {
+            return Ok(dir.to_path_buf());
+        }
+        if let Some(dir) = env.filter(|dir| !dir.is_empty()) {
+            return Ok(PathBuf::from(dir));
+        }
+        let mut cache_dir = dirs::cache_dir().ok_or(Error::InvalidPgUrl)?;
+        cache_dir.push(PG_EMBED_CACHE_DIR_NAME);
+        Ok(cache_dir)
+    }
+
+    /// Returns the per-version binary cache directory for `fetch_settings`,
+    /// e.g. `~/.cache/pg-embed/linux/amd64/17.8.0`, or
+    /// `~/.cache/pg-embed/linux/amd64/17.8.0-src-91ad03c4e5f2` for a
+    /// non-default source (see [`crate::pg_fetch::CacheKey`]).
+    ///
+    /// Nothing is created on disk.
     ///
     /// # Errors
     ///
     /// Returns [`Error::InvalidPgUrl`] if the OS cache directory cannot be
     /// resolved.
-    /// Returns [`Error::DirCreationError`] if the directory cannot be created.
-    async fn create_cache_dir_structure(fetch_settings: &PgFetchSettings) -> Result<PathBuf> {
-        let cache_dir = dirs::cache_dir().ok_or(Error::InvalidPgUrl)?;
+    pub fn binary_cache_dir(fetch_settings: &PgFetchSettings) -> Result<PathBuf> {
+        let cache_dir = Self::cache_root(fetch_settings)?;
         let os_string = match fetch_settings.operating_system {
             OperationSystem::Darwin | OperationSystem::Windows | OperationSystem::Linux => {
                 fetch_settings.operating_system.to_string()
@@ -130,18 +235,34 @@ impl PgAccess {
                 format!("arch_{}", fetch_settings.operating_system)
             }
         };
+        let version_dir = match fetch_settings.cache_tag() {
+            Some(tag) => format!("{}-{}", fetch_settings.version, tag),
+            None => fetch_settings.version.to_string(),
+        };
         let pg_path = format!(
-            "{}/{}/{}/{}",
-            PG_EMBED_CACHE_DIR_NAME,
-            os_string,
-            fetch_settings.architecture,
-            fetch_settings.version.0
+            "{}/{}/{}",
+            os_string, fetch_settings.architecture, version_dir
         );
         let mut cache_pg_embed = cache_dir;
         cache_pg_embed.push(pg_path);
-        tokio::fs::create_dir_all(&cache_pg_embed)
-            .await
-            .map_err(|e| Error::DirCreationError(e.to_string()))?;
+        Ok(cache_pg_embed)
+    }
+
+    /// Creates the OS-specific cache directory tree for this OS/arch and
+    /// returns the (not yet existing) version directory inside it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPgUrl`] if the OS cache directory cannot be
+    /// resolved.
+    /// Returns [`Error::DirCreationError`] if the directory cannot be created.
+    async fn create_cache_dir_structure(fetch_settings: &PgFetchSettings) -> Result<PathBuf> {
+        let cache_pg_embed = Self::binary_cache_dir(fetch_settings)?;
+        if let Some(parent) = cache_pg_embed.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| Error::DirCreationError(e.to_string()))?;
+        }
         Ok(cache_pg_embed)
     }
 
@@ -158,35 +279,90 @@ impl PgAccess {
 
     /// Downloads and unpacks the PostgreSQL binaries if they are not already cached.
     ///
+    /// Does nothing for an existing installation (see
+    /// [`PgBinarySource`]).
+    ///
     /// Acquires the `ACQUIRED_PG_BINS` lock for the duration.  If another
     /// instance already cached the binaries (i.e. [`Self::pg_executables_cached`]
     /// returns `true`), this method returns immediately without downloading.
+    /// Otherwise the cross-process cache lock is taken as well, waiting up to
+    /// [`PgFetchSettings::cache_lock_timeout`] for another process that is
+    /// acquiring the same binaries; if that process succeeds, nothing is
+    /// downloaded here.
+    ///
+    /// The bundle is downloaded and unpacked into a staging directory next to
+    /// [`Self::cache_dir`], a [`CacheManifest`] is written once extraction has
+    /// finished, and the staging directory is then renamed into place.  A
+    /// [`Self::cache_dir`] without a manifest is left over from an interrupted
+    /// acquisition and is replaced.
+    ///
+    /// In offline mode (see [`PgFetchSettings::offline`]) nothing is
+    /// downloaded: missing binaries are an error.
+    ///
+    /// An [`PgBinarySource::Embedded`] bundle is verified against its digest
+    /// and extracted the same way instead of downloading, also in offline
+    /// mode.
     ///
     /// # Errors
     ///
+    /// Returns [`Error::OfflineCacheMiss`] if the binaries are not cached and
+    /// offline mode is enabled or the `download` feature is disabled.
     /// Returns [`Error::DirCreationError`] if directories cannot be created.
     /// Returns [`Error::DownloadFailure`] or [`Error::ConversionFailure`] if
     /// the HTTP download fails.
     /// Returns [`Error::WriteFileError`] if the JAR cannot be written to disk.
+    /// Returns [`Error::ChecksumMismatch`] if the download or embedded bundle
+    /// fails verification; nothing is unpacked in that case.
     /// Returns [`Error::UnpackFailure`] or [`Error::InvalidPgPackage`] if
     /// extraction fails.
+    /// Returns [`Error::PgPurgeFailure`] if a corrupt cache directory cannot be
+    /// removed.
+    /// Returns [`Error::CacheLockTimeout`] if another process holds the cache
+    /// lock for longer than [`PgFetchSettings::cache_lock_timeout`].
     pub async fn maybe_acquire_postgres(&self) -> Result<()> {
+        if self.uses_existing_installation() {
+            return Ok(());
+        }
         let mut lock = ACQUIRED_PG_BINS.lock().await;
 
         if self.pg_executables_cached().await? {
             return Ok(());
         }
+        if let PgBinarySource::Embedded(bundle) = &self.fetch_settings.binary_source {
+            bundle.install_into(&self.cache_dir, &self.fetch_settings).await?;
+            lock.insert(self.cache_dir.clone(), PgAcquisitionStatus::Finished);
+            return Ok(());
+        }
+        if let Some(reason) = self.fetch_settings.downloads_disabled() {
+            return Err(Error::OfflineCacheMiss {
+                version: self.fetch_settings.version.to_string(),
+                platform: self.fetch_settings.platform(),
+                cache_dir: self.cache_dir.display().to_string(),
+                reason: reason.to_string(),
+            });
+        }
+        let _cache_lock =
+            CacheLock::acquire(&self.cache_dir, self.fetch_settings.cache_lock_timeout).await?;
+        // Another process may have finished the acquisition while we waited.
+        if self.pg_executables_cached().await? {
+            lock.insert(self.cache_dir.clone(), PgAcquisitionStatus::Finished);
+            return Ok(());
+        }
+        pg_cache::remove_stale_staging_dirs(&self.cache_dir).await?;
+        if Self::path_exists(&self.cache_dir).await? {
+            log::warn!(
+                "{} has no completion marker; discarding incomplete binaries",
+                self.cache_dir.display()
+            );
+        }
 
         lock.insert(self.cache_dir.clone(), PgAcquisitionStatus::InProgress);
-        self.fetch_settings
-            .fetch_postgres_to_file(&self.zip_file_path)
-            .await?;
-        log::debug!(
-            "Unpacking postgres binaries {} {}",
-            self.zip_file_path.display(),
-            self.cache_dir.display()
-        );
-        pg_unpack::unpack_postgres(&self.zip_file_path, &self.cache_dir).await?;
+        let staging = pg_cache::staging_dir(&self.cache_dir);
+        let result = self.acquire_into(&staging).await;
+        if result.is_err() {
+            let _ = pg_cache::remove_dir_if_exists(&staging).await;
+        }
+        result?;
 
         if let Some(status) = lock.get_mut(&self.cache_dir) {
             *status = PgAcquisitionStatus::Finished;
@@ -194,13 +370,127 @@ impl PgAccess {
         Ok(())
     }
 
-    /// Returns `true` if the `initdb` executable is present in the cache.
+    /// Downloads and unpacks the binaries into `staging`, then installs it as
+    /// [`Self::cache_dir`].
+    async fn acquire_into(&self, staging: &Path) -> Result<()> {
+        tokio::fs::create_dir_all(staging)
+            .await
+            .map_err(|e| Error::DirCreationError(e.to_string()))?;
+        let zip_file_name = self
+            .zip_file_path
+            .file_name()
+            .ok_or(Error::InvalidPgUrl)?;
+        let staged_zip = staging.join(zip_file_name);
+        let (digests, source_url) = self.fetch_settings.fetch_postgres_to_file(&staged_zip).await?;
+        log::debug!(
+            "Unpacking postgres binaries {} {}",
+            staged_zip.display(),
+            staging.display()
+        );
+        let unpack_options = self.fetch_settings.unpack_options();
+        pg_unpack::unpack_postgres_with(&staged_zip, staging, &unpack_options).await?;
+
+        let manifest = CacheManifest {
+            version: self.fetch_settings.version.to_string(),
+            platform: self.fetch_settings.platform(),
+            source_url,
+            sha256: digests.sha256,
+            files: Vec::new(),
+        };
+        pg_cache::install(staging, &self.cache_dir, manifest).await
+    }
+
+    /// Exports the cached binaries to a single bundle file at `dest`.
+    ///
+    /// See [`pg_bundle::export_bundle`].
     ///
     /// # Errors
     ///
-    /// Returns [`Error::ReadFileError`] if the filesystem existence check fails.
+    /// Returns [`Error::BinariesNotFound`] if the binaries are not completely
+    /// installed in the cache, e.g. because [`Self::maybe_acquire_postgres`]
+    /// has not run yet or an existing installation is used.
+    /// Returns [`Error::ReadFileError`] or [`Error::WriteFileError`] if the
+    /// bundle cannot be written.
+    pub async fn export_bundle(&self, dest: &Path) -> Result<BundleMetadata> {
+        pg_bundle::export_from(&self.cache_dir, dest).await
+    }
+
+    /// Seeds the cache from a bundle written by [`Self::export_bundle`] or a
+    /// zonky `.jar` at `src`, without network access.
+    ///
+    /// A subsequent [`Self::maybe_acquire_postgres`] uses the imported
+    /// binaries.  See [`pg_bundle::import_bundle`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BinariesNotFound`] if an existing installation is
+    /// used, since there is no cache to import into.
+    /// Otherwise returns the errors of [`pg_bundle::import_bundle`].
+    pub async fn import_bundle(&self, src: &Path) -> Result<CacheManifest> {
+        if self.uses_existing_installation() {
+            return Err(Error::BinariesNotFound(format!(
+                "cannot import {} while using the installation in {}",
+                src.display(),
+                self.cache_dir.display()
+            )));
+        }
+        let mut lock = ACQUIRED_PG_BINS.lock().await;
+        let manifest = pg_bundle::import_into(&self.cache_dir, &self.fetch_settings, src).await?;
+        lock.insert(self.cache_dir.clone(), PgAcquisitionStatus::Finished);
+        Ok(manifest)
+    }
+
+    /// Checks that the binaries can run on this host.
+    ///
+    /// Runs every [`crate::pg_preflight::PreflightCheck`]: the executables
+    /// exist and are executable, `postgres --version` reports the expected
+    /// version, `ldd` resolves every shared library of `postgres`, and there
+    /// is enough free space for the cluster and in `/dev/shm`.  Call after
+    /// [`Self::maybe_acquire_postgres`].
+    ///
+    /// # Returns
+    ///
+    /// A report with one result per check.  Use
+    /// [`PreflightReport::ensure_ok`] to turn failures into an error.
+    pub async fn preflight(&self) -> PreflightReport {
+        let target = PreflightTarget {
+            executables: [&self.init_db_exe, &self.pg_ctl_exe, &self.postgres_exe],
+            postgres_exe: &self.postgres_exe,
+            expected_version: self.detected_version.unwrap_or(self.fetch_settings.version),
+            database_dir: &self.database_dir,
+        };
+        pg_preflight::run(&target).await
+    }
+
+    /// Returns `true` if the binaries come from an existing installation
+    /// rather than the download cache.
+    pub fn uses_existing_installation(&self) -> bool {
+        matches!(
+            self.fetch_settings.binary_source,
+            PgBinarySource::Directory(_) | PgBinarySource::PgConfig
+        )
+    }
+
+    /// Returns `true` if the binaries are completely installed in the cache.
+    ///
+    /// Both the [`CacheManifest`] completion marker and the `initdb`
+    /// executable must be present.  For an existing installation only the
+    /// executable is checked.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReadFileError`] if a filesystem existence check fails.
     pub async fn pg_executables_cached(&self) -> Result<bool> {
-        Self::path_exists(self.init_db_exe.as_path()).await
+        if self.uses_existing_installation() {
+            return Self::path_exists(
+                &self.init_db_exe.with_file_name(pg_install::exe_name("initdb")),
+            )
+            .await;
+        }
+        Ok(
+            Self::path_exists(&self.cache_dir.join(pg_cache::MANIFEST_FILE_NAME)).await?
+                && Self::path_exists(self.init_db_exe.as_path()).await?,
+        )
     }
 
     /// Returns `true` if both the executables and the cluster version file exist.
@@ -277,7 +567,13 @@ impl PgAccess {
         dir_result.and(file_result)
     }
 
-    /// Removes the entire `pg-embed` binary cache directory.
+    /// Removes every cached version from the default `pg-embed` binary cache
+    /// directory.
+    ///
+    /// The directory honours [`PG_EMBED_CACHE_DIR_ENV`]; use
+    /// [`Self::purge_cache`] for a cache root configured through
+    /// [`PgFetchSettings::cache_dir`].  See [`Self::purge_cache`] for what is
+    /// kept.
     ///
     /// Useful for freeing disk space or forcing a fresh download.  Errors
     /// during removal are silently ignored (the function always returns `Ok`).
@@ -287,13 +583,131 @@ impl PgAccess {
     /// Returns [`Error::ReadFileError`] if the OS cache directory cannot be
     /// resolved.
     pub async fn purge() -> Result<()> {
-        let mut cache_dir = dirs::cache_dir()
-            .ok_or_else(|| Error::ReadFileError("cache dir not found".into()))?;
-        cache_dir.push(PG_EMBED_CACHE_DIR_NAME);
-        let _ = tokio::fs::remove_dir_all(&cache_dir).await;
+        Self::purge_cache(&PgFetchSettings::default()).await
+    }
+
+    /// Removes every cached version (`{os}/{arch}/{version}`) from the binary
+    /// cache root of `fetch_settings` (see [`Self::cache_root`]).
+    ///
+    /// Only pg-embed's own entries are removed, through
+    /// [`pg_cache::remove_entries`]: the root itself and anything else in it
+    /// stay, since it may be a shared directory such as `/var/cache`, and
+    /// versions in use by a cluster or being acquired are kept.
+    ///
+    /// Errors during removal are silently ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReadFileError`] if the cache root cannot be resolved.
+    pub async fn purge_cache(fetch_settings: &PgFetchSettings) -> Result<()> {
+        let cache_dir = Self::cache_root(fetch_settings)
+            .map_err(|_| Error::ReadFileError("cache dir not found".into()))?;
+        let _ = pg_cache::remove_entries(&cache_dir, |_| true).await;
         Ok(())
     }
 
+    /// Lists the versions in the binary cache root of `fetch_settings`, with
+    /// their size on disk and last use.
+    ///
+    /// See [`pg_cache::inventory`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPgUrl`] if the cache root cannot be resolved.
+    /// Otherwise returns the errors of [`pg_cache::inventory`].
+    pub async fn cache_inventory(fetch_settings: &PgFetchSettings) -> Result<Vec<CacheEntry>> {
+        pg_cache::inventory(&Self::cache_root(fetch_settings)?).await
+    }
+
+    /// Removes the cached versions for which `predicate` returns `true`,
+    /// except those in use by a cluster.
+    ///
+    /// ```rust,no_run
+    /// # use pg_embed::pg_access::PgAccess;
+    /// # use pg_embed::pg_fetch::PgFetchSettings;
+    /// # async fn example() -> pg_embed::pg_errors::Result<()> {
+    /// let settings = PgFetchSettings::default();
+    /// // Everything older than PostgreSQL 16:
+    /// PgAccess::remove_cached(&settings, |e| e.version.major() < 16).await?;
+    /// // Everything for another platform:
+    /// PgAccess::remove_cached(&settings, |e| e.os == "darwin" && e.arch == "arm64v8").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// The removed entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPgUrl`] if the cache root cannot be resolved.
+    /// Otherwise returns the errors of [`pg_cache::remove_entries`].
+    pub async fn remove_cached(
+        fetch_settings: &PgFetchSettings,
+        predicate: impl Fn(&CacheEntry) -> bool,
+    ) -> Result<Vec<CacheEntry>> {
+        pg_cache::remove_entries(&Self::cache_root(fetch_settings)?, predicate).await
+    }
+
+    /// Removes incomplete, unused and least recently used versions from the
+    /// cache root of `fetch_settings` until it satisfies both limits.
+    ///
+    /// Versions in use by a cluster are never removed.  See [`pg_cache::gc`].
+    ///
+    /// # Arguments
+    ///
+    /// * `fetch_settings` — Determines the cache root.
+    /// * `max_total_size` — Size limit for the whole cache in bytes.
+    /// * `max_age` — Versions not used for longer than this are removed.
+    ///
+    /// # Returns
+    ///
+    /// The removed entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPgUrl`] if the cache root cannot be resolved.
+    /// Otherwise returns the errors of [`pg_cache::gc`].
+    pub async fn gc_cache(
+        fetch_settings: &PgFetchSettings,
+        max_total_size: Option<u64>,
+        max_age: Option<Duration>,
+    ) -> Result<Vec<CacheEntry>> {
+        pg_cache::gc(&Self::cache_root(fetch_settings)?, max_total_size, max_age).await
+    }
+
+    /// Marks the cached binaries as in use so that [`Self::gc_cache`] and
+    /// [`Self::remove_cached`] keep them until the guard is dropped.
+    ///
+    /// Waits while a removal of the version is in progress.
+    ///
+    /// # Returns
+    ///
+    /// `None` for an existing installation, which is not part of the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::CacheLockTimeout`] if a removal takes longer than
+    /// [`PgFetchSettings::cache_lock_timeout`], or the lock errors of
+    /// [`Self::maybe_acquire_postgres`].
+    pub(crate) async fn lock_in_use(&self) -> Result<Option<UsageLock>> {
+        if self.uses_existing_installation() {
+            return Ok(None);
+        }
+        UsageLock::shared(&self.cache_dir, self.fetch_settings.cache_lock_timeout)
+            .await
+            .map(Some)
+    }
+
+    /// Records a use of the cached binaries for
+    /// [`CacheEntry::last_used`].
+    pub(crate) fn mark_used(&self) {
+        if !self.uses_existing_installation() {
+            pg_cache::mark_used(&self.cache_dir);
+        }
+    }
+
     /// Removes `database_dir` and `pw_file` asynchronously.
     ///
     /// Unlike [`Self::clean`], this is an `async` free-standing helper and
@@ -340,6 +754,25 @@ impl PgAccess {
             .map_err(|e| Error::WriteFileError(e.to_string()))
     }
 
+    /// Returns the path of the `extension/` directory inside the binary cache.
+    ///
+    /// Searches for an existing `extension/` subdirectory under `share/` in the
+    /// cache (trying common PostgreSQL layout variants).  Falls back to
+    /// `share/postgresql/extension` — the standard location used by the
+    /// zonkyio binaries — when none of the candidates exist yet.
+    async fn share_extension_dir(cache_dir: &Path) -> PathBuf {
+        let candidates = [
+            cache_dir.join("share/postgresql/extension"),
+            cache_dir.join("share/extension"),
+        ];
+        for candidate in &candidates {
+            if tokio::fs::try_exists(candidate).await.unwrap_or(false) {
+                return candidate.clone();
+            }
+        }
+        candidates[0].clone()
+    }
+
     /// Installs a third-party extension into the binary cache.
     ///
     /// Copies files from `extension_dir` into the appropriate subdirectory of
@@ -355,6 +788,10 @@ impl PgAccess {
     /// [`crate::postgres::PgEmbed::start_db`], then run
     /// `CREATE EXTENSION IF NOT EXISTS <name>` once the server is up.
     ///
+    /// Not available for an existing installation
+    /// ([`Self::uses_existing_installation`]): its directories belong to the
+    /// system, so install extensions there with its package manager.
+    ///
     /// # Arguments
     ///
     /// * `extension_dir` — Directory containing the extension files to install.
@@ -366,26 +803,15 @@ impl PgAccess {
     /// Returns [`Error::ReadFileError`] if `extension_dir` cannot be read or a
     /// directory entry cannot be inspected.
     /// Returns [`Error::WriteFileError`] if a file cannot be copied.
-    /// Returns the path of the `extension/` directory inside the binary cache.
-    ///
-    /// Searches for an existing `extension/` subdirectory under `share/` in the
-    /// cache (trying common PostgreSQL layout variants).  Falls back to
-    /// `share/postgresql/extension` — the standard location used by the
-    /// zonkyio binaries — when none of the candidates exist yet.
-    async fn share_extension_dir(cache_dir: &Path) -> PathBuf {
-        let candidates = [
-            cache_dir.join("share/postgresql/extension"),
-            cache_dir.join("share/extension"),
-        ];
-        for candidate in &candidates {
-            if tokio::fs::try_exists(candidate).await.unwrap_or(false) {
-                return candidate.clone();
-            }
-        }
-        candidates[0].clone()
-    }
-
+    /// Returns [`Error::InvalidSettings`] for an existing installation.
     pub async fn install_extension(&self, extension_dir: &Path) -> Result<()> {
+        if self.uses_existing_installation() {
+            return Err(Error::InvalidSettings(format!(
+                "install_extension cannot copy files into the existing installation at {}; \
+                 install the extension with its package manager",
+                self.cache_dir.display()
+            )));
+        }
         let lib_dir = self.cache_dir.join("lib");
         let share_ext_dir = Self::share_extension_dir(&self.cache_dir).await;
 
@@ -451,7 +877,113 @@ impl PgAccess {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::pg_fetch::{PgFetchSettings, PG_V17};
+    use crate::pg_fetch::{PgBinarySource, PgFetchSettings, PG_V17};
+    use crate::pg_preflight::{CheckStatus, PreflightCheck};
+
+    #[test]
+    fn test_cache_root_precedence() -> Result<()> {
+        let configured = Path::new("/srv/pg-cache");
+        assert_eq!(
+            PgAccess::resolve_cache_root(Some(configured), Some("/tmp/env-cache".into()))?,
+            configured
+        );
+        assert_eq!(
+            PgAccess::resolve_cache_root(None, Some("/tmp/env-cache".into()))?,
+            Path::new("/tmp/env-cache")
+        );
+        let default = PgAccess::resolve_cache_root(None, Some(OsString::new()))?;
+        assert!(default.ends_with(PG_EMBED_CACHE_DIR_NAME));
+        assert_eq!(PgAccess::resolve_cache_root(None, None)?, default);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_fails_without_download() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let db_dir = tempfile::TempDir::new().unwrap();
+        let fetch_settings = PgFetchSettings {
+            version: PG_V17,
+            // Unreachable: a download attempt would fail differently.
+            hosts: vec!["http://127.0.0.1:1".to_string()],
+            cache_dir: Some(root.path().to_path_buf()),
+            offline: true,
+            ..Default::default()
+        };
+        let pg_access = PgAccess::new(&fetch_settings, db_dir.path()).await?;
+
+        let err = pg_access.maybe_acquire_postgres().await.unwrap_err();
+        let Error::OfflineCacheMiss {
+            version,
+            platform,
+            cache_dir,
+            ..
+        } = &err
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(version, &PG_V17.to_string());
+        assert_eq!(platform, &fetch_settings.platform());
+        assert_eq!(cache_dir, &pg_access.cache_dir.display().to_string());
+        assert!(err.to_string().contains(cache_dir.as_str()));
+        assert!(!pg_access.cache_dir.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_purge_cache_removes_only_cached_versions() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let fetch_settings = PgFetchSettings {
+            version: PG_V17,
+            cache_dir: Some(root.path().to_path_buf()),
+            ..Default::default()
+        };
+        let cache_dir = PgAccess::binary_cache_dir(&fetch_settings)?;
+        let staging = root.path().join("staging");
+        std::fs::create_dir_all(staging.join("bin")).unwrap();
+        std::fs::write(staging.join("bin/initdb"), b"#!/bin/sh\n").unwrap();
+        std::fs::create_dir_all(cache_dir.parent().unwrap()).unwrap();
+        let manifest = CacheManifest {
+            version: PG_V17.to_string(),
+            platform: fetch_settings.platform(),
+            source_url: "https://example.invalid/pg.jar".to_string(),
+            sha256: "00".repeat(32),
+            files: Vec::new(),
+        };
+        pg_cache::install(&staging, &cache_dir, manifest).await?;
+        std::fs::write(root.path().join("unrelated.txt"), b"keep").unwrap();
+
+        let in_use = UsageLock::shared(&cache_dir, Some(Duration::ZERO)).await?;
+        PgAccess::purge_cache(&fetch_settings).await?;
+        assert!(cache_dir.exists());
+
+        drop(in_use);
+        PgAccess::purge_cache(&fetch_settings).await?;
+        assert!(!cache_dir.exists());
+        assert!(root.path().join("unrelated.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_cache_dir_is_keyed_by_source() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let maven = PgFetchSettings {
+            version: PG_V17,
+            cache_dir: Some(root.path().to_path_buf()),
+            ..Default::default()
+        };
+        let maven_dir = PgAccess::binary_cache_dir(&maven)?;
+        assert!(maven_dir.starts_with(root.path()));
+        assert_eq!(maven_dir.file_name().unwrap(), PG_V17.to_string().as_str());
+
+        let mirror = PgFetchSettings {
+            hosts: vec!["https://mirror.example.com".to_string()],
+            ..maven.clone()
+        };
+        let mirror_dir = PgAccess::binary_cache_dir(&mirror)?;
+        assert_eq!(mirror_dir.parent(), maven_dir.parent());
+        assert_ne!(mirror_dir, maven_dir);
+        Ok(())
+    }
 
     #[tokio::test]
     async fn test_install_extension() {
@@ -472,6 +1004,8 @@ mod tests {
             database_dir: cache_path.join("db"),
             pg_ctl_exe: cache_path.join("bin/pg_ctl"),
             init_db_exe: cache_path.join("bin/initdb"),
+            postgres_exe: cache_path.join("bin/postgres"),
+            detected_version: None,
             pw_file_path: cache_path.join("db.pwfile"),
             zip_file_path: cache_path.join("pg.zip"),
             pg_version_file: cache_path.join("db/PG_VERSION"),
@@ -503,4 +1037,86 @@ mod tests {
             "README.txt should not be in share/postgresql/extension/"
         );
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_existing_installation() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let prefix = tempfile::TempDir::new().unwrap();
+        let bin_dir = prefix.path().join("bin");
+        std::fs::create_dir_all(&bin_dir).unwrap();
+        for (name, script) in [
+            ("initdb", "#!/bin/sh\nexit 0\n"),
+            ("pg_ctl", "#!/bin/sh\nexit 0\n"),
+            ("postgres", "#!/bin/sh\necho 'postgres (PostgreSQL) 16.4'\n"),
+        ] {
+            let path = bin_dir.join(name);
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let db_dir = tempfile::TempDir::new().unwrap();
+
+        let fetch_settings = PgFetchSettings {
+            // Unreachable: the existing installation must not touch the network.
+            hosts: vec!["http://127.0.0.1:19999".to_string()],
+            binary_source: PgBinarySource::Directory(prefix.path().to_path_buf()),
+            ..Default::default()
+        };
+        let pg_access = PgAccess::new(&fetch_settings, &db_dir.path().join("db")).await?;
+
+        assert_eq!(pg_access.cache_dir, prefix.path());
+        assert_eq!(pg_access.init_db_exe, bin_dir.join("initdb"));
+        assert_eq!(pg_access.pg_ctl_exe, bin_dir.join("pg_ctl"));
+        assert_eq!(pg_access.detected_version, Some(PostgresVersion::new(16, 4, 0)));
+        assert!(pg_access.pg_executables_cached().await?);
+        pg_access.maybe_acquire_postgres().await?;
+
+        let ext_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(ext_dir.path().join("myvec.so"), b"fake so").unwrap();
+        assert!(matches!(
+            pg_access.install_extension(ext_dir.path()).await,
+            Err(Error::InvalidSettings(_))
+        ));
+        assert!(!prefix.path().join("lib").exists());
+
+        let report = pg_access.preflight().await;
+        let version = report.get(PreflightCheck::Version).unwrap();
+        assert_eq!(version.status, CheckStatus::Passed, "{}", version.detail);
+        assert_eq!(
+            report.get(PreflightCheck::Executables).unwrap().status,
+            CheckStatus::Passed
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_preflight_names_failed_checks() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let db_dir = tempfile::TempDir::new().unwrap();
+        let fetch_settings = PgFetchSettings {
+            version: PG_V17,
+            cache_dir: Some(root.path().to_path_buf()),
+            ..Default::default()
+        };
+        let pg_access = PgAccess::new(&fetch_settings, db_dir.path()).await?;
+        let bin_dir = pg_access.postgres_exe.parent().unwrap();
+        std::fs::create_dir_all(bin_dir).unwrap();
+        std::fs::write(&pg_access.postgres_exe, "#!/bin/sh\necho 'postgres (PostgreSQL) 16.4'\n")
+            .unwrap();
+
+        let report = pg_access.preflight().await;
+        let executables = report.get(PreflightCheck::Executables).unwrap();
+        assert_eq!(executables.status, CheckStatus::Failed);
+        assert!(executables.detail.contains("initdb is missing"), "{}", executables.detail);
+        assert!(executables.detail.contains("postgres is not executable"));
+        assert_eq!(report.get(PreflightCheck::Version).unwrap().status, CheckStatus::Failed);
+        let Err(Error::PreflightFailed(message)) = report.ensure_ok() else {
+            panic!("expected a preflight failure:\n{report}");
+        };
+        assert!(message.starts_with("executables: "), "{message}");
+        assert!(message.contains("; version: "), "{message}");
+        Ok(())
+    }
 }
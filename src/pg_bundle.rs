@@ -0,0 +1,773 @@
+//! Offline transfer of cached PostgreSQL binaries.
+//!
+//! [`export_bundle`] packs one completed version cache directory
+//! (`{os}/{arch}/{version}`) into a single uncompressed tar file, preceded by
+//! a [`BundleMetadata`] entry.  [`import_bundle`] installs such a file — or a
+//! zonky `.jar` as published on Maven, or any other
+//! [`BundleFormat`](crate::pg_unpack::BundleFormat) — into the cache of
+//! another machine without touching the network.  Once imported,
+//! [`PgAccess::maybe_acquire_postgres`](crate::pg_access::PgAccess::maybe_acquire_postgres)
+//! finds the binaries in the cache and skips the download.
+//!
+//! Imports go through the same staging directory, completion manifest and
+//! cross-process lock as downloads (see [`crate::pg_cache`]), so an
+//! interrupted import never leaves a half-populated cache directory behind.
+
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pg_access::PgAccess;
+use crate::pg_cache::{self, CacheLock, CacheManifest, MANIFEST_FILE_NAME};
+use crate::pg_checksum::BundleHasher;
+use crate::pg_errors::{Error, Result};
+use crate::pg_fetch::PgFetchSettings;
+use crate::pg_install;
+use crate::pg_unpack::{self, BundleFormat, ExtractionPolicy};
+
+/// Name of the metadata entry at the start of every exported bundle.
+pub const BUNDLE_METADATA_FILE_NAME: &str = "pg-embed-bundle.json";
+
+/// Current version of the bundle layout.
+const BUNDLE_FORMAT: u32 = 1;
+
+/// File name prefix of the zonky artifacts on Maven.
+const ZONKY_JAR_PREFIX: &str = "embedded-postgres-binaries-";
+
+/// Metadata stored as the first entry of an exported bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleMetadata {
+    /// Layout version of the bundle.
+    pub format: u32,
+    /// Path of the version directory inside the bundle and relative to the
+    /// pg-embed cache root, e.g. `linux/amd64/17.8.0`.
+    pub path: String,
+    /// Manifest of the exported cache directory.
+    pub manifest: CacheManifest,
+}
+
+/// Exports the cached binaries described by `fetch_settings` to `dest`.
+///
+/// # Arguments
+///
+/// * `fetch_settings` — Selects the OS, architecture and version to export.
+/// * `dest` — The bundle file to write.  Replaced if it exists.
+///
+/// # Returns
+///
+/// The metadata written into the bundle.
+///
+/// # Errors
+///
+/// Returns [`Error::BinariesNotFound`] if the version is not completely
+/// installed in the cache.
+/// Returns [`Error::ReadFileError`] or [`Error::WriteFileError`] if reading
+/// the cache or writing `dest` fails.
+pub async fn export_bundle(
+    fetch_settings: &PgFetchSettings,
+    dest: &Path,
+) -> Result<BundleMetadata> {
+    let cache_dir = PgAccess::binary_cache_dir(fetch_settings)?;
+    export_from(&cache_dir, dest).await
+}
+
+/// Imports a bundle or zonky `.jar` from `src` into the cache described by
+/// `fetch_settings`.
+///
+/// The file type is detected from its content.  A bundle must have been
+/// exported for the same version and platform.  A `.jar` — or a `.tar.gz`,
+/// `.tar.xz` or `.tar.zst` archive, see
+/// [`BundleFormat`](crate::pg_unpack::BundleFormat) — is checked against
+/// [`PgFetchSettings::pinned_checksum`] if one is set; there are no
+/// sidecar files to compare against offline.  It is unpacked with
+/// [`PgFetchSettings::bundle_format`] and
+/// [`PgFetchSettings::strip_components`].  A `.jar` named as on Maven must
+/// be named for the expected platform and version, and unpacked binaries
+/// that run on this machine must report the expected version.
+///
+/// If the version is already installed, the cache is left unchanged.
+///
+/// # Returns
+///
+/// The manifest of the installed cache directory.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidPgPackage`] if `src` is neither a bundle nor a
+/// `.jar`, is corrupt, or was exported or built for another version or
+/// platform.
+/// Returns [`Error::ChecksumMismatch`] if a `.jar` does not match the pinned
+/// checksum.
+/// Returns [`Error::CacheLockTimeout`] if another process holds the cache
+/// lock for longer than [`PgFetchSettings::cache_lock_timeout`].
+/// Returns [`Error::ReadFileError`], [`Error::WriteFileError`],
+/// [`Error::DirCreationError`] or [`Error::UnpackFailure`] on I/O failures.
+pub async fn import_bundle(
+    fetch_settings: &PgFetchSettings,
+    src: &Path,
+) -> Result<CacheManifest> {
+    let cache_dir = PgAccess::binary_cache_dir(fetch_settings)?;
+    import_into(&cache_dir, fetch_settings, src).await
+}
+
+/// Exports the completed cache directory `cache_dir` to `dest`.
+///
+/// The bundle is written next to `dest` first and renamed into place, so an
+/// interrupted export never leaves a truncated bundle at `dest`.
+pub(crate) async fn export_from(cache_dir: &Path, dest: &Path) -> Result<BundleMetadata> {
+    let manifest = CacheManifest::read(cache_dir).await?.ok_or_else(|| {
+        Error::BinariesNotFound(format!(
+            "{} is not a completely installed binary cache directory",
+            cache_dir.display()
+        ))
+    })?;
+    let metadata = BundleMetadata {
+        format: BUNDLE_FORMAT,
+        path: bundle_path(cache_dir),
+        manifest,
+    };
+
+    let cache_dir = cache_dir.to_path_buf();
+    let dest = dest.to_path_buf();
+    let written = metadata.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut partial = dest.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let result = write_bundle(&cache_dir, &partial, &written).and_then(|()| {
+            std::fs::rename(&partial, &dest).map_err(|e| Error::WriteFileError(e.to_string()))
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        result
+    })
+    .await
+    .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))??;
+    Ok(metadata)
+}
+
+/// Imports `src` into `cache_dir`; see [`import_bundle`].
+pub(crate) async fn import_into(
+    cache_dir: &Path,
+    fetch_settings: &PgFetchSettings,
+    src: &Path,
+) -> Result<CacheManifest> {
+    let kind = sniff(src).await?;
+    if let Some(parent) = cache_dir.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| Error::DirCreationError(e.to_string()))?;
+    }
+    let _cache_lock = CacheLock::acquire(cache_dir, fetch_settings.cache_lock_timeout).await?;
+    if let Some(existing) = CacheManifest::read(cache_dir).await? {
+        log::info!(
+            "{} is already installed; not importing {}",
+            cache_dir.display(),
+            src.display()
+        );
+        return Ok(existing);
+    }
+    pg_cache::remove_stale_staging_dirs(cache_dir).await?;
+
+    let staging = pg_cache::staging_dir(cache_dir);
+    tokio::fs::create_dir_all(&staging)
+        .await
+        .map_err(|e| Error::DirCreationError(e.to_string()))?;
+    let result = match kind {
+        SourceKind::Archive => import_archive(&staging, fetch_settings, src).await,
+        SourceKind::Bundle => import_tar(&staging, fetch_settings, src).await,
+    };
+    let manifest = match result {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            let _ = pg_cache::remove_dir_if_exists(&staging).await;
+            return Err(e);
+        }
+    };
+    pg_cache::install(&staging, cache_dir, manifest).await?;
+    log::info!("Imported {} into {}", src.display(), cache_dir.display());
+    CacheManifest::read(cache_dir)
+        .await?
+        .ok_or_else(|| Error::ReadFileError(format!("{} has no manifest", cache_dir.display())))
+}
+
+/// Kinds of file [`import_bundle`] accepts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SourceKind {
+    /// A zonky `.jar` as published on Maven, or another [`BundleFormat`].
+    Archive,
+    /// A bundle written by [`export_bundle`].
+    Bundle,
+}
+
+/// Detects the kind of `src` from its first bytes.
+async fn sniff(src: &Path) -> Result<SourceKind> {
+    let src = src.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&src).map_err(|e| Error::ReadFileError(e.to_string()))?;
+        let mut header = [0u8; 512];
+        let mut len = 0;
+        while len < header.len() {
+            match file.read(&mut header[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) => return Err(Error::ReadFileError(e.to_string())),
+            }
+        }
+        if BundleFormat::detect(&header[..len]).is_some() {
+            Ok(SourceKind::Archive)
+        } else if len == header.len() && &header[257..262] == b"ustar" {
+            Ok(SourceKind::Bundle)
+        } else {
+            log::error!(
+                "{} is neither a pg-embed bundle nor a PostgreSQL archive",
+                src.display()
+            );
+            Err(Error::InvalidPgPackage)
+        }
+    })
+    .await
+    .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))?
+}
+
+/// Copies a zonky `.jar` or another archive into `staging` and unpacks it
+/// there.
+///
+/// Unlike a bundle, an archive records neither version nor platform, so
+/// both are checked where they can be: the name of a `.jar` as published on
+/// Maven, and the version reported by the unpacked `bin/postgres`.
+async fn import_archive(
+    staging: &Path,
+    fetch_settings: &PgFetchSettings,
+    src: &Path,
+) -> Result<CacheManifest> {
+    check_jar_name(src, fetch_settings)?;
+    let file_name = src.file_name().ok_or(Error::InvalidPgPackage)?;
+    let staged_archive = staging.join(file_name);
+    let (src_owned, staged) = (src.to_path_buf(), staged_archive.clone());
+    let digests = tokio::task::spawn_blocking(move || {
+        let mut reader =
+            std::fs::File::open(&src_owned).map_err(|e| Error::ReadFileError(e.to_string()))?;
+        let mut writer =
+            std::fs::File::create(&staged).map_err(|e| Error::WriteFileError(e.to_string()))?;
+        let mut hasher = BundleHasher::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader
+                .read(&mut buffer)
+                .map_err(|e| Error::ReadFileError(e.to_string()))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            std::io::Write::write_all(&mut writer, &buffer[..n])
+                .map_err(|e| Error::WriteFileError(e.to_string()))?;
+        }
+        writer
+            .sync_data()
+            .map_err(|e| Error::WriteFileError(e.to_string()))?;
+        Ok(hasher.finalize())
+    })
+    .await
+    .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))??;
+
+    match &fetch_settings.pinned_checksum {
+        Some(expected) => digests.verify(expected)?,
+        None => log::warn!(
+            "Importing {} without verification; set pinned_checksum to verify it",
+            src.display()
+        ),
+    }
+    let unpack_options = fetch_settings.unpack_options();
+    pg_unpack::unpack_postgres_with(&staged_archive, staging, &unpack_options).await?;
+    check_unpacked_version(staging, fetch_settings, src).await?;
+
+    let source = std::fs::canonicalize(src).unwrap_or_else(|_| src.to_path_buf());
+    Ok(CacheManifest {
+        version: fetch_settings.version.to_string(),
+        platform: fetch_settings.platform(),
+        source_url: format!("file://{}", source.display()),
+        sha256: digests.sha256,
+        files: Vec::new(),
+    })
+}
+
+/// Checks the name of a zonky `.jar` as published on Maven
+/// (`embedded-postgres-binaries-{platform}-{version}.jar`) against the
+/// platform and version of `fetch_settings`.  Other names are not checked.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidPgPackage`] if the name is for another platform or
+/// version.
+fn check_jar_name(src: &Path, fetch_settings: &PgFetchSettings) -> Result<()> {
+    let Some(coordinates) = src
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix(ZONKY_JAR_PREFIX))
+        .and_then(|name| name.strip_suffix(".jar"))
+    else {
+        return Ok(());
+    };
+    let expected = format!("{}-{}", fetch_settings.platform(), fetch_settings.version);
+    if coordinates != expected {
+        log::error!(
+            "{} is named for {}, expected {}",
+            src.display(),
+            coordinates,
+            expected
+        );
+        return Err(Error::InvalidPgPackage);
+    }
+    Ok(())
+}
+
+/// Compares the version reported by the unpacked `bin/postgres --version`
+/// with [`PgFetchSettings::version`].  Binaries that cannot run here, e.g.
+/// those for another platform, are imported with a warning.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidPgPackage`] if the binaries report another
+/// version.
+async fn check_unpacked_version(
+    staging: &Path,
+    fetch_settings: &PgFetchSettings,
+    src: &Path,
+) -> Result<()> {
+    let postgres_exe = staging.join("bin").join(pg_install::exe_name("postgres"));
+    match pg_install::detect_version(&postgres_exe).await {
+        Ok(version) if version == fetch_settings.version => Ok(()),
+        Ok(version) => {
+            log::error!(
+                "{} contains PostgreSQL {}, expected {}",
+                src.display(),
+                version,
+                fetch_settings.version
+            );
+            Err(Error::InvalidPgPackage)
+        }
+        Err(e) => {
+            log::warn!("Could not check the version of {}: {}", src.display(), e);
+            Ok(())
+        }
+    }
+}
+
+/// Extracts an exported bundle into `staging`.
+async fn import_tar(
+    staging: &Path,
+    fetch_settings: &PgFetchSettings,
+    src: &Path,
+) -> Result<CacheManifest> {
+    let (src_owned, staging_owned) = (src.to_path_buf(), staging.to_path_buf());
+    let extraction = fetch_settings.extraction;
+    let metadata =
+        tokio::task::spawn_blocking(move || read_bundle(&src_owned, &staging_owned, &extraction))
+            .await
+            .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))??;
+
+    let manifest = metadata.manifest;
+    if manifest.version != fetch_settings.version.to_string()
+        || manifest.platform != fetch_settings.platform()
+    {
+        log::error!(
+            "{} contains PostgreSQL {} for {}, expected {} for {}",
+            src.display(),
+            manifest.version,
+            manifest.platform,
+            fetch_settings.version,
+            fetch_settings.platform()
+        );
+        return Err(Error::InvalidPgPackage);
+    }
+    let files = pg_cache::list_files(staging).await?;
+    if files != manifest.files {
+        log::error!(
+            "{} is incomplete: expected {} files, found {}",
+            src.display(),
+            manifest.files.len(),
+            files.len()
+        );
+        return Err(Error::InvalidPgPackage);
+    }
+    Ok(manifest)
+}
+
+/// Returns the last three components of `cache_dir` (`{os}/{arch}/{version}`).
+fn bundle_path(cache_dir: &Path) -> String {
+    let mut components: Vec<String> = cache_dir
+        .components()
+        .rev()
+        .take(3)
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    components.reverse();
+    components.join("/")
+}
+
+/// Blocking implementation of the export.
+fn write_bundle(cache_dir: &Path, dest: &Path, metadata: &BundleMetadata) -> Result<()> {
+    let file = std::fs::File::create(dest).map_err(|e| Error::WriteFileError(e.to_string()))?;
+    let mut builder = tar::Builder::new(file);
+    builder.follow_symlinks(false);
+
+    let json =
+        serde_json::to_vec_pretty(metadata).map_err(|e| Error::WriteFileError(e.to_string()))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, BUNDLE_METADATA_FILE_NAME, json.as_slice())
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    builder
+        .append_dir_all(&metadata.path, cache_dir)
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+
+    let file = builder
+        .into_inner()
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    file.sync_all()
+        .map_err(|e| Error::WriteFileError(e.to_string()))
+}
+
+/// Blocking implementation of the bundle extraction.
+///
+/// Only entries below [`BundleMetadata::path`] are extracted.  The manifest
+/// inside the bundle is skipped because [`pg_cache::install`] writes a fresh
+/// one.  Every extracted entry is checked against `extraction`, and entries
+/// that would be written outside the staging directory — by their name or
+/// through a symlink extracted earlier — are rejected before anything is
+/// written.
+fn read_bundle(src: &Path, staging: &Path, extraction: &ExtractionPolicy) -> Result<BundleMetadata> {
+    let file = std::fs::File::open(src).map_err(|e| Error::ReadFileError(e.to_string()))?;
+    let mut archive = tar::Archive::new(file);
+    archive.set_preserve_permissions(true);
+    let mut entries = archive.entries().map_err(|_| Error::InvalidPgPackage)?;
+
+    let mut first = entries
+        .next()
+        .ok_or(Error::InvalidPgPackage)?
+        .map_err(|_| Error::InvalidPgPackage)?;
+    if first.path().map_err(|_| Error::InvalidPgPackage)?.as_ref()
+        != Path::new(BUNDLE_METADATA_FILE_NAME)
+    {
+        log::error!("{} has no {} entry", src.display(), BUNDLE_METADATA_FILE_NAME);
+        return Err(Error::InvalidPgPackage);
+    }
+    let mut json = Vec::new();
+    first
+        .read_to_end(&mut json)
+        .map_err(|e| Error::ReadFileError(e.to_string()))?;
+    let metadata: BundleMetadata =
+        serde_json::from_slice(&json).map_err(|_| Error::InvalidPgPackage)?;
+    if metadata.format != BUNDLE_FORMAT {
+        log::error!(
+            "{} has unsupported bundle format {}",
+            src.display(),
+            metadata.format
+        );
+        return Err(Error::InvalidPgPackage);
+    }
+
+    let prefix = PathBuf::from(&metadata.path);
+    let strip = prefix.components().count();
+    let canonical_staging =
+        std::fs::canonicalize(staging).map_err(|e| Error::ReadFileError(e.to_string()))?;
+    let mut extracted = 0u64;
+    for entry in entries {
+        let mut entry = entry.map_err(|_| Error::InvalidPgPackage)?;
+        let path = entry.path().map_err(|_| Error::InvalidPgPackage)?.into_owned();
+        let Ok(relative) = path.strip_prefix(&prefix) else {
+            continue;
+        };
+        if relative.as_os_str().is_empty() || relative == Path::new(MANIFEST_FILE_NAME) {
+            continue;
+        }
+        if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            log::error!("{} contains unsafe path {}", src.display(), path.display());
+            return Err(Error::InvalidPgPackage);
+        }
+        extracted += 1;
+        extraction.check(&entry, staging, relative, extracted)?;
+        pg_unpack::unpack_stripped(&mut entry, staging, &canonical_staging, relative, strip)?;
+    }
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn fake_cache_dir(root: &Path, fetch_settings: &PgFetchSettings) -> PathBuf {
+        let cache_dir = root.join(format!("linux/amd64/{}", fetch_settings.version));
+        let staging = root.join("staging");
+        tokio::fs::create_dir_all(staging.join("bin")).await.unwrap();
+        tokio::fs::write(staging.join("bin/initdb"), b"#!/bin/sh\n").await.unwrap();
+        tokio::fs::create_dir_all(staging.join("share/postgresql")).await.unwrap();
+        tokio::fs::write(staging.join("share/postgresql/postgres.bki"), b"bki").await.unwrap();
+        tokio::fs::create_dir_all(cache_dir.parent().unwrap()).await.unwrap();
+        let manifest = CacheManifest {
+            version: fetch_settings.version.to_string(),
+            platform: fetch_settings.platform(),
+            source_url: "https://example.invalid/pg.jar".to_string(),
+            sha256: "00".repeat(32),
+            files: Vec::new(),
+        };
+        pg_cache::install(&staging, &cache_dir, manifest).await.unwrap();
+        cache_dir
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() -> Result<()> {
+        let fetch_settings = PgFetchSettings::default();
+        let source = tempfile::TempDir::new().unwrap();
+        let cache_dir = fake_cache_dir(source.path(), &fetch_settings).await;
+        let bundle = source.path().join("pg.tar");
+
+        let metadata = export_from(&cache_dir, &bundle).await?;
+        assert_eq!(metadata.path, format!("linux/amd64/{}", fetch_settings.version));
+
+        let target = tempfile::TempDir::new().unwrap();
+        let imported_dir = target.path().join("linux/amd64").join(fetch_settings.version.to_string());
+        let manifest = import_into(&imported_dir, &fetch_settings, &bundle).await?;
+        assert_eq!(manifest, metadata.manifest);
+        assert_eq!(
+            tokio::fs::read(imported_dir.join("share/postgresql/postgres.bki")).await.unwrap(),
+            b"bki"
+        );
+        Ok(())
+    }
+
+    /// Writes a zonky-style `.jar` holding `(name, mode, content)` files.
+    fn write_jar(jar: &Path, files: &[(&str, u32, &[u8])]) {
+        use std::io::Write;
+
+        let mut tar_data = Vec::new();
+        {
+            let mut builder = tar::Builder::new(&mut tar_data);
+            for (name, mode, content) in files {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(*mode);
+                header.set_cksum();
+                builder.append_data(&mut header, name, *content).unwrap();
+            }
+        }
+        let mut xz =
+            lzma_rust2::XzWriter::new(Vec::new(), lzma_rust2::XzOptions::default()).unwrap();
+        xz.write_all(&tar_data).unwrap();
+        let xz = xz.finish().unwrap();
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(jar).unwrap());
+        zip.start_file("postgres.txz", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&xz).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_import_jar_checks_pinned_checksum() -> Result<()> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let jar = dir.path().join("embedded-postgres-binaries.jar");
+        write_jar(&jar, &[("bin/initdb", 0o644, b"sh\n")]);
+
+        let pinned = PgFetchSettings {
+            pinned_checksum: Some(crate::pg_checksum::Checksum::sha256("00".repeat(32))),
+            ..Default::default()
+        };
+        let cache_dir = dir.path().join("linux/amd64").join(pinned.version.to_string());
+        assert!(matches!(
+            import_into(&cache_dir, &pinned, &jar).await,
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert!(!cache_dir.exists());
+
+        let manifest = import_into(&cache_dir, &PgFetchSettings::default(), &jar).await?;
+        assert!(manifest.files.contains(&"bin/initdb".to_string()));
+        assert!(manifest.source_url.starts_with("file://"));
+        assert!(cache_dir.join("bin/initdb").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_jar_checks_name() -> Result<()> {
+        let fetch_settings = PgFetchSettings::default();
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = dir.path().join("cache").join(fetch_settings.version.to_string());
+
+        let other = PgFetchSettings {
+            version: crate::pg_fetch::PG_V16,
+            ..Default::default()
+        };
+        for name in [
+            format!("embedded-postgres-binaries-{}-{}.jar", fetch_settings.platform(), other.version),
+            format!("embedded-postgres-binaries-other-arch-{}.jar", fetch_settings.version),
+        ] {
+            let jar = dir.path().join(name);
+            write_jar(&jar, &[("bin/initdb", 0o644, b"sh\n")]);
+            assert_eq!(
+                import_into(&cache_dir, &fetch_settings, &jar).await,
+                Err(Error::InvalidPgPackage)
+            );
+            assert!(!cache_dir.exists());
+        }
+
+        let jar = dir.path().join(format!(
+            "embedded-postgres-binaries-{}-{}.jar",
+            fetch_settings.platform(),
+            fetch_settings.version
+        ));
+        write_jar(&jar, &[("bin/initdb", 0o644, b"sh\n")]);
+        import_into(&cache_dir, &fetch_settings, &jar).await?;
+        assert!(cache_dir.join("bin/initdb").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_import_jar_checks_postgres_version() -> Result<()> {
+        let fetch_settings = PgFetchSettings::default();
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache_dir = dir.path().join("cache").join(fetch_settings.version.to_string());
+        let script = |version: &str| format!("#!/bin/sh\necho 'postgres (PostgreSQL) {}'\n", version);
+
+        let jar = dir.path().join("renamed.jar");
+        write_jar(&jar, &[("bin/postgres", 0o755, script("16.4").as_bytes())]);
+        assert_eq!(
+            import_into(&cache_dir, &fetch_settings, &jar).await,
+            Err(Error::InvalidPgPackage)
+        );
+        assert!(!cache_dir.exists());
+
+        let version = fetch_settings.version.to_string();
+        write_jar(&jar, &[("bin/postgres", 0o755, script(&version).as_bytes())]);
+        import_into(&cache_dir, &fetch_settings, &jar).await?;
+        assert!(cache_dir.join("bin/postgres").exists());
+        Ok(())
+    }
+
+    /// Writes a bundle for `fetch_settings` whose version directory holds
+    /// `entries`: `(name, symlink target)` for links, `(name, "")` for files.
+    #[cfg(unix)]
+    fn raw_bundle(dest: &Path, fetch_settings: &PgFetchSettings, entries: &[(&str, &str)]) {
+        let path = format!("linux/amd64/{}", fetch_settings.version);
+        let metadata = BundleMetadata {
+            format: BUNDLE_FORMAT,
+            path: path.clone(),
+            manifest: CacheManifest {
+                version: fetch_settings.version.to_string(),
+                platform: fetch_settings.platform(),
+                source_url: "https://example.invalid/pg.jar".to_string(),
+                sha256: "00".repeat(32),
+                files: Vec::new(),
+            },
+        };
+        let json = serde_json::to_vec(&metadata).unwrap();
+        let mut builder = tar::Builder::new(std::fs::File::create(dest).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, BUNDLE_METADATA_FILE_NAME, json.as_slice())
+            .unwrap();
+        for (name, link) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            let name = format!("{}/{}", path, name);
+            if link.is_empty() {
+                header.set_size(1);
+                header.set_cksum();
+                builder.append_data(&mut header, name, &b"x"[..]).unwrap();
+            } else {
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                builder.append_link(&mut header, name, link).unwrap();
+            }
+        }
+        builder.finish().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_import_rejects_symlink_escapes() {
+        let fetch_settings = PgFetchSettings::default();
+        let source = tempfile::TempDir::new().unwrap();
+        let target = tempfile::TempDir::new().unwrap();
+        let cache_dir = target.path().join("linux/amd64").join(fetch_settings.version.to_string());
+
+        // Each link looks harmless on its own; together `a3` is the root of
+        // `target`, two levels above the staging directory.
+        let chain = source.path().join("chain.tar");
+        raw_bundle(
+            &chain,
+            &fetch_settings,
+            &[
+                ("b", "."),
+                ("a1", "b/.."),
+                ("a2", "a1/.."),
+                ("a3", "a2/.."),
+                ("a3/pwned", ""),
+            ],
+        );
+        assert!(import_into(&cache_dir, &fetch_settings, &chain).await.is_err());
+        assert!(!target.path().join("pwned").exists());
+        assert!(!cache_dir.exists());
+
+        // Without the extraction checks the link itself is accepted, but
+        // nothing may be created or written through it.
+        let outside = tempfile::TempDir::new().unwrap();
+        let absolute = source.path().join("absolute.tar");
+        raw_bundle(
+            &absolute,
+            &fetch_settings,
+            &[
+                ("escape", outside.path().to_str().unwrap()),
+                ("escape/sub/pwned", ""),
+                ("escape/pwned", ""),
+            ],
+        );
+        let relaxed = PgFetchSettings {
+            extraction: ExtractionPolicy {
+                hardened: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(import_into(&cache_dir, &relaxed, &absolute).await.is_err());
+        assert!(!outside.path().join("sub").exists());
+        assert!(!outside.path().join("pwned").exists());
+        assert!(!cache_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_other_version_and_garbage() -> Result<()> {
+        let fetch_settings = PgFetchSettings::default();
+        let source = tempfile::TempDir::new().unwrap();
+        let cache_dir = fake_cache_dir(source.path(), &fetch_settings).await;
+        let bundle = source.path().join("pg.tar");
+        export_from(&cache_dir, &bundle).await?;
+
+        let target = tempfile::TempDir::new().unwrap();
+        let other = PgFetchSettings {
+            version: crate::pg_fetch::PG_V16,
+            ..Default::default()
+        };
+        let other_dir = target.path().join("linux/amd64").join(other.version.to_string());
+        assert_eq!(
+            import_into(&other_dir, &other, &bundle).await,
+            Err(Error::InvalidPgPackage)
+        );
+        assert!(!other_dir.exists());
+
+        let garbage = source.path().join("garbage.bin");
+        tokio::fs::write(&garbage, b"not a bundle").await.unwrap();
+        assert_eq!(
+            import_into(&other_dir, &other, &garbage).await,
+            Err(Error::InvalidPgPackage)
+        );
+        Ok(())
+    }
+}
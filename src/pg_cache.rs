@@ -0,0 +1,996 @@
+//! Crash-safe installation of PostgreSQL binaries into the cache.
+//!
+//! Binaries are never unpacked directly into their final cache directory.
+//! Instead [`PgAccess`](crate::pg_access::PgAccess) downloads and unpacks into
+//! a staging directory next to it (see [`staging_dir`]), records a
+//! [`CacheManifest`] once extraction has finished, and then [`install`]s the
+//! staging directory with a single atomic rename.  Everything in the staging
+//! directory is flushed to disk before the rename, so a power loss cannot
+//! leave a complete-looking version directory with truncated files.
+//!
+//! A version directory without a manifest is therefore the remnant of an
+//! interrupted acquisition and is treated as corrupt.
+//!
+//! Acquisitions of the same version by different processes (e.g. one per
+//! test under cargo-nextest) are serialised with a [`CacheLock`], an advisory
+//! file lock next to the version directory.
+//!
+//! [`inventory`] lists the installed versions, and [`remove_entries`] and
+//! [`gc`] delete them selectively.  A version directory used by a set-up
+//! [`PgEmbed`](crate::postgres::PgEmbed) is protected by a shared
+//! [`UsageLock`] and is never removed.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pg_errors::{Error, Result};
+use crate::pg_fetch::PostgresVersion;
+
+/// Name of the completion marker written into every fully installed cache directory.
+pub const MANIFEST_FILE_NAME: &str = ".pg-embed-manifest.json";
+
+/// Distinguishes staging directories created by the same process.
+static STAGING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// How often a blocked [`CacheLock::acquire`] retries the lock.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Completion marker describing a fully installed set of binaries.
+///
+/// Serialised as JSON to [`MANIFEST_FILE_NAME`] inside the cache directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheManifest {
+    /// PostgreSQL version of the installed binaries, e.g. `17.8.0`.
+    pub version: String,
+    /// Maven platform classifier, e.g. `linux-amd64`.
+    pub platform: String,
+    /// URL the bundle was acquired from.
+    pub source_url: String,
+    /// Hex-encoded SHA-256 digest of the bundle.
+    pub sha256: String,
+    /// Every installed file, relative to the cache directory, `/`-separated
+    /// and sorted.  The manifest itself is not listed.
+    pub files: Vec<String>,
+}
+
+impl CacheManifest {
+    /// Reads the manifest from `cache_dir`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if `cache_dir` has no manifest, i.e. it is not a completed
+    /// installation.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReadFileError`] if the manifest exists but cannot be
+    /// read or parsed.
+    pub async fn read(cache_dir: &Path) -> Result<Option<Self>> {
+        let path = cache_dir.join(MANIFEST_FILE_NAME);
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::ReadFileError(e.to_string())),
+        };
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|e| Error::ReadFileError(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Writes the manifest into `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::WriteFileError`] if serialisation or the write fails.
+    async fn write(&self, dir: &Path) -> Result<()> {
+        let content =
+            serde_json::to_vec_pretty(self).map_err(|e| Error::WriteFileError(e.to_string()))?;
+        tokio::fs::write(dir.join(MANIFEST_FILE_NAME), content)
+            .await
+            .map_err(|e| Error::WriteFileError(e.to_string()))
+    }
+}
+
+/// Cross-process advisory lock on one version cache directory.
+///
+/// Backed by `flock` (`LockFileEx` on Windows) on a `{version}.lock` file
+/// next to the version directory.  The lock file cannot live inside the
+/// version directory itself because that directory is replaced wholesale by
+/// [`install`].  The lock is released when the guard is dropped or the
+/// owning process exits.
+pub(crate) struct CacheLock {
+    file: std::fs::File,
+}
+
+impl CacheLock {
+    /// Returns the lock file path guarding `cache_dir`.
+    pub(crate) fn lock_path(cache_dir: &Path) -> PathBuf {
+        let name = cache_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        cache_dir.with_file_name(format!("{}.lock", name))
+    }
+
+    /// Acquires the exclusive lock for `cache_dir`, waiting for other
+    /// processes to release it.
+    ///
+    /// Logs once at `info` level if the lock is held elsewhere, since that
+    /// means another process is currently downloading the same binaries.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache_dir` — The version cache directory to guard.
+    /// * `timeout` — Maximum time to wait.  `None` waits indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::WriteFileError`] if the lock file cannot be opened.
+    /// Returns [`Error::PgLockError`] if locking fails for a reason other than
+    /// contention.
+    /// Returns [`Error::CacheLockTimeout`] if `timeout` elapses first.
+    pub(crate) async fn acquire(cache_dir: &Path, timeout: Option<Duration>) -> Result<Self> {
+        let lock_path = Self::lock_path(cache_dir);
+        let file = open_lock_file(&lock_path)?;
+
+        let started = Instant::now();
+        let mut announced = false;
+        loop {
+            if try_lock(&file, &lock_path, false)? {
+                return Ok(CacheLock { file });
+            }
+            if !announced {
+                log::info!(
+                    "Waiting for another process to finish acquiring PostgreSQL binaries into {} \
+                     (lock file {})",
+                    cache_dir.display(),
+                    lock_path.display()
+                );
+                announced = true;
+            }
+            if let Some(timeout) = timeout
+                && started.elapsed() >= timeout
+            {
+                return Err(Error::CacheLockTimeout(format!(
+                    "{} still locked by another process after {:?}",
+                    lock_path.display(),
+                    timeout
+                )));
+            }
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Takes the lock for `cache_dir` if no other holder has it.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the lock is held elsewhere, i.e. the version is being
+    /// acquired right now.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Self::acquire`], except that there is no timeout.
+    pub(crate) fn try_acquire(cache_dir: &Path) -> Result<Option<Self>> {
+        let lock_path = Self::lock_path(cache_dir);
+        let file = open_lock_file(&lock_path)?;
+        Ok(try_lock(&file, &lock_path, false)?.then_some(CacheLock { file }))
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs4::FileExt::unlock(&self.file) {
+            log::warn!("Could not release binary cache lock: {e}");
+        }
+    }
+}
+
+/// Cross-process advisory lock marking a version cache directory as in use.
+///
+/// Every [`PgEmbed`](crate::postgres::PgEmbed) set up from the cache holds a
+/// shared lock on a `{version}.in-use` file next to the version directory
+/// until it is dropped.  Removal takes the lock exclusively, so it skips
+/// versions in use and a cluster being set up waits until a removal has
+/// finished (and then acquires the binaries again).  A separate file from
+/// the [`CacheLock`] keeps running clusters from blocking acquisitions.
+pub(crate) struct UsageLock {
+    file: std::fs::File,
+}
+
+impl UsageLock {
+    /// Returns the lock file path marking `cache_dir` as in use.
+    pub(crate) fn lock_path(cache_dir: &Path) -> PathBuf {
+        let name = cache_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        cache_dir.with_file_name(format!("{}.in-use", name))
+    }
+
+    /// Marks `cache_dir` as in use, waiting for a running removal to finish.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache_dir` — The version cache directory to protect.
+    /// * `timeout` — Maximum time to wait.  `None` waits indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::WriteFileError`] if the lock file cannot be opened.
+    /// Returns [`Error::PgLockError`] if locking fails for a reason other than
+    /// contention.
+    /// Returns [`Error::CacheLockTimeout`] if `timeout` elapses first.
+    pub(crate) async fn shared(cache_dir: &Path, timeout: Option<Duration>) -> Result<Self> {
+        let lock_path = Self::lock_path(cache_dir);
+        let file = open_lock_file(&lock_path)?;
+        let started = Instant::now();
+        loop {
+            if try_lock(&file, &lock_path, true)? {
+                return Ok(UsageLock { file });
+            }
+            if let Some(timeout) = timeout
+                && started.elapsed() >= timeout
+            {
+                return Err(Error::CacheLockTimeout(format!(
+                    "{} still being removed after {:?}",
+                    cache_dir.display(),
+                    timeout
+                )));
+            }
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Takes the lock exclusively if `cache_dir` is not in use.
+    ///
+    /// # Returns
+    ///
+    /// `None` if a cluster is using `cache_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Self::shared`], except that there is no timeout.
+    pub(crate) fn try_exclusive(cache_dir: &Path) -> Result<Option<Self>> {
+        let lock_path = Self::lock_path(cache_dir);
+        let file = open_lock_file(&lock_path)?;
+        Ok(try_lock(&file, &lock_path, false)?.then_some(UsageLock { file }))
+    }
+
+    /// Returns whether a cluster is using `cache_dir`, without creating or
+    /// writing anything, so that a read-only cache can be listed.
+    ///
+    /// A missing lock file means the version was never used.  Otherwise the
+    /// file is opened read-only and probed with an exclusive lock that is
+    /// released at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReadFileError`] if the lock file exists but cannot be
+    /// opened.
+    /// Returns [`Error::PgLockError`] if locking fails for a reason other than
+    /// contention.
+    pub(crate) fn is_in_use(cache_dir: &Path) -> Result<bool> {
+        let lock_path = Self::lock_path(cache_dir);
+        let file = match std::fs::File::open(&lock_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(Error::ReadFileError(e.to_string())),
+        };
+        if !try_lock(&file, &lock_path, false)? {
+            return Ok(true);
+        }
+        if let Err(e) = fs4::FileExt::unlock(&file) {
+            log::warn!("Could not release binary cache usage lock: {e}");
+        }
+        Ok(false)
+    }
+}
+
+impl Drop for UsageLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs4::FileExt::unlock(&self.file) {
+            log::warn!("Could not release binary cache usage lock: {e}");
+        }
+    }
+}
+
+/// Opens (creating if needed) the lock file at `path`.
+///
+/// # Errors
+///
+/// Returns [`Error::WriteFileError`] if the file cannot be opened.
+fn open_lock_file(path: &Path) -> Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(|e| Error::WriteFileError(e.to_string()))
+}
+
+/// Tries to lock `file` without waiting.
+///
+/// # Returns
+///
+/// `false` if the lock is held elsewhere.
+///
+/// # Errors
+///
+/// Returns [`Error::PgLockError`] if locking fails for a reason other than
+/// contention.
+fn try_lock(file: &std::fs::File, path: &Path, shared: bool) -> Result<bool> {
+    let result = if shared {
+        fs4::FileExt::try_lock_shared(file)
+    } else {
+        fs4::FileExt::try_lock(file)
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(fs4::TryLockError::WouldBlock) => Ok(false),
+        Err(fs4::TryLockError::Error(e)) => {
+            log::error!("Could not lock {}: {}", path.display(), e);
+            Err(Error::PgLockError)
+        }
+    }
+}
+
+/// Returns a fresh staging directory path next to `cache_dir`.
+///
+/// The name is hidden and unique per process and call, e.g.
+/// `{arch}/.17.8.0.staging-4711-0` for `{arch}/17.8.0`, so that it lives on
+/// the same file system as `cache_dir` and can be renamed onto it atomically.
+pub(crate) fn staging_dir(cache_dir: &Path) -> PathBuf {
+    let name = cache_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let counter = STAGING_COUNTER.fetch_add(1, Ordering::Relaxed);
+    cache_dir.with_file_name(format!(".{}.staging-{}-{}", name, std::process::id(), counter))
+}
+
+/// Removes staging directories for `cache_dir` left behind by crashed processes.
+///
+/// Must only be called while holding the [`CacheLock`] for `cache_dir`, since
+/// it cannot tell a stale staging directory from one in active use.
+///
+/// # Errors
+///
+/// Returns [`Error::ReadFileError`] if the parent directory cannot be read.
+/// Returns [`Error::PgPurgeFailure`] if a staging directory cannot be removed.
+pub(crate) async fn remove_stale_staging_dirs(cache_dir: &Path) -> Result<()> {
+    let (Some(parent), Some(name)) = (cache_dir.parent(), cache_dir.file_name()) else {
+        return Ok(());
+    };
+    let prefix = format!(".{}.staging-", name.to_string_lossy());
+    let mut entries = match tokio::fs::read_dir(parent).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(Error::ReadFileError(e.to_string())),
+    };
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| Error::ReadFileError(e.to_string()))?
+    {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            log::debug!("Removing stale staging directory {}", entry.path().display());
+            remove_dir_if_exists(&entry.path()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists every file below `root`, relative to it.
+///
+/// Paths use `/` as separator regardless of platform and are sorted.
+/// Directories themselves are not listed; symbolic links are listed but not
+/// followed.
+///
+/// # Errors
+///
+/// Returns [`Error::ReadFileError`] if a directory cannot be read.
+pub(crate) async fn list_files(root: &Path) -> Result<Vec<String>> {
+    let root = root.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut files = Vec::new();
+        let mut pending = vec![root.clone()];
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(&dir).map_err(|e| Error::ReadFileError(e.to_string()))? {
+                let entry = entry.map_err(|e| Error::ReadFileError(e.to_string()))?;
+                let file_type = entry
+                    .file_type()
+                    .map_err(|e| Error::ReadFileError(e.to_string()))?;
+                let path = entry.path();
+                if file_type.is_dir() {
+                    pending.push(path);
+                } else if let Ok(relative) = path.strip_prefix(&root) {
+                    let components: Vec<_> = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                        .collect();
+                    files.push(components.join("/"));
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    })
+    .await
+    .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))?
+}
+
+/// Completes an acquisition by moving `staging` into place as `cache_dir`.
+///
+/// Fills in [`CacheManifest::files`] from the contents of `staging`, writes
+/// the manifest as the last file, flushes `staging` to disk, and renames it
+/// to `cache_dir`; the rename itself is flushed through the parent
+/// directory.  A leftover `cache_dir` without a manifest is removed first.  If another
+/// acquisition completed `cache_dir` in the meantime, `staging` is discarded
+/// and the existing installation is kept.
+///
+/// # Errors
+///
+/// Returns [`Error::ReadFileError`] if `staging` cannot be listed.
+/// Returns [`Error::WriteFileError`] if the manifest cannot be written,
+/// flushing fails, or the rename fails.
+/// Returns [`Error::PgPurgeFailure`] if a corrupt `cache_dir` cannot be removed.
+pub(crate) async fn install(
+    staging: &Path,
+    cache_dir: &Path,
+    mut manifest: CacheManifest,
+) -> Result<()> {
+    manifest.files = list_files(staging).await?;
+    manifest.write(staging).await?;
+    let staging_owned = staging.to_path_buf();
+    tokio::task::spawn_blocking(move || sync_tree(&staging_owned))
+        .await
+        .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))?
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+
+    if tokio::fs::try_exists(cache_dir)
+        .await
+        .map_err(|e| Error::ReadFileError(e.to_string()))?
+    {
+        if CacheManifest::read(cache_dir).await?.is_some() {
+            log::debug!(
+                "{} was installed concurrently; discarding {}",
+                cache_dir.display(),
+                staging.display()
+            );
+            remove_dir_if_exists(staging).await?;
+            return Ok(());
+        }
+        remove_dir_if_exists(cache_dir).await?;
+    }
+
+    tokio::fs::rename(staging, cache_dir)
+        .await
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    if let Some(parent) = cache_dir.parent() {
+        let parent = parent.to_path_buf();
+        tokio::task::spawn_blocking(move || sync_dir(&parent))
+            .await
+            .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))?
+            .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Flushes every regular file below `dir`, and the directories themselves,
+/// to disk.  Symlinks are stored in their directory and flushed with it.
+fn sync_tree(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            sync_tree(&entry.path())?;
+        } else if file_type.is_file() {
+            sync_file(&entry.path())?;
+        }
+    }
+    sync_dir(dir)
+}
+
+/// Flushes the contents of the file at `path` to disk.
+fn sync_file(path: &Path) -> std::io::Result<()> {
+    // Windows only flushes handles opened for writing.
+    #[cfg(windows)]
+    let file = std::fs::File::options().write(true).open(path)?;
+    #[cfg(not(windows))]
+    let file = std::fs::File::open(path)?;
+    file.sync_all()
+}
+
+/// Flushes the entries of directory `dir` to disk.  Does nothing on Windows,
+/// where directories cannot be opened as files.
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    std::fs::File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// Records that the binaries in `cache_dir` were just used.
+///
+/// Sets the modification time of the [`CacheManifest`], which
+/// [`CacheEntry::last_used`] reports.  Failures (e.g. a read-only cache) are
+/// only logged.
+pub(crate) fn mark_used(cache_dir: &Path) {
+    let path = cache_dir.join(MANIFEST_FILE_NAME);
+    let result = std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(e) = result {
+        log::debug!("Could not update last use of {}: {}", path.display(), e);
+    }
+}
+
+/// One version directory in the binary cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    /// The version directory, `{cache_root}/{os}/{arch}/{version}[-{tag}]`.
+    pub path: PathBuf,
+    /// Operating system directory, e.g. `linux` or `darwin`.
+    pub os: String,
+    /// Architecture directory, e.g. `amd64`.
+    pub arch: String,
+    /// PostgreSQL version of the binaries.
+    pub version: PostgresVersion,
+    /// Source tag after the version, if any; see
+    /// [`CacheKey`](crate::pg_fetch::CacheKey).
+    pub tag: Option<String>,
+    /// Total size of the files in bytes.
+    pub size: u64,
+    /// When a cluster last used the binaries, or when they were installed.
+    /// For an incomplete entry, the modification time of the directory.
+    pub last_used: SystemTime,
+    /// `true` if the entry has a [`CacheManifest`].  Incomplete entries are
+    /// remnants of interrupted acquisitions or caches of earlier releases,
+    /// and are replaced on their next use.
+    pub complete: bool,
+    /// `true` if a [`PgEmbed`](crate::postgres::PgEmbed) is using the
+    /// binaries, at the time of listing.
+    pub in_use: bool,
+}
+
+/// Lists every version directory below the cache `root`.
+///
+/// The result is sorted by OS, architecture, version and tag.  Directories
+/// that do not follow the `{os}/{arch}/{version}[-{tag}]` layout, staging
+/// directories and lock files are ignored; a missing `root` is an empty cache.
+///
+/// # Errors
+///
+/// Returns [`Error::ReadFileError`] if a directory cannot be read.
+/// Returns [`Error::WriteFileError`] or [`Error::PgLockError`] if a lock file
+/// cannot be checked.
+pub async fn inventory(root: &Path) -> Result<Vec<CacheEntry>> {
+    let root = root.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut entries = Vec::new();
+        for (os, os_dir) in sub_dirs(&root)? {
+            for (arch, arch_dir) in sub_dirs(&os_dir)? {
+                for (name, path) in sub_dirs(&arch_dir)? {
+                    if let Some(entry) = read_entry(&os, &arch, &name, path)? {
+                        entries.push(entry);
+                    }
+                }
+            }
+        }
+        entries.sort_by(|a, b| {
+            (&a.os, &a.arch, &a.version, &a.tag).cmp(&(&b.os, &b.arch, &b.version, &b.tag))
+        });
+        Ok(entries)
+    })
+    .await
+    .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))?
+}
+
+/// Returns the names and paths of the non-hidden directories in `dir`.
+fn sub_dirs(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::ReadFileError(e.to_string())),
+    };
+    let mut dirs = Vec::new();
+    for entry in read_dir {
+        let entry = entry.map_err(|e| Error::ReadFileError(e.to_string()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_dir = entry
+            .file_type()
+            .map_err(|e| Error::ReadFileError(e.to_string()))?
+            .is_dir();
+        if is_dir && !name.starts_with('.') {
+            dirs.push((name, entry.path()));
+        }
+    }
+    Ok(dirs)
+}
+
+/// Describes the version directory `path` named `name`, or returns `None`
+/// if the name does not start with a version.
+fn read_entry(os: &str, arch: &str, name: &str, path: PathBuf) -> Result<Option<CacheEntry>> {
+    let (version, tag) = match name.split_once('-') {
+        Some((version, tag)) => (version, Some(tag.to_string())),
+        None => (name, None),
+    };
+    let Ok(version) = version.parse::<PostgresVersion>() else {
+        return Ok(None);
+    };
+    let read_error = |e: std::io::Error| Error::ReadFileError(format!("{}: {}", path.display(), e));
+    let manifest = path.join(MANIFEST_FILE_NAME);
+    let complete = manifest.is_file();
+    let marker = if complete { &manifest } else { &path };
+    let last_used = std::fs::metadata(marker)
+        .and_then(|m| m.modified())
+        .map_err(read_error)?;
+    let mut size = 0;
+    let mut pending = vec![path.clone()];
+    while let Some(dir) = pending.pop() {
+        for child in std::fs::read_dir(&dir).map_err(read_error)? {
+            let child = child.map_err(read_error)?;
+            let metadata = child.metadata().map_err(read_error)?;
+            if metadata.is_dir() {
+                pending.push(child.path());
+            } else {
+                size += metadata.len();
+            }
+        }
+    }
+    let in_use = UsageLock::is_in_use(&path)?;
+    Ok(Some(CacheEntry {
+        os: os.to_string(),
+        arch: arch.to_string(),
+        version,
+        tag,
+        size,
+        last_used,
+        complete,
+        in_use,
+        path,
+    }))
+}
+
+/// Removes the cache entries below `root` for which `predicate` returns
+/// `true`, e.g. `|e| e.version.major() == 16` or `|e| e.os == "darwin"`.
+///
+/// Entries in use by a cluster, or being acquired right now, are kept.
+/// Lock files next to removed entries are left in place, since other
+/// processes may be waiting on them.
+///
+/// # Returns
+///
+/// The entries that were removed.
+///
+/// # Errors
+///
+/// Returns the errors of [`inventory`].
+/// Returns [`Error::PgPurgeFailure`] if a directory cannot be removed.
+pub async fn remove_entries(
+    root: &Path,
+    predicate: impl Fn(&CacheEntry) -> bool,
+) -> Result<Vec<CacheEntry>> {
+    let mut removed = Vec::new();
+    for entry in inventory(root).await? {
+        if predicate(&entry) && remove_entry(&entry).await? {
+            removed.push(entry);
+        }
+    }
+    Ok(removed)
+}
+
+/// Frees space in the cache below `root`.
+///
+/// Removes, in this order:
+///
+/// 1. incomplete entries,
+/// 2. entries not used for longer than `max_age`,
+/// 3. the least recently used entries until the cache holds at most
+///    `max_total_size` bytes.
+///
+/// Entries in use by a cluster, or being acquired right now, are never
+/// removed but still count towards `max_total_size`, so the cache may stay
+/// above the limit.  `None` disables the respective limit.
+///
+/// # Returns
+///
+/// The entries that were removed.
+///
+/// # Errors
+///
+/// Same as [`remove_entries`].
+pub async fn gc(
+    root: &Path,
+    max_total_size: Option<u64>,
+    max_age: Option<Duration>,
+) -> Result<Vec<CacheEntry>> {
+    let now = SystemTime::now();
+    let mut entries = inventory(root).await?;
+    // Least recently used first.
+    entries.sort_by_key(|e| e.last_used);
+    let mut total: u64 = entries.iter().map(|e| e.size).sum();
+    let mut removed = Vec::new();
+    for entry in entries {
+        let expired = max_age.is_some_and(|max_age| {
+            now.duration_since(entry.last_used)
+                .is_ok_and(|age| age > max_age)
+        });
+        let over_size = max_total_size.is_some_and(|max| total > max);
+        if (!entry.complete || expired || over_size) && remove_entry(&entry).await? {
+            total -= entry.size;
+            removed.push(entry);
+        }
+    }
+    Ok(removed)
+}
+
+/// Removes the directory of `entry` unless it is in use or being acquired.
+///
+/// # Returns
+///
+/// `true` if the entry was removed.
+async fn remove_entry(entry: &CacheEntry) -> Result<bool> {
+    let Some(_usage) = UsageLock::try_exclusive(&entry.path)? else {
+        log::debug!("Keeping {}: in use", entry.path.display());
+        return Ok(false);
+    };
+    let Some(_lock) = CacheLock::try_acquire(&entry.path)? else {
+        log::debug!("Keeping {}: being acquired", entry.path.display());
+        return Ok(false);
+    };
+    log::info!("Removing cached binaries {}", entry.path.display());
+    remove_dir_if_exists(&entry.path).await?;
+    Ok(true)
+}
+
+/// Removes `dir` recursively, treating a missing directory as success.
+///
+/// # Errors
+///
+/// Returns [`Error::PgPurgeFailure`] if the removal fails.
+pub(crate) async fn remove_dir_if_exists(dir: &Path) -> Result<()> {
+    match tokio::fs::remove_dir_all(dir).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::PgPurgeFailure(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> CacheManifest {
+        CacheManifest {
+            version: "17.8.0".to_string(),
+            platform: "linux-amd64".to_string(),
+            source_url: "https://example.invalid/pg.jar".to_string(),
+            sha256: "00".repeat(32),
+            files: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_replaces_corrupt_cache_dir() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let cache_dir = root.path().join("17.8.0");
+
+        // Leftover from an interrupted acquisition: no manifest.
+        std::fs::create_dir_all(cache_dir.join("bin")).unwrap();
+        std::fs::write(cache_dir.join("bin/half-written"), b"").unwrap();
+        assert_eq!(CacheManifest::read(&cache_dir).await?, None);
+
+        let staging = staging_dir(&cache_dir);
+        assert_eq!(staging.parent(), cache_dir.parent());
+        std::fs::create_dir_all(staging.join("bin")).unwrap();
+        std::fs::write(staging.join("bin/initdb"), b"#!").unwrap();
+        std::fs::write(staging.join("LICENSE"), b"").unwrap();
+
+        install(&staging, &cache_dir, manifest()).await?;
+
+        assert!(!staging.exists());
+        assert!(!cache_dir.join("bin/half-written").exists());
+        let installed = CacheManifest::read(&cache_dir).await?.expect("manifest missing");
+        assert_eq!(installed.files, vec!["LICENSE".to_string(), "bin/initdb".to_string()]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_lock_excludes_second_holder() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let cache_dir = root.path().join("17.8.0");
+
+        let first = CacheLock::acquire(&cache_dir, Some(Duration::ZERO)).await?;
+        assert!(CacheLock::lock_path(&cache_dir).exists());
+        let contended = CacheLock::acquire(&cache_dir, Some(Duration::from_millis(200))).await;
+        assert!(matches!(contended, Err(Error::CacheLockTimeout(_))));
+
+        drop(first);
+        CacheLock::acquire(&cache_dir, Some(Duration::ZERO)).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_stale_staging_dirs() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let cache_dir = root.path().join("17.8.0");
+        let stale = staging_dir(&cache_dir);
+        let other_version = staging_dir(&root.path().join("16.12.0"));
+        std::fs::create_dir_all(&stale).unwrap();
+        std::fs::create_dir_all(&other_version).unwrap();
+
+        remove_stale_staging_dirs(&cache_dir).await?;
+
+        assert!(!stale.exists());
+        assert!(other_version.exists());
+        Ok(())
+    }
+
+    /// Creates a complete cache entry `root/{rel}` holding `size` bytes and
+    /// last used `age` ago.
+    fn cached(root: &Path, rel: &str, size: usize, age: Duration) -> PathBuf {
+        let dir = root.join(rel);
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        std::fs::write(dir.join("bin/postgres"), vec![0u8; size]).unwrap();
+        std::fs::write(dir.join(MANIFEST_FILE_NAME), b"{}").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join(MANIFEST_FILE_NAME))
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        dir
+    }
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[tokio::test]
+    async fn test_inventory() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        assert!(inventory(&root.path().join("missing")).await?.is_empty());
+
+        let v17 = cached(root.path(), "linux/amd64/17.8.0", 100, HOUR);
+        cached(root.path(), "linux/amd64/16.4.0-src-91ad03c4e5f2", 10, HOUR);
+        cached(root.path(), "darwin/arm64v8/17.8.0", 1, HOUR);
+        let incomplete = root.path().join("linux/amd64/15.1.0");
+        std::fs::create_dir_all(&incomplete).unwrap();
+        std::fs::create_dir_all(staging_dir(&v17)).unwrap();
+        std::fs::create_dir_all(root.path().join("metadata")).unwrap();
+        std::fs::write(root.path().join("metadata/linux-amd64.json"), b"{}").unwrap();
+        let _in_use = UsageLock::shared(&v17, Some(Duration::ZERO)).await?;
+
+        let entries = inventory(root.path()).await?;
+        let names: Vec<_> = entries
+            .iter()
+            .map(|e| format!("{}/{}/{}/{:?}", e.os, e.arch, e.version, e.tag))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "darwin/arm64v8/17.8.0/None",
+                "linux/amd64/15.1.0/None",
+                "linux/amd64/16.4.0/Some(\"src-91ad03c4e5f2\")",
+                "linux/amd64/17.8.0/None",
+            ]
+        );
+        let [_, old, _, current] = &entries[..] else { unreachable!() };
+        assert!(!old.complete && old.size == 0);
+        assert!(current.complete && current.in_use);
+        assert_eq!(current.size, 102);
+        assert!(current.last_used < SystemTime::now() - HOUR / 2);
+
+        mark_used(&v17);
+        let entries = inventory(root.path()).await?;
+        assert!(entries[3].last_used > SystemTime::now() - HOUR / 2);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_inventory_of_read_only_cache() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::TempDir::new().unwrap();
+        let v17 = cached(root.path(), "linux/amd64/17.8.0", 1, HOUR);
+        let v16 = cached(root.path(), "linux/amd64/16.4.0", 1, HOUR);
+        let _in_use = UsageLock::shared(&v16, Some(Duration::ZERO)).await?;
+        let arch_dir = v17.parent().unwrap().to_path_buf();
+        let set_mode = |mode| {
+            std::fs::set_permissions(&arch_dir, std::fs::Permissions::from_mode(mode)).unwrap()
+        };
+
+        set_mode(0o555);
+        let entries = inventory(root.path()).await;
+        set_mode(0o755);
+        let entries = entries?;
+        assert_eq!(entries.len(), 2);
+        let in_use = |dir: &Path| entries.iter().find(|e| e.path == dir).unwrap().in_use;
+        assert!(!in_use(&v17) && in_use(&v16));
+        assert!(!UsageLock::lock_path(&v17).exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_entries_keeps_entries_in_use() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let v16 = cached(root.path(), "linux/amd64/16.4.0", 1, HOUR);
+        let v16_darwin = cached(root.path(), "darwin/amd64/16.4.0", 1, HOUR);
+        let v17 = cached(root.path(), "linux/amd64/17.8.0", 1, HOUR);
+        let in_use = UsageLock::shared(&v16_darwin, Some(Duration::ZERO)).await?;
+
+        let removed = remove_entries(root.path(), |e| e.version.major() == 16).await?;
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].path, v16);
+        assert!(!v16.exists() && v16_darwin.exists() && v17.exists());
+
+        // A removal in progress makes setup wait instead of using the
+        // binaries while they are deleted.
+        drop(in_use);
+        let removing = UsageLock::try_exclusive(&v16_darwin)?.expect("not in use");
+        let waited = UsageLock::shared(&v16_darwin, Some(Duration::from_millis(200))).await;
+        assert!(matches!(waited, Err(Error::CacheLockTimeout(_))));
+        drop(removing);
+
+        // Versions being acquired are kept as well.
+        let _acquiring = CacheLock::acquire(&v17, Some(Duration::ZERO)).await?;
+        assert_eq!(remove_entries(root.path(), |_| true).await?.len(), 1);
+        assert!(!v16_darwin.exists() && v17.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gc() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let stale = cached(root.path(), "linux/amd64/14.1.0", 10, 100 * HOUR);
+        let old = cached(root.path(), "linux/amd64/15.1.0", 1000, 3 * HOUR);
+        let in_use = cached(root.path(), "linux/amd64/16.4.0", 1000, 2 * HOUR);
+        let recent = cached(root.path(), "linux/amd64/17.8.0", 1000, HOUR);
+        let incomplete = root.path().join("linux/amd64/13.1.0");
+        std::fs::create_dir_all(&incomplete).unwrap();
+        let _in_use = UsageLock::shared(&in_use, Some(Duration::ZERO)).await?;
+
+        let removed = gc(root.path(), None, Some(50 * HOUR)).await?;
+        assert_eq!(removed.len(), 2);
+        assert!(!stale.exists() && !incomplete.exists() && old.exists());
+
+        // Three entries of 1002 bytes; the one in use counts but is kept, so
+        // the least recently used other entry goes.
+        let removed = gc(root.path(), Some(2500), None).await?;
+        assert_eq!(removed.len(), 1);
+        assert!(!old.exists() && in_use.exists() && recent.exists());
+
+        let removed = gc(root.path(), Some(0), None).await?;
+        assert_eq!(removed.len(), 1);
+        assert!(in_use.exists() && !recent.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_install_keeps_completed_cache_dir() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let cache_dir = root.path().join("17.8.0");
+
+        let first = staging_dir(&cache_dir);
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::write(first.join("first"), b"").unwrap();
+        install(&first, &cache_dir, manifest()).await?;
+
+        let second = staging_dir(&cache_dir);
+        assert_ne!(first, second);
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(second.join("second"), b"").unwrap();
+        install(&second, &cache_dir, manifest()).await?;
+
+        assert!(!second.exists());
+        assert!(cache_dir.join("first").exists());
+        assert!(!cache_dir.join("second").exists());
+        Ok(())
+    }
+}
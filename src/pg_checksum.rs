@@ -0,0 +1,247 @@
+//! Integrity verification for downloaded PostgreSQL binary bundles.
+//!
+//! Maven publishes `.sha256`, `.sha1` and `.md5` sidecar files next to every
+//! artifact.  [`BundleHasher`] computes all three digests in a single pass
+//! while the bundle is streamed to disk, and [`BundleDigests::verify`]
+//! compares the result against an expected [`Checksum`] — either a pinned one
+//! from [`crate::pg_fetch::PgFetchSettings::pinned_checksum`] or one read from
+//! a sidecar file.
+
+use std::fmt;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::pg_errors::{Error, Result};
+
+/// Digest algorithms understood by pg-embed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChecksumAlgorithm {
+    /// SHA-256
+    Sha256,
+    /// SHA-1
+    Sha1,
+    /// MD5
+    Md5,
+}
+
+impl ChecksumAlgorithm {
+    /// All supported algorithms, strongest first.
+    pub const ALL: [ChecksumAlgorithm; 3] =
+        [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Sha1, ChecksumAlgorithm::Md5];
+
+    /// File extension Maven uses for the sidecar file of this algorithm
+    /// (without the leading dot).
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha1 => "sha1",
+            ChecksumAlgorithm::Md5 => "md5",
+        }
+    }
+
+    /// Length of the hex-encoded digest.
+    #[cfg(feature = "download")]
+    fn hex_len(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Sha256 => 64,
+            ChecksumAlgorithm::Sha1 => 40,
+            ChecksumAlgorithm::Md5 => 32,
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// An expected digest of a binary bundle.
+///
+/// The hex string is normalised to lowercase on construction so comparisons
+/// are case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    /// Algorithm that produced [`Self::hex`].
+    pub algorithm: ChecksumAlgorithm,
+    /// Lowercase hex-encoded digest.
+    pub hex: String,
+}
+
+impl Checksum {
+    /// Creates a new checksum from a hex-encoded digest.
+    pub fn new(algorithm: ChecksumAlgorithm, hex: impl Into<String>) -> Self {
+        Checksum {
+            algorithm,
+            hex: hex.into().trim().to_ascii_lowercase(),
+        }
+    }
+
+    /// Shorthand for a SHA-256 checksum.
+    pub fn sha256(hex: impl Into<String>) -> Self {
+        Self::new(ChecksumAlgorithm::Sha256, hex)
+    }
+
+    /// Parses the body of a Maven sidecar file.
+    ///
+    /// Sidecars contain either the bare hex digest or the `sha1sum`-style
+    /// `{digest}  {file name}` form; only the first token is used.  Returns
+    /// `None` if the token is not a well-formed digest for `algorithm` (e.g.
+    /// an HTML error page served with status 200).
+    #[cfg(feature = "download")]
+    pub(crate) fn parse_sidecar(algorithm: ChecksumAlgorithm, body: &str) -> Option<Self> {
+        let token = body.split_whitespace().next()?;
+        if token.len() != algorithm.hex_len() || !token.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(Self::new(algorithm, token))
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.hex)
+    }
+}
+
+/// All digests of a downloaded bundle, hex-encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleDigests {
+    /// SHA-256 digest.
+    pub sha256: String,
+    /// SHA-1 digest.
+    pub sha1: String,
+    /// MD5 digest.
+    pub md5: String,
+}
+
+impl BundleDigests {
+    /// Computes the digests of an in-memory buffer.
+    pub fn of(data: &[u8]) -> Self {
+        let mut hasher = BundleHasher::new();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Returns the digest for `algorithm`.
+    pub fn get(&self, algorithm: ChecksumAlgorithm) -> &str {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => &self.sha256,
+            ChecksumAlgorithm::Sha1 => &self.sha1,
+            ChecksumAlgorithm::Md5 => &self.md5,
+        }
+    }
+
+    /// Compares these digests against `expected`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChecksumMismatch`] if the digest for
+    /// `expected.algorithm` differs from `expected.hex`.
+    pub fn verify(&self, expected: &Checksum) -> Result<()> {
+        let actual = self.get(expected.algorithm);
+        if actual == expected.hex {
+            Ok(())
+        } else {
+            Err(Error::ChecksumMismatch {
+                expected: expected.to_string(),
+                actual: Checksum::new(expected.algorithm, actual).to_string(),
+            })
+        }
+    }
+}
+
+/// Incrementally hashes a bundle with every [`ChecksumAlgorithm`] at once.
+pub(crate) struct BundleHasher {
+    sha256: Sha256,
+    sha1: Sha1,
+    md5: Md5,
+}
+
+impl BundleHasher {
+    pub(crate) fn new() -> Self {
+        BundleHasher {
+            sha256: Sha256::new(),
+            sha1: Sha1::new(),
+            md5: Md5::new(),
+        }
+    }
+
+    /// Feeds the next chunk of the bundle into all hashers.
+    pub(crate) fn update(&mut self, chunk: &[u8]) {
+        self.sha256.update(chunk);
+        self.sha1.update(chunk);
+        self.md5.update(chunk);
+    }
+
+    /// Consumes the hasher and returns the hex-encoded digests.
+    pub(crate) fn finalize(self) -> BundleDigests {
+        BundleDigests {
+            sha256: to_hex(&self.sha256.finalize()),
+            sha1: to_hex(&self.sha1.finalize()),
+            md5: to_hex(&self.md5.finalize()),
+        }
+    }
+}
+
+/// Lowercase hex encoding of `bytes`.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_digests_known_vectors() {
+        let digests = BundleDigests::of(b"abc");
+        assert_eq!(
+            digests.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(digests.sha1, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(digests.md5, "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn test_incremental_hashing_matches_one_shot() {
+        let mut hasher = BundleHasher::new();
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        assert_eq!(hasher.finalize(), BundleDigests::of(b"abc"));
+    }
+
+    #[test]
+    #[cfg(feature = "download")]
+    fn test_parse_sidecar() {
+        let sha1 = "A9993E364706816ABA3E25717850C26C9CD0D89D";
+        let parsed = Checksum::parse_sidecar(ChecksumAlgorithm::Sha1, sha1).unwrap();
+        assert_eq!(parsed.hex, sha1.to_ascii_lowercase());
+
+        let with_name = format!("{}  postgres.jar\n", "900150983cd24fb0d6963f7d28e17f72");
+        assert!(Checksum::parse_sidecar(ChecksumAlgorithm::Md5, &with_name).is_some());
+
+        assert!(Checksum::parse_sidecar(ChecksumAlgorithm::Md5, "<html>").is_none());
+        assert!(Checksum::parse_sidecar(ChecksumAlgorithm::Sha256, sha1).is_none());
+    }
+
+    #[test]
+    fn test_verify() {
+        let digests = BundleDigests::of(b"abc");
+        assert!(digests
+            .verify(&Checksum::new(ChecksumAlgorithm::Md5, "900150983CD24FB0D6963F7D28E17F72"))
+            .is_ok());
+
+        let wrong = Checksum::sha256("00".repeat(32));
+        assert_eq!(
+            digests.verify(&wrong),
+            Err(Error::ChecksumMismatch {
+                expected: format!("sha256:{}", "00".repeat(32)),
+                actual: format!("sha256:{}", digests.sha256),
+            })
+        );
+    }
+}
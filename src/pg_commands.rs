@@ -11,6 +11,8 @@ use crate::command_executor::{AsyncCommand, AsyncCommandExecutor};
 use crate::pg_enums::{PgAuthMethod, PgProcessType, PgServerStatus};
 use crate::pg_errors::Error;
 use crate::pg_errors::Result;
+use crate::pg_fetch::PostgresVersion;
+use crate::postgres::InitDbOptions;
 
 /// Factories for the three PostgreSQL lifecycle commands.
 pub struct PgCommand {}
@@ -26,7 +28,11 @@ impl PgCommand {
     /// * `pw_file_path` — Path to the password file created by
     ///   [`crate::pg_access::PgAccess::create_password_file`].
     /// * `user` — Name of the initial superuser.
-    /// * `auth_method` — Authentication method written to `pg_hba.conf`.
+    /// * `auth_method` — Authentication method written to `pg_hba.conf`,
+    ///   unless `options` overrides it.
+    /// * `options` — Encoding, locale and further `initdb` options.
+    /// * `version` — PostgreSQL version of `init_db_exe`; decides how
+    ///   version-dependent options are passed.
     ///
     /// # Errors
     ///
@@ -40,34 +46,11 @@ impl PgCommand {
         pw_file_path: &Path,
         user: &str,
         auth_method: &PgAuthMethod,
+        options: &InitDbOptions,
+        version: &PostgresVersion,
     ) -> Result<AsyncCommandExecutor<PgServerStatus, Error, PgProcessType>> {
         let init_db_executable = init_db_exe.as_os_str();
-        let pw_file_str = pw_file_path
-            .to_str()
-            .ok_or(Error::InvalidPgUrl)?;
-        let password_file_arg = format!("--pwfile={}", pw_file_str);
-        let auth_host = match auth_method {
-            PgAuthMethod::Plain => "password",
-            PgAuthMethod::MD5 => "md5",
-            PgAuthMethod::ScramSha256 => "scram-sha-256",
-        };
-        let db_dir_str = database_dir.to_str().ok_or(Error::InvalidPgUrl)?;
-        let args = [
-            "-A",
-            auth_host,
-            "-U",
-            user,
-            // The postgres-tokio driver uses utf8 encoding, however on windows
-            // if -E is not specified WIN1252 encoding is chosen by default
-            // which can lead to encoding errors like this:
-            //
-            // ERROR: character with byte sequence 0xe0 0xab 0x87 in encoding
-            // "UTF8" has no equivalent in encoding "WIN1252"
-            "-E=UTF8",
-            "-D",
-            db_dir_str,
-            &password_file_arg,
-        ];
+        let args = Self::init_db_args(database_dir, pw_file_path, user, auth_method, options, version)?;
 
         AsyncCommandExecutor::<PgServerStatus, Error, PgProcessType>::new(
             init_db_executable,
@@ -76,6 +59,30 @@ impl PgCommand {
         )
     }
 
+    /// Builds the `initdb` command line; see [`Self::init_db_executor`].
+    fn init_db_args(
+        database_dir: &Path,
+        pw_file_path: &Path,
+        user: &str,
+        auth_method: &PgAuthMethod,
+        options: &InitDbOptions,
+        version: &PostgresVersion,
+    ) -> Result<Vec<String>> {
+        let pw_file_str = pw_file_path
+            .to_str()
+            .ok_or(Error::InvalidPgUrl)?;
+        let db_dir_str = database_dir.to_str().ok_or(Error::InvalidPgUrl)?;
+        let mut args = vec![
+            "-U".to_string(),
+            user.to_string(),
+            "-D".to_string(),
+            db_dir_str.to_string(),
+            format!("--pwfile={}", pw_file_str),
+        ];
+        args.extend(options.args(auth_method, version));
+        Ok(args)
+    }
+
     /// Creates an [`AsyncCommandExecutor`] that runs `pg_ctl start`.
     ///
     /// # Arguments
@@ -128,4 +135,114 @@ impl PgCommand {
             PgProcessType::StopDb,
         )
     }
+
+    /// Creates an [`AsyncCommandExecutor`] that runs `pg_ctl reload`, which
+    /// signals the server to re-read its configuration files.
+    ///
+    /// # Arguments
+    ///
+    /// * `pg_ctl_exe` — Path to the `pg_ctl` binary.
+    /// * `database_dir` — The cluster directory passed to `pg_ctl -D`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPgUrl`] if `database_dir` is not valid UTF-8.
+    /// Returns [`Error::PgReloadFailure`] if the process cannot be spawned.
+    pub fn reload_db_executor(
+        pg_ctl_exe: &Path,
+        database_dir: &Path,
+    ) -> Result<AsyncCommandExecutor<PgServerStatus, Error, PgProcessType>> {
+        let pg_ctl_executable = pg_ctl_exe.as_os_str();
+        let db_dir_str = database_dir.to_str().ok_or(Error::InvalidPgUrl)?;
+        let args = ["reload", "-D", db_dir_str];
+        AsyncCommandExecutor::<PgServerStatus, Error, PgProcessType>::new(
+            pg_ctl_executable,
+            args,
+            PgProcessType::ReloadDb,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pg_enums::LocaleProvider;
+    use crate::pg_fetch::{PG_V17, PG_V18};
+
+    #[test]
+    fn test_init_db_args_default() -> Result<()> {
+        let args = PgCommand::init_db_args(
+            Path::new("/data/db"),
+            Path::new("/data/db.pwfile"),
+            "postgres",
+            &PgAuthMethod::MD5,
+            &InitDbOptions::default(),
+            &PG_V17,
+        )?;
+        assert_eq!(
+            args,
+            [
+                "-U",
+                "postgres",
+                "-D",
+                "/data/db",
+                "--pwfile=/data/db.pwfile",
+                "--auth-local=md5",
+                "--auth-host=md5",
+                "--encoding=UTF8",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_db_args_options() -> Result<()> {
+        let options = InitDbOptions {
+            encoding: "LATIN1".to_string(),
+            locale: Some("de_DE".to_string()),
+            lc_collate: Some("C".to_string()),
+            locale_provider: Some(LocaleProvider::Icu),
+            icu_locale: Some("und".to_string()),
+            data_checksums: Some(true),
+            wal_segment_size_mb: Some(64),
+            auth_local: Some(PgAuthMethod::Plain),
+            extra_args: vec!["--no-instructions".to_string()],
+            ..Default::default()
+        };
+        let args = PgCommand::init_db_args(
+            Path::new("/db"),
+            Path::new("/pw"),
+            "admin",
+            &PgAuthMethod::ScramSha256,
+            &options,
+            &PG_V17,
+        )?;
+        assert_eq!(
+            &args[5..],
+            [
+                "--auth-local=password",
+                "--auth-host=scram-sha-256",
+                "--encoding=LATIN1",
+                "--locale=de_DE",
+                "--lc-collate=C",
+                "--icu-locale=und",
+                "--locale-provider=icu",
+                "--data-checksums",
+                "--wal-segsize=64",
+                "--no-instructions",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_db_args_disable_checksums() {
+        let options = InitDbOptions {
+            data_checksums: Some(false),
+            ..Default::default()
+        };
+        let method = PgAuthMethod::MD5;
+        assert!(options.args(&method, &PG_V18).contains(&"--no-data-checksums".to_string()));
+        assert!(!options.args(&method, &PG_V17).iter().any(|a| a.contains("checksums")));
+    }
 }
@@ -0,0 +1,471 @@
+//! Typed server configuration written to the cluster directory.
+//!
+//! [`ServerConfig`] holds the parameters (GUCs) pg-embed sets on the server:
+//! common ones as typed fields, anything else in [`ServerConfig::extra`].
+//! Before every start, and on
+//! [`PgEmbed::set_config`](crate::postgres::PgEmbed::set_config), they are
+//! written to `pg-embed.conf` in the cluster directory, which
+//! `postgresql.conf` includes.  `postgresql.conf` itself and
+//! `postgresql.auto.conf` (used by `ALTER SYSTEM`) are never rewritten, so
+//! settings made there survive; `ALTER SYSTEM` still takes precedence.
+//!
+//! ```rust
+//! use std::collections::BTreeMap;
+//! use pg_embed::pg_conf::{LogStatement, MemorySize, ServerConfig};
+//!
+//! let config = ServerConfig {
+//!     shared_buffers: Some(MemorySize::mib(256)),
+//!     max_connections: Some(200),
+//!     fsync: Some(false),
+//!     log_statement: Some(LogStatement::Ddl),
+//!     timezone: Some("UTC".to_string()),
+//!     extra: BTreeMap::from([("jit".to_string(), "off".to_string())]),
+//!     ..Default::default()
+//! };
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::pg_errors::{Error, Result};
+
+/// File in the cluster directory holding the parameters of [`ServerConfig`].
+pub const CONFIG_FILE_NAME: &str = "pg-embed.conf";
+
+/// Line appended to `postgresql.conf` to load [`CONFIG_FILE_NAME`].
+const INCLUDE_LINE: &str = "include_if_exists = 'pg-embed.conf'";
+
+/// Parameters that only take effect when the server restarts (context
+/// `postmaster`).  Every other parameter is applied by a reload.
+pub const RESTART_PARAMETERS: &[&str] = &[
+    "archive_mode",
+    "autovacuum_max_workers",
+    "cluster_name",
+    "data_directory",
+    "dynamic_shared_memory_type",
+    "hba_file",
+    "huge_pages",
+    "ident_file",
+    "listen_addresses",
+    "max_connections",
+    "max_files_per_process",
+    "max_locks_per_transaction",
+    "max_logical_replication_workers",
+    "max_pred_locks_per_transaction",
+    "max_prepared_transactions",
+    "max_replication_slots",
+    "max_wal_senders",
+    "max_worker_processes",
+    "port",
+    "shared_buffers",
+    "shared_memory_type",
+    "shared_preload_libraries",
+    "superuser_reserved_connections",
+    "track_commit_timestamp",
+    "unix_socket_directories",
+    "unix_socket_group",
+    "unix_socket_permissions",
+    "wal_buffers",
+    "wal_level",
+    "wal_log_hints",
+];
+
+/// Parameters pg-embed controls through [`crate::postgres::PgSettings`] and
+/// that therefore may not appear in [`ServerConfig::extra`].
+const RESERVED_PARAMETERS: &[&str] = &["port", "data_directory", "unix_socket_directories"];
+
+/// An amount of memory, written in the largest exact PostgreSQL unit, e.g.
+/// `128MB`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemorySize(u64);
+
+impl MemorySize {
+    /// `n` kibibytes.  PostgreSQL's `kB` is 1024 bytes.
+    pub const fn kib(n: u64) -> Self {
+        MemorySize(n)
+    }
+
+    /// `n` mebibytes.
+    pub const fn mib(n: u64) -> Self {
+        MemorySize(n * 1024)
+    }
+
+    /// `n` gibibytes.
+    pub const fn gib(n: u64) -> Self {
+        MemorySize(n * 1024 * 1024)
+    }
+
+    /// Returns the size in kibibytes.
+    pub const fn as_kib(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for MemorySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = [("TB", 1 << 30), ("GB", 1 << 20), ("MB", 1 << 10)];
+        for (unit, kib) in units {
+            if self.0 != 0 && self.0.is_multiple_of(kib) {
+                return write!(f, "{}{}", self.0 / kib, unit);
+            }
+        }
+        write!(f, "{}kB", self.0)
+    }
+}
+
+/// Values of `synchronous_commit`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SynchronousCommit {
+    /// Wait for the local WAL flush (and synchronous standbys).
+    On,
+    /// Do not wait for the WAL flush; a crash may lose recent commits.
+    Off,
+    /// Wait for the local WAL flush only.
+    Local,
+    /// Wait until synchronous standbys have written the WAL.
+    RemoteWrite,
+    /// Wait until synchronous standbys have applied the WAL.
+    RemoteApply,
+}
+
+impl fmt::Display for SynchronousCommit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SynchronousCommit::On => write!(f, "on"),
+            SynchronousCommit::Off => write!(f, "off"),
+            SynchronousCommit::Local => write!(f, "local"),
+            SynchronousCommit::RemoteWrite => write!(f, "remote_write"),
+            SynchronousCommit::RemoteApply => write!(f, "remote_apply"),
+        }
+    }
+}
+
+/// Values of `log_statement`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LogStatement {
+    /// Log no statements.
+    None,
+    /// Log data definition statements.
+    Ddl,
+    /// Log data definition and modifying statements.
+    Mod,
+    /// Log all statements.
+    All,
+}
+
+impl fmt::Display for LogStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogStatement::None => write!(f, "none"),
+            LogStatement::Ddl => write!(f, "ddl"),
+            LogStatement::Mod => write!(f, "mod"),
+            LogStatement::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Server parameters.  `None` fields keep the value from `postgresql.conf`
+/// or the server default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// `shared_buffers`.  Requires a restart.
+    pub shared_buffers: Option<MemorySize>,
+    /// `max_connections`.  Requires a restart.
+    pub max_connections: Option<u32>,
+    /// `work_mem`.
+    pub work_mem: Option<MemorySize>,
+    /// `maintenance_work_mem`.
+    pub maintenance_work_mem: Option<MemorySize>,
+    /// `fsync`.  Turning it off speeds up tests but risks corruption on a
+    /// crash.
+    pub fsync: Option<bool>,
+    /// `synchronous_commit`.
+    pub synchronous_commit: Option<SynchronousCommit>,
+    /// `full_page_writes`.
+    pub full_page_writes: Option<bool>,
+    /// `log_statement`.
+    pub log_statement: Option<LogStatement>,
+    /// `log_min_duration_statement` in milliseconds; `-1` disables it.
+    pub log_min_duration_statement_ms: Option<i64>,
+    /// `timezone`, e.g. `UTC` or `Europe/Berlin`.
+    pub timezone: Option<String>,
+    /// Any other parameter, by name.  Values are written quoted, exactly as
+    /// given, e.g. `("jit", "off")` or `("statement_timeout", "30s")`.
+    pub extra: BTreeMap<String, String>,
+}
+
+impl ServerConfig {
+    /// Returns every set parameter as `(name, value)`, typed fields first
+    /// and then [`Self::extra`] in name order.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let typed = [
+            ("shared_buffers", self.shared_buffers.map(|v| v.to_string())),
+            ("max_connections", self.max_connections.map(|v| v.to_string())),
+            ("work_mem", self.work_mem.map(|v| v.to_string())),
+            ("maintenance_work_mem", self.maintenance_work_mem.map(|v| v.to_string())),
+            ("fsync", self.fsync.map(on_off)),
+            ("synchronous_commit", self.synchronous_commit.map(|v| v.to_string())),
+            ("full_page_writes", self.full_page_writes.map(on_off)),
+            ("log_statement", self.log_statement.map(|v| v.to_string())),
+            (
+                "log_min_duration_statement",
+                self.log_min_duration_statement_ms.map(|v| v.to_string()),
+            ),
+            ("timezone", self.timezone.clone()),
+        ];
+        typed
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value?)))
+            .chain(self.extra.iter().map(|(k, v)| (k.clone(), v.clone())))
+            .collect()
+    }
+
+    /// Checks that the configuration can be written.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSettings`] if a name in [`Self::extra`] is not
+    /// a valid parameter name, duplicates a typed field, or is `port`,
+    /// `data_directory` or `unix_socket_directories` (set through
+    /// [`PgSettings`](crate::postgres::PgSettings)), or if a value contains
+    /// a line break.
+    pub fn validate(&self) -> Result<()> {
+        let typed: Vec<String> = ServerConfig {
+            extra: BTreeMap::new(),
+            ..self.clone()
+        }
+        .entries()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+        for name in self.extra.keys() {
+            let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+            if name.is_empty() || !name.chars().all(valid) {
+                return Err(Error::InvalidSettings(format!(
+                    "invalid server parameter name {:?}",
+                    name
+                )));
+            }
+            let lower = name.to_ascii_lowercase();
+            if typed.contains(&lower) {
+                return Err(Error::InvalidSettings(format!(
+                    "server parameter {} is set both as a field and in extra",
+                    name
+                )));
+            }
+            if RESERVED_PARAMETERS.contains(&lower.as_str()) {
+                return Err(Error::InvalidSettings(format!(
+                    "server parameter {} is controlled by PgSettings",
+                    name
+                )));
+            }
+        }
+        for (name, value) in self.entries() {
+            if value.contains(['\n', '\r']) {
+                return Err(Error::InvalidSettings(format!(
+                    "value of server parameter {} contains a line break",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the configuration file.
+    fn render(&self) -> String {
+        let mut out = String::from(
+            "# Written by pg-embed from PgSettings::config; changes are overwritten.\n",
+        );
+        for (name, value) in self.entries() {
+            out.push_str(&format!("{} = '{}'\n", name, value.replace('\'', "''")));
+        }
+        out
+    }
+}
+
+/// Parameters changed by [`PgEmbed::set_config`](crate::postgres::PgEmbed::set_config).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigChanges {
+    /// Changed parameters that a reload applies.
+    pub applied: Vec<String>,
+    /// Changed parameters that only take effect after a restart; see
+    /// [`RESTART_PARAMETERS`].
+    pub requires_restart: Vec<String>,
+}
+
+impl ConfigChanges {
+    /// Compares two configurations.  Parameters that were added, removed or
+    /// given another value count as changed.
+    pub fn between(old: &ServerConfig, new: &ServerConfig) -> Self {
+        let old: BTreeMap<String, String> = old.entries().into_iter().collect();
+        let new: BTreeMap<String, String> = new.entries().into_iter().collect();
+        let mut changes = ConfigChanges::default();
+        let names: std::collections::BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for name in names {
+            if old.get(name) == new.get(name) {
+                continue;
+            }
+            if RESTART_PARAMETERS.contains(&name.to_ascii_lowercase().as_str()) {
+                changes.requires_restart.push(name.clone());
+            } else {
+                changes.applied.push(name.clone());
+            }
+        }
+        changes
+    }
+
+    /// Returns `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.requires_restart.is_empty()
+    }
+}
+
+/// Writes `config` to [`CONFIG_FILE_NAME`] in `database_dir` and makes sure
+/// `postgresql.conf` includes it.
+///
+/// The file is written next to its final name and renamed into place, so
+/// the server never reads a partial file.
+///
+/// # Errors
+///
+/// Returns [`Error::ReadFileError`] if `postgresql.conf` cannot be read.
+/// Returns [`Error::WriteFileError`] if a file cannot be written.
+pub(crate) async fn write(database_dir: &Path, config: &ServerConfig) -> Result<()> {
+    let path = database_dir.join(CONFIG_FILE_NAME);
+    let partial = PathBuf::from(format!("{}.partial", path.display()));
+    tokio::fs::write(&partial, config.render())
+        .await
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    tokio::fs::rename(&partial, &path)
+        .await
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+
+    let main = database_dir.join("postgresql.conf");
+    let contents = tokio::fs::read_to_string(&main)
+        .await
+        .map_err(|e| Error::ReadFileError(format!("{}: {}", main.display(), e)))?;
+    if !contents.lines().any(|line| line.trim() == INCLUDE_LINE) {
+        let separator = if contents.is_empty() || contents.ends_with('\n') { "" } else { "\n" };
+        let appended = format!(
+            "{}{}\n# Parameters from pg-embed\n{}\n",
+            contents, separator, INCLUDE_LINE
+        );
+        tokio::fs::write(&main, appended)
+            .await
+            .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Renders a boolean parameter.
+fn on_off(value: bool) -> String {
+    if value { "on" } else { "off" }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_size_display() {
+        assert_eq!(MemorySize::mib(128).to_string(), "128MB");
+        assert_eq!(MemorySize::gib(2).to_string(), "2GB");
+        assert_eq!(MemorySize::kib(1536).to_string(), "1536kB");
+        assert_eq!(MemorySize::gib(1024).to_string(), "1TB");
+        assert_eq!(MemorySize::kib(0).to_string(), "0kB");
+    }
+
+    #[test]
+    fn test_render() {
+        let config = ServerConfig {
+            shared_buffers: Some(MemorySize::mib(256)),
+            fsync: Some(false),
+            synchronous_commit: Some(SynchronousCommit::RemoteApply),
+            timezone: Some("Europe/Berlin".to_string()),
+            extra: BTreeMap::from([
+                ("application_name".to_string(), "it's".to_string()),
+                ("jit".to_string(), "off".to_string()),
+            ]),
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        assert_eq!(
+            config.render().lines().skip(1).collect::<Vec<_>>(),
+            [
+                "shared_buffers = '256MB'",
+                "fsync = 'off'",
+                "synchronous_commit = 'remote_apply'",
+                "timezone = 'Europe/Berlin'",
+                "application_name = 'it''s'",
+                "jit = 'off'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let with_extra = |name: &str, value: &str| ServerConfig {
+            fsync: Some(true),
+            extra: BTreeMap::from([(name.to_string(), value.to_string())]),
+            ..Default::default()
+        };
+        with_extra("auto_explain.log_min_duration", "1s").validate().unwrap();
+        for (name, value) in [
+            ("fsync", "off"),
+            ("port", "5433"),
+            ("unix_socket_directories", "/tmp"),
+            ("bad name", "1"),
+            ("", "1"),
+            ("search_path", "public\nfsync = off"),
+        ] {
+            assert!(
+                matches!(with_extra(name, value).validate(), Err(Error::InvalidSettings(_))),
+                "{name:?} = {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_changes_between() {
+        let old = ServerConfig {
+            shared_buffers: Some(MemorySize::mib(128)),
+            work_mem: Some(MemorySize::mib(4)),
+            log_statement: Some(LogStatement::All),
+            ..Default::default()
+        };
+        let new = ServerConfig {
+            shared_buffers: Some(MemorySize::mib(256)),
+            work_mem: Some(MemorySize::mib(4)),
+            extra: BTreeMap::from([("wal_level".to_string(), "logical".to_string())]),
+            ..Default::default()
+        };
+        let changes = ConfigChanges::between(&old, &new);
+        assert_eq!(changes.applied, ["log_statement"]);
+        assert_eq!(changes.requires_restart, ["shared_buffers", "wal_level"]);
+        assert!(ConfigChanges::between(&new, &new).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_write_includes_once() -> Result<()> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let main = dir.path().join("postgresql.conf");
+        tokio::fs::write(&main, "max_connections = 100").await.unwrap();
+        let config = ServerConfig {
+            max_connections: Some(10),
+            ..Default::default()
+        };
+        write(dir.path(), &config).await?;
+        write(dir.path(), &config).await?;
+
+        let contents = tokio::fs::read_to_string(&main).await.unwrap();
+        assert!(contents.starts_with("max_connections = 100\n"));
+        assert_eq!(contents.matches(INCLUDE_LINE).count(), 1);
+        let written = tokio::fs::read_to_string(dir.path().join(CONFIG_FILE_NAME))
+            .await
+            .unwrap();
+        assert!(written.ends_with("max_connections = '10'\n"));
+        assert!(!dir.path().join("pg-embed.conf.partial").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,535 @@
+//! PostgreSQL binaries embedded into the executable at build time.
+//!
+//! A build script calls [`embed_bundle`], which locates the bundle for the
+//! configured version and platform — an explicit file, the `.jar` kept in the
+//! local binary cache, or a fresh download — and copies it into `OUT_DIR`
+//! together with an [`EmbeddedMetadata`] file.  The bundle is stored as
+//! published, i.e. still compressed.
+//!
+//! The [`embedded_bundle!`](crate::embedded_bundle) macro then includes both
+//! files into the executable as an [`EmbeddedBundle`].  With
+//! [`PgBinarySource::Embedded`] the first
+//! [`PgAccess::maybe_acquire_postgres`](crate::pg_access::PgAccess::maybe_acquire_postgres)
+//! verifies the bundle's SHA-256 digest and extracts it into the cache;
+//! later starts find it there.  No network access takes place at run time.
+//!
+//! `build.rs` (with pg-embed also listed under `[build-dependencies]`):
+//!
+//! ```rust,ignore
+//! use pg_embed::pg_fetch::{PgFetchSettings, PG_V17};
+//!
+//! fn main() {
+//!     let settings = PgFetchSettings { version: PG_V17, ..Default::default() };
+//!     pg_embed::pg_embedded::embed_bundle(&settings, None).unwrap();
+//! }
+//! ```
+//!
+//! Application:
+//!
+//! ```rust,ignore
+//! use pg_embed::pg_embedded::EmbeddedBundle;
+//!
+//! static POSTGRES: EmbeddedBundle = pg_embed::embedded_bundle!();
+//!
+//! let fetch_settings = POSTGRES.fetch_settings()?;
+//! let mut pg = PgEmbed::new(pg_settings, fetch_settings).await?;
+//! ```
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pg_access::PgAccess;
+use crate::pg_cache::{self, CacheLock, CacheManifest};
+use crate::pg_checksum::{BundleDigests, BundleHasher, Checksum, ChecksumAlgorithm};
+use crate::pg_errors::{Error, Result};
+use crate::pg_fetch::{PgBinarySource, PgFetchSettings, PostgresVersion};
+use crate::pg_unpack::{self, BundleFormat, UnpackOptions};
+
+/// Directory below `OUT_DIR` that [`embed_bundle`] writes to.
+pub const EMBED_DIR_NAME: &str = "pg-embed";
+
+/// File name of the embedded bundle inside [`EMBED_DIR_NAME`].
+pub const EMBED_BUNDLE_FILE_NAME: &str = "bundle";
+
+/// File name of the [`EmbeddedMetadata`] inside [`EMBED_DIR_NAME`].
+pub const EMBED_METADATA_FILE_NAME: &str = "bundle.json";
+
+/// Describes an embedded bundle.  Written by [`embed_bundle`] as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddedMetadata {
+    /// PostgreSQL version of the binaries.
+    pub version: PostgresVersion,
+    /// Maven platform classifier, e.g. `linux-amd64`.
+    pub platform: String,
+    /// Hex-encoded SHA-256 digest of the bundle.
+    pub sha256: String,
+    /// Leading path components to remove when unpacking; see
+    /// [`PgFetchSettings::strip_components`].
+    pub strip_components: usize,
+    /// Where the bundle came from at build time: a URL or a file path.
+    pub source: String,
+}
+
+/// PostgreSQL binaries included into the executable.
+///
+/// Created by the [`embedded_bundle!`](crate::embedded_bundle) macro and
+/// used through [`PgBinarySource::Embedded`].
+#[derive(Clone, Copy)]
+pub struct EmbeddedBundle {
+    bytes: &'static [u8],
+    metadata: &'static str,
+}
+
+impl EmbeddedBundle {
+    /// Wraps a bundle and its [`EmbeddedMetadata`] JSON.
+    ///
+    /// Normally called through [`embedded_bundle!`](crate::embedded_bundle).
+    pub const fn new(bytes: &'static [u8], metadata: &'static str) -> Self {
+        EmbeddedBundle { bytes, metadata }
+    }
+
+    /// Returns the compressed bundle.
+    pub fn bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+
+    /// Parses the metadata written by [`embed_bundle`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSettings`] if the metadata is malformed.
+    pub fn metadata(&self) -> Result<EmbeddedMetadata> {
+        serde_json::from_str(self.metadata)
+            .map_err(|e| Error::InvalidSettings(format!("invalid embedded bundle metadata: {}", e)))
+    }
+
+    /// Returns default fetch settings that use this bundle, with the version
+    /// taken from its metadata.
+    ///
+    /// The platform is the one detected at run time (see
+    /// [`Platform::current`](crate::pg_platform::Platform::current)); set
+    /// [`PgFetchSettings::operating_system`] and
+    /// [`PgFetchSettings::architecture`] if the bundle was built for another
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSettings`] if the metadata is malformed.
+    pub fn fetch_settings(&self) -> Result<PgFetchSettings> {
+        Ok(PgFetchSettings {
+            version: self.metadata()?.version,
+            binary_source: PgBinarySource::Embedded(*self),
+            ..Default::default()
+        })
+    }
+
+    /// Verifies the bundle and extracts it into `cache_dir`.
+    ///
+    /// Goes through the same cross-process lock, staging directory and
+    /// completion manifest as a download.  If the version is already
+    /// installed, the cache is left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSettings`] if the metadata is malformed.
+    /// Returns [`Error::ChecksumMismatch`] if the bundle does not match the
+    /// digest recorded at build time; nothing is unpacked in that case.
+    /// Returns [`Error::CacheLockTimeout`] if another process holds the cache
+    /// lock for longer than [`PgFetchSettings::cache_lock_timeout`].
+    /// Returns [`Error::WriteFileError`], [`Error::DirCreationError`],
+    /// [`Error::UnpackFailure`] or [`Error::InvalidPgPackage`] if extraction
+    /// fails.
+    pub(crate) async fn install_into(
+        &self,
+        cache_dir: &Path,
+        fetch_settings: &PgFetchSettings,
+    ) -> Result<CacheManifest> {
+        let metadata = self.metadata()?;
+        if let Some(parent) = cache_dir.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| Error::DirCreationError(e.to_string()))?;
+        }
+        let _cache_lock = CacheLock::acquire(cache_dir, fetch_settings.cache_lock_timeout).await?;
+        if let Some(existing) = CacheManifest::read(cache_dir).await? {
+            return Ok(existing);
+        }
+        pg_cache::remove_stale_staging_dirs(cache_dir).await?;
+
+        let staging = pg_cache::staging_dir(cache_dir);
+        let result = self.extract(&staging, fetch_settings, &metadata).await;
+        if result.is_err() {
+            let _ = pg_cache::remove_dir_if_exists(&staging).await;
+        }
+        result?;
+        let manifest = CacheManifest {
+            version: metadata.version.to_string(),
+            platform: metadata.platform,
+            source_url: format!("embedded:{}", metadata.source),
+            sha256: metadata.sha256,
+            files: Vec::new(),
+        };
+        pg_cache::install(&staging, cache_dir, manifest).await?;
+        log::info!("Extracted embedded PostgreSQL binaries into {}", cache_dir.display());
+        CacheManifest::read(cache_dir)
+            .await?
+            .ok_or_else(|| Error::ReadFileError(format!("{} has no manifest", cache_dir.display())))
+    }
+
+    /// Checks the digest, writes the bundle into `staging` and unpacks it
+    /// there.  The written bundle is removed again, since the executable
+    /// already holds a copy.
+    async fn extract(
+        &self,
+        staging: &Path,
+        fetch_settings: &PgFetchSettings,
+        metadata: &EmbeddedMetadata,
+    ) -> Result<()> {
+        let bytes = self.bytes;
+        let digests = tokio::task::spawn_blocking(move || BundleDigests::of(bytes))
+            .await
+            .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))?;
+        digests.verify(&Checksum::new(ChecksumAlgorithm::Sha256, &metadata.sha256))?;
+
+        tokio::fs::create_dir_all(staging)
+            .await
+            .map_err(|e| Error::DirCreationError(e.to_string()))?;
+        let archive = staging.join(EMBED_BUNDLE_FILE_NAME);
+        tokio::fs::write(&archive, bytes)
+            .await
+            .map_err(|e| Error::WriteFileError(e.to_string()))?;
+        let options = UnpackOptions {
+            strip_components: metadata.strip_components,
+            ..fetch_settings.unpack_options()
+        };
+        pg_unpack::unpack_postgres_with(&archive, staging, &options).await?;
+        tokio::fs::remove_file(&archive)
+            .await
+            .map_err(|e| Error::WriteFileError(e.to_string()))
+    }
+}
+
+impl fmt::Debug for EmbeddedBundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EmbeddedBundle")
+            .field("bytes", &format_args!("<{} bytes>", self.bytes.len()))
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+impl PartialEq for EmbeddedBundle {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.bytes, other.bytes) && self.metadata == other.metadata
+    }
+}
+
+/// Includes the bundle prepared by [`embed_bundle`] into the executable.
+///
+/// Expands to a constant [`EmbeddedBundle`] expression reading
+/// `$OUT_DIR/pg-embed/bundle` and `$OUT_DIR/pg-embed/bundle.json`, so it
+/// must be used in the crate whose build script called [`embed_bundle`].
+#[macro_export]
+macro_rules! embedded_bundle {
+    () => {
+        $crate::pg_embedded::EmbeddedBundle::new(
+            include_bytes!(concat!(env!("OUT_DIR"), "/pg-embed/bundle")),
+            include_str!(concat!(env!("OUT_DIR"), "/pg-embed/bundle.json")),
+        )
+    };
+}
+
+/// Prepares the bundle for `fetch_settings` for embedding.  Call from a
+/// build script.
+///
+/// The bundle is taken from the first of:
+///
+/// 1. `bundle`, a zonky `.jar` or any other
+///    [`BundleFormat`](crate::pg_unpack::BundleFormat);
+/// 2. the `.jar` kept in the binary cache, if the version was downloaded
+///    before;
+/// 3. a download from [`PgFetchSettings::hosts`], verified like any other.
+///
+/// It is copied to `$OUT_DIR/pg-embed/bundle` and described in
+/// `$OUT_DIR/pg-embed/bundle.json`, ready for
+/// [`embedded_bundle!`](crate::embedded_bundle).  Build scripts run on the
+/// host, so set [`PgFetchSettings::operating_system`] and
+/// [`PgFetchSettings::architecture`] explicitly when cross-compiling.
+///
+/// # Arguments
+///
+/// * `fetch_settings` — Version and platform to embed.  Also supplies
+///   [`PgFetchSettings::pinned_checksum`], which the bundle must match, and
+///   [`PgFetchSettings::strip_components`].
+/// * `bundle` — Explicit bundle file, or `None` to locate or download one.
+///
+/// # Returns
+///
+/// The metadata written next to the bundle.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidSettings`] if `OUT_DIR` is not set, i.e. this is
+/// not called from a build script.
+/// Returns [`Error::InvalidPgPackage`] if the bundle is not a supported
+/// archive.
+/// Returns [`Error::ChecksumMismatch`] if the bundle does not match
+/// [`PgFetchSettings::pinned_checksum`].
+/// Returns [`Error::OfflineCacheMiss`] if the bundle would have to be
+/// downloaded but downloads are disabled.
+/// Returns the errors of a download, and [`Error::ReadFileError`] or
+/// [`Error::WriteFileError`] on I/O failures.
+pub fn embed_bundle(
+    fetch_settings: &PgFetchSettings,
+    bundle: Option<&Path>,
+) -> Result<EmbeddedMetadata> {
+    let out_dir = std::env::var_os("OUT_DIR").ok_or_else(|| {
+        Error::InvalidSettings("OUT_DIR is not set; call embed_bundle from a build script".into())
+    })?;
+    if let Some(bundle) = bundle {
+        println!("cargo:rerun-if-changed={}", bundle.display());
+    }
+    embed_bundle_into(
+        fetch_settings,
+        bundle,
+        &PathBuf::from(out_dir).join(EMBED_DIR_NAME),
+    )
+}
+
+/// Implementation of [`embed_bundle`] with an explicit output directory.
+fn embed_bundle_into(
+    fetch_settings: &PgFetchSettings,
+    bundle: Option<&Path>,
+    dir: &Path,
+) -> Result<EmbeddedMetadata> {
+    std::fs::create_dir_all(dir).map_err(|e| Error::DirCreationError(e.to_string()))?;
+    let dest = dir.join(EMBED_BUNDLE_FILE_NAME);
+    let cached_jar = PgAccess::binary_cache_dir(fetch_settings)?.join(format!(
+        "{}-{}.zip",
+        fetch_settings.platform(),
+        fetch_settings.version
+    ));
+    let (digests, source) = match bundle {
+        Some(src) => (copy_hashed(src, &dest)?, src.display().to_string()),
+        None if cached_jar.is_file() => {
+            log::info!("Embedding {}", cached_jar.display());
+            (copy_hashed(&cached_jar, &dest)?, cached_jar.display().to_string())
+        }
+        None => download(fetch_settings, &dest, &cached_jar)?,
+    };
+    if let Some(expected) = &fetch_settings.pinned_checksum {
+        digests.verify(expected)?;
+    }
+    check_format(&dest)?;
+
+    let metadata = EmbeddedMetadata {
+        version: fetch_settings.version,
+        platform: fetch_settings.platform(),
+        sha256: digests.sha256,
+        strip_components: fetch_settings.strip_components,
+        source,
+    };
+    let json = serde_json::to_vec_pretty(&metadata)
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    std::fs::write(dir.join(EMBED_METADATA_FILE_NAME), json)
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    Ok(metadata)
+}
+
+/// Downloads the bundle to `dest` on a temporary runtime, since build
+/// scripts are synchronous.
+fn download(
+    fetch_settings: &PgFetchSettings,
+    dest: &Path,
+    cached_jar: &Path,
+) -> Result<(BundleDigests, String)> {
+    if let Some(reason) = fetch_settings.downloads_disabled() {
+        return Err(Error::OfflineCacheMiss {
+            version: fetch_settings.version.to_string(),
+            platform: fetch_settings.platform(),
+            cache_dir: cached_jar
+                .parent()
+                .unwrap_or(cached_jar)
+                .display()
+                .to_string(),
+            reason: reason.to_string(),
+        });
+    }
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| Error::PgError(e.to_string(), "building a tokio runtime".into()))?;
+    runtime.block_on(fetch_settings.fetch_postgres_to_file(dest))
+}
+
+/// Copies `src` to `dest`, hashing it on the way.
+fn copy_hashed(src: &Path, dest: &Path) -> Result<BundleDigests> {
+    let mut reader = std::fs::File::open(src)
+        .map_err(|e| Error::ReadFileError(format!("{}: {}", src.display(), e)))?;
+    let mut writer =
+        std::fs::File::create(dest).map_err(|e| Error::WriteFileError(e.to_string()))?;
+    let mut hasher = BundleHasher::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader
+            .read(&mut buffer)
+            .map_err(|e| Error::ReadFileError(e.to_string()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        writer
+            .write_all(&buffer[..n])
+            .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    }
+    Ok(hasher.finalize())
+}
+
+/// Checks that `path` starts like a supported archive.
+fn check_format(path: &Path) -> Result<()> {
+    let mut head = [0u8; 8];
+    let mut file = std::fs::File::open(path).map_err(|e| Error::ReadFileError(e.to_string()))?;
+    let len = file
+        .read(&mut head)
+        .map_err(|e| Error::ReadFileError(e.to_string()))?;
+    match BundleFormat::detect(&head[..len]) {
+        Some(_) => Ok(()),
+        None => {
+            log::error!("{} is not a PostgreSQL archive", path.display());
+            Err(Error::InvalidPgPackage)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pg_fetch::{PG_V16, PG_V17};
+
+    /// Writes a `.tar.gz` holding `bin/initdb` below a top-level directory.
+    fn write_tar_gz(path: &Path) {
+        let gz = flate2::write::GzEncoder::new(
+            std::fs::File::create(path).unwrap(),
+            flate2::Compression::fast(),
+        );
+        let mut builder = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "pgsql/bin/initdb", &b"sh\n"[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    /// Embeds a fresh bundle for `fetch_settings` and leaks it the way
+    /// `include_bytes!` would hold it.
+    fn embed(fetch_settings: &PgFetchSettings, root: &Path) -> Result<EmbeddedBundle> {
+        let archive = root.join("postgres.tar.gz");
+        write_tar_gz(&archive);
+        let out_dir = root.join(EMBED_DIR_NAME);
+        embed_bundle_into(fetch_settings, Some(&archive), &out_dir)?;
+        let bytes = std::fs::read(out_dir.join(EMBED_BUNDLE_FILE_NAME)).unwrap();
+        let metadata = std::fs::read_to_string(out_dir.join(EMBED_METADATA_FILE_NAME)).unwrap();
+        Ok(EmbeddedBundle::new(bytes.leak(), metadata.leak()))
+    }
+
+    #[tokio::test]
+    async fn test_embedded_bundle_is_extracted_offline() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let db_dir = tempfile::TempDir::new().unwrap();
+        let build_settings = PgFetchSettings {
+            version: PG_V17,
+            strip_components: 1,
+            ..Default::default()
+        };
+        let bundle = embed(&build_settings, root.path())?;
+        let metadata = bundle.metadata()?;
+        assert_eq!(metadata.version, PG_V17);
+        assert_eq!(metadata.strip_components, 1);
+        assert_eq!(metadata.sha256, BundleDigests::of(bundle.bytes()).sha256);
+
+        let fetch_settings = PgFetchSettings {
+            cache_dir: Some(root.path().join("cache")),
+            offline: true,
+            ..bundle.fetch_settings()?
+        };
+        fetch_settings.validate()?;
+        let pg_access = PgAccess::new(&fetch_settings, db_dir.path()).await?;
+        assert!(!pg_access.uses_existing_installation());
+        assert!(pg_access
+            .cache_dir
+            .to_string_lossy()
+            .ends_with(&format!("{}-sha256-{}", PG_V17, &metadata.sha256[..12])));
+
+        pg_access.maybe_acquire_postgres().await?;
+        assert!(pg_access.pg_executables_cached().await?);
+        let manifest = CacheManifest::read(&pg_access.cache_dir).await?.unwrap();
+        assert_eq!(manifest.sha256, metadata.sha256);
+        assert!(manifest.source_url.starts_with("embedded:"));
+        assert!(!pg_access.cache_dir.join(EMBED_BUNDLE_FILE_NAME).exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tampered_bundle_is_rejected() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let bundle = embed(&PgFetchSettings::default(), root.path())?;
+        let mut bytes = bundle.bytes().to_vec();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let tampered = EmbeddedBundle::new(bytes.leak(), bundle.metadata);
+
+        let cache_dir = root.path().join("cache");
+        let err = tampered
+            .install_into(&cache_dir, &tampered.fetch_settings()?)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }), "{err}");
+        assert!(!cache_dir.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_embedded_bundle_must_match_settings() -> Result<()> {
+        let root = tempfile::TempDir::new().unwrap();
+        let bundle = embed(&PgFetchSettings::default(), root.path())?;
+        let fetch_settings = PgFetchSettings {
+            version: PG_V16,
+            ..bundle.fetch_settings()?
+        };
+        assert!(matches!(
+            fetch_settings.validate(),
+            Err(Error::InvalidSettings(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_embed_bundle_checks_pinned_checksum() {
+        let root = tempfile::TempDir::new().unwrap();
+        let fetch_settings = PgFetchSettings {
+            pinned_checksum: Some(Checksum::sha256("00".repeat(32))),
+            ..Default::default()
+        };
+        assert!(matches!(
+            embed(&fetch_settings, root.path()),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_embed_bundle_rejects_other_files() {
+        let root = tempfile::TempDir::new().unwrap();
+        let file = root.path().join("README");
+        std::fs::write(&file, b"not an archive").unwrap();
+        assert!(matches!(
+            embed_bundle_into(&PgFetchSettings::default(), Some(&file), root.path()),
+            Err(Error::InvalidPgPackage)
+        ));
+    }
+}
@@ -10,9 +10,13 @@ use crate::pg_errors::Error;
 ///
 /// Postgresql authentication method
 ///
-/// Choose between plain password, md5 or scram_sha_256 authentication.
-/// Scram_sha_256 authentication is only available on postgresql versions >= 11
+/// Choose between plain password, md5 or scram_sha_256 authentication, or
+/// trust, peer, reject and cert.
+/// Scram_sha_256 authentication is only available on postgresql versions >= 10.
+/// Peer only works for local (Unix-domain socket) connections and cert only
+/// for `hostssl` rules (see [`crate::pg_hba::HbaRule`]).
 ///
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum PgAuthMethod {
     /// plain-text
     Plain,
@@ -20,6 +24,14 @@ pub enum PgAuthMethod {
     MD5,
     /// scram_sha_256
     ScramSha256,
+    /// allow without a password
+    Trust,
+    /// operating system user name must match the database user name
+    Peer,
+    /// deny the connection
+    Reject,
+    /// SSL client certificate
+    Cert,
 }
 
 impl fmt::Display for PgAuthMethod {
@@ -28,10 +40,51 @@ impl fmt::Display for PgAuthMethod {
             PgAuthMethod::Plain => write!(f, "password"),
             PgAuthMethod::MD5 => write!(f, "md5"),
             PgAuthMethod::ScramSha256 => write!(f, "scram-sha-256"),
+            PgAuthMethod::Trust => write!(f, "trust"),
+            PgAuthMethod::Peer => write!(f, "peer"),
+            PgAuthMethod::Reject => write!(f, "reject"),
+            PgAuthMethod::Cert => write!(f, "cert"),
         }
     }
 }
 
+///
+/// Locale provider used by `initdb --locale-provider`
+///
+/// `Icu` requires PostgreSQL 15 or newer, `Builtin` PostgreSQL 17 or newer.
+///
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LocaleProvider {
+    /// The operating system's C library
+    Libc,
+    /// ICU, with the locale taken from `icu_locale`
+    Icu,
+    /// PostgreSQL's own provider; only supports the `C` and `C.UTF-8` locales
+    Builtin,
+}
+
+impl fmt::Display for LocaleProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocaleProvider::Libc => write!(f, "libc"),
+            LocaleProvider::Icu => write!(f, "icu"),
+            LocaleProvider::Builtin => write!(f, "builtin"),
+        }
+    }
+}
+
+///
+/// How `PgEmbed::start_db` starts the server
+///
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StartMode {
+    /// `pg_ctl start -w`; the server runs detached from pg-embed
+    PgCtl,
+    /// `postgres` as a child process that pg-embed supervises; see
+    /// [`crate::pg_supervisor`]
+    Supervised,
+}
+
 ///
 /// Postgresql server status
 ///
@@ -67,6 +120,8 @@ pub enum PgProcessType {
     StartDb,
     /// pg_ctl stop process
     StopDb,
+    /// pg_ctl reload process
+    ReloadDb,
 }
 
 impl ProcessStatus<PgServerStatus, Error> for PgProcessType {
@@ -75,6 +130,7 @@ impl ProcessStatus<PgServerStatus, Error> for PgProcessType {
             PgProcessType::InitDb => PgServerStatus::Initializing,
             PgProcessType::StartDb => PgServerStatus::Starting,
             PgProcessType::StopDb => PgServerStatus::Stopping,
+            PgProcessType::ReloadDb => PgServerStatus::Started,
         }
     }
 
@@ -83,6 +139,7 @@ impl ProcessStatus<PgServerStatus, Error> for PgProcessType {
             PgProcessType::InitDb => PgServerStatus::Initialized,
             PgProcessType::StartDb => PgServerStatus::Started,
             PgProcessType::StopDb => PgServerStatus::Stopped,
+            PgProcessType::ReloadDb => PgServerStatus::Started,
         }
     }
 
@@ -91,6 +148,7 @@ impl ProcessStatus<PgServerStatus, Error> for PgProcessType {
             PgProcessType::InitDb => Error::PgInitFailure,
             PgProcessType::StartDb => Error::PgStartFailure,
             PgProcessType::StopDb => Error::PgStopFailure,
+            PgProcessType::ReloadDb => Error::PgReloadFailure,
         }
     }
 
@@ -113,6 +171,7 @@ impl fmt::Display for PgProcessType {
             PgProcessType::InitDb => write!(f, "initdb"),
             PgProcessType::StartDb => write!(f, "start"),
             PgProcessType::StopDb => write!(f, "stop"),
+            PgProcessType::ReloadDb => write!(f, "reload"),
         }
     }
 }
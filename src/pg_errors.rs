@@ -19,6 +19,15 @@ pub enum Error {
     #[error("Invalid PostgreSQL binaries download URL.")]
     InvalidPgUrl,
 
+    /// The combination of settings passed to
+    /// [`crate::postgres::PgEmbed::new`] cannot work.
+    ///
+    /// Raised before anything is downloaded or started, e.g. for
+    /// [`crate::pg_enums::PgAuthMethod::ScramSha256`] with PostgreSQL 9.x.
+    /// The inner string describes the conflict.
+    #[error("Invalid settings: {0}")]
+    InvalidSettings(String),
+
     /// The downloaded file is not a valid PostgreSQL binaries package.
     ///
     /// Raised when the ZIP archive cannot be opened or does not contain the
@@ -26,6 +35,15 @@ pub enum Error {
     #[error("Invalid PostgreSQL binaries package.")]
     InvalidPgPackage,
 
+    /// An existing PostgreSQL installation could not be used.
+    ///
+    /// Raised for [`crate::pg_fetch::PgBinarySource::Directory`] and
+    /// [`crate::pg_fetch::PgBinarySource::PgConfig`] when the executables
+    /// cannot be found or `postgres --version` cannot be run.  The inner
+    /// string describes what was looked for and where.
+    #[error("PostgreSQL binaries not found: {0}")]
+    BinariesNotFound(String),
+
     /// A file write operation failed.
     ///
     /// The inner string is the OS error message (e.g. `Permission denied`).
@@ -48,6 +66,58 @@ pub enum Error {
     #[error("Failed to unpack PostgreSQL binaries.")]
     UnpackFailure,
 
+    /// An archive entry has an absolute path or a `..` component, or would
+    /// replace a symlink extracted before it.
+    ///
+    /// Raised by hardened extraction (see
+    /// [`crate::pg_unpack::ExtractionPolicy`]).  The inner string is the
+    /// entry's path.
+    #[error("Archive entry escapes the destination directory: {0}")]
+    ArchivePathTraversal(String),
+
+    /// A symlink or hardlink in an archive points outside the destination
+    /// directory.
+    ///
+    /// Raised by hardened extraction (see
+    /// [`crate::pg_unpack::ExtractionPolicy`]).
+    #[error("Archive link {entry} points outside the destination directory: {target}")]
+    ArchiveLinkEscape {
+        /// The link's path.
+        entry: String,
+        /// The link target as stored in the archive.
+        target: String,
+    },
+
+    /// An archive entry is a device node or FIFO.
+    ///
+    /// Raised by hardened extraction (see
+    /// [`crate::pg_unpack::ExtractionPolicy`]).  The inner string is the
+    /// entry's path.
+    #[error("Archive entry is a device node or FIFO: {0}")]
+    ArchiveSpecialFile(String),
+
+    /// An archive entry is larger than
+    /// [`crate::pg_unpack::ExtractionPolicy::max_entry_size`].
+    #[error("Archive entry {entry} is {size} bytes, more than the limit of {limit}")]
+    ArchiveEntryTooLarge {
+        /// The entry's path.
+        entry: String,
+        /// The entry's size in bytes.
+        size: u64,
+        /// The configured limit in bytes.
+        limit: u64,
+    },
+
+    /// An archive has more entries than
+    /// [`crate::pg_unpack::ExtractionPolicy::max_entries`].
+    #[error("Archive has more than {limit} entries; rejected at {entry}")]
+    ArchiveTooManyEntries {
+        /// The first entry over the limit.
+        entry: String,
+        /// The configured limit.
+        limit: u64,
+    },
+
     /// `pg_ctl start` exited with a non-zero status.
     #[error("PostgreSQL could not be started.")]
     PgStartFailure,
@@ -56,10 +126,32 @@ pub enum Error {
     #[error("PostgreSQL could not be stopped.")]
     PgStopFailure,
 
+    /// `pg_ctl reload` exited with a non-zero status.
+    #[error("PostgreSQL could not reload its configuration.")]
+    PgReloadFailure,
+
+    /// A `postgres` process started with
+    /// [`crate::pg_enums::StartMode::Supervised`] exited before it accepted
+    /// connections.
+    #[error("PostgreSQL exited during startup with code {code:?}:\n{log_tail}")]
+    PgExited {
+        /// Exit code, or `None` if the process was killed by a signal.
+        code: Option<i32>,
+        /// The last output lines of the server.
+        log_tail: String,
+    },
+
     /// `initdb` exited with a non-zero status.
     #[error("PostgreSQL could not be initialized.")]
     PgInitFailure,
 
+    /// One or more preflight checks failed; see
+    /// [`crate::pg_preflight::PreflightReport::ensure_ok`].
+    ///
+    /// The inner string names each failed check and what it found.
+    #[error("Preflight checks failed: {0}")]
+    PreflightFailed(String),
+
     /// Removal of the database directory or password file failed.
     ///
     /// The inner string is the OS error message.
@@ -80,6 +172,13 @@ pub enum Error {
     #[error("Lock error.")]
     PgLockError,
 
+    /// Another process held the binary cache lock for longer than
+    /// [`crate::pg_fetch::PgFetchSettings::cache_lock_timeout`].
+    ///
+    /// The inner string names the lock file and the time waited.
+    #[error("Timed out waiting for the binary cache lock: {0}")]
+    CacheLockTimeout(String),
+
     /// Spawning or waiting on a child process failed.
     #[error("Child process error.")]
     PgProcessError,
@@ -103,6 +202,15 @@ pub enum Error {
     #[error("PgError: {0}, {1}")]
     PgError(String, String),
 
+    /// The HTTP client configuration is invalid.
+    ///
+    /// Raised for unreadable or malformed root certificate files, invalid
+    /// proxy URLs and other settings in
+    /// [`crate::pg_http::HttpClientConfig`].  The inner string describes the
+    /// problem.
+    #[error("Invalid HTTP client configuration: {0}")]
+    InvalidHttpConfig(String),
+
     /// The HTTP download of the PostgreSQL binaries JAR failed.
     ///
     /// The inner string is the `reqwest` error message.
@@ -115,6 +223,58 @@ pub enum Error {
     #[error("Request response bytes conversion failure: {0}")]
     ConversionFailure(String),
 
+    /// The binaries are not in the cache and downloading them is not allowed.
+    ///
+    /// Raised instead of a download when offline mode is enabled (see
+    /// [`crate::pg_fetch::PgFetchSettings::offline`]) or pg-embed was built
+    /// without the `download` feature.  Seed the cache with
+    /// [`crate::pg_bundle::import_bundle`] or a run with network access.
+    #[error("PostgreSQL {version} for {platform} is not in the binary cache at {cache_dir} and downloads are disabled: {reason}")]
+    OfflineCacheMiss {
+        /// The requested PostgreSQL version.
+        version: String,
+        /// Maven platform classifier, e.g. `linux-amd64`.
+        platform: String,
+        /// The version cache directory that was checked.
+        cache_dir: String,
+        /// Why downloads are disabled.
+        reason: String,
+    },
+
+    /// The downloaded binaries do not match their expected digest.
+    ///
+    /// Both fields are formatted as `{algorithm}:{hex digest}`.  `expected`
+    /// comes from [`crate::pg_fetch::PgFetchSettings::pinned_checksum`] or
+    /// from a Maven `.sha256`/`.sha1`/`.md5` sidecar file.
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        /// The expected digest.
+        expected: String,
+        /// The digest of the downloaded file.
+        actual: String,
+    },
+
+    /// A PostgreSQL version string is not in `MAJOR.MINOR.PATCH` form.
+    ///
+    /// The inner string is the rejected input.
+    #[error("Invalid PostgreSQL version: {0}")]
+    InvalidPostgresVersion(String),
+
+    /// A PostgreSQL version requirement could not be parsed.
+    ///
+    /// The inner string is the rejected requirement.  See
+    /// [`crate::pg_resolve::VersionRequirement`] for the accepted forms.
+    #[error("Invalid PostgreSQL version requirement: {0}")]
+    InvalidVersionRequirement(String),
+
+    /// No published PostgreSQL version satisfies a requirement.
+    ///
+    /// Also raised when `maven-metadata.xml` cannot be fetched and no earlier
+    /// result is cached on disk.  The inner string describes the requirement
+    /// and the platform.
+    #[error("No matching PostgreSQL version: {0}")]
+    VersionNotFound(String),
+
     /// An internal MPSC channel send failed because the receiver was dropped.
     #[error("Channel send error.")]
     SendFailure,
@@ -3,42 +3,177 @@
 //! The [`PgFetchSettings`] struct describes *which* binary to fetch (OS,
 //! architecture, version) and exposes [`PgFetchSettings::fetch_postgres`] to
 //! perform the actual HTTP download.  The downloaded bytes are a JAR file
-//! (ZIP) that is later unpacked by [`crate::pg_unpack`].
+//! (ZIP) that is later unpacked by [`crate::pg_unpack`].  Other hosts and
+//! archive formats can be used through [`PgFetchSettings::url_template`].
+//!
+//! Every download is verified against the `.sha256`/`.sha1`/`.md5` sidecar
+//! files Maven publishes next to the artifact and, if set, against
+//! [`PgFetchSettings::pinned_checksum`].
+//!
+//! Downloads need the `download` feature (enabled by `rt_tokio` and
+//! `rt_tokio_migrate`).  With [`PgFetchSettings::offline`], the
+//! [`PG_EMBED_OFFLINE_ENV`] environment variable, or without that feature,
+//! nothing is downloaded and binaries missing from the cache are reported as
+//! [`Error::OfflineCacheMiss`].
 
-use std::path::Path;
+use std::ffi::OsString;
+use std::fmt;
+#[cfg(feature = "download")]
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
-use tokio::io::AsyncWriteExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "download")]
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 
+use crate::pg_checksum::{to_hex, BundleDigests, Checksum};
+#[cfg(feature = "download")]
+use crate::pg_checksum::{BundleHasher, ChecksumAlgorithm};
+use crate::pg_embedded::EmbeddedBundle;
 use crate::pg_enums::{Architecture, OperationSystem};
 use crate::pg_errors::Error;
 use crate::pg_errors::Result;
+#[cfg(feature = "download")]
+use crate::pg_http::HttpClientConfig;
+use crate::pg_platform::Platform;
+use crate::pg_progress::Progress;
+#[cfg(feature = "download")]
+use crate::pg_progress::ProgressEvent;
+use crate::pg_unpack::{BundleFormat, ExtractionPolicy, UnpackOptions};
 
-/// A PostgreSQL version string in `MAJOR.MINOR.PATCH` form.
+/// A PostgreSQL version in `MAJOR.MINOR.PATCH` form.
+///
+/// Use one of the provided constants ([`PG_V17`], [`PG_V16`], …), parse one
+/// from configuration with [`str::parse`], or resolve one at run time with
+/// [`crate::pg_resolve::resolve_version`]:
+///
+/// ```rust
+/// use pg_embed::pg_fetch::{PostgresVersion, PG_V17};
 ///
-/// Use one of the provided constants ([`PG_V17`], [`PG_V16`], …) rather than
-/// constructing this type directly.
-#[derive(Debug, Copy, Clone)]
-pub struct PostgresVersion(pub &'static str);
+/// let version: PostgresVersion = "17.8.0".parse().unwrap();
+/// assert_eq!(version, PG_V17);
+/// assert_eq!(version.major(), 17);
+/// assert!(version > "16.12.0".parse().unwrap());
+/// ```
+///
+/// Versions order numerically and are serialised as their `Display` string
+/// (`"17.8.0"`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PostgresVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
 
+impl PostgresVersion {
+    /// Creates a version from its components.
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        PostgresVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Returns the major version, e.g. `17` for `17.8.0`.
+    pub const fn major(&self) -> u32 {
+        self.major
+    }
+
+    /// Returns the minor version, e.g. `8` for `17.8.0`.
+    pub const fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    /// Returns the patch version, e.g. `0` for `17.8.0`.
+    pub const fn patch(&self) -> u32 {
+        self.patch
+    }
+}
+
+impl fmt::Display for PostgresVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for PostgresVersion {
+    type Err = Error;
+
+    /// Parses a `MAJOR.MINOR.PATCH` string.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPostgresVersion`] unless `s` consists of
+    /// exactly three dot-separated numbers.  Pre-releases such as
+    /// `10.0.0-beta` are rejected.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidPostgresVersion(s.to_string());
+        let mut parts = s.trim().split('.').map(|p| p.parse::<u32>().map_err(|_| invalid()));
+        let (Some(major), Some(minor), Some(patch), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        Ok(PostgresVersion::new(major?, minor?, patch?))
+    }
+}
+
+impl Serialize for PostgresVersion {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PostgresVersion {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
 
 /// PostgreSQL 18.2.0 binaries.
-pub const PG_V18: PostgresVersion = PostgresVersion("18.2.0");
+pub const PG_V18: PostgresVersion = PostgresVersion::new(18, 2, 0);
 /// PostgreSQL 17.8.0 binaries.
-pub const PG_V17: PostgresVersion = PostgresVersion("17.8.0");
+pub const PG_V17: PostgresVersion = PostgresVersion::new(17, 8, 0);
 /// PostgreSQL 16.12.0 binaries.
-pub const PG_V16: PostgresVersion = PostgresVersion("16.12.0");
+pub const PG_V16: PostgresVersion = PostgresVersion::new(16, 12, 0);
 /// PostgreSQL 15.16.0 binaries.
-pub const PG_V15: PostgresVersion = PostgresVersion("15.16.0");
+pub const PG_V15: PostgresVersion = PostgresVersion::new(15, 16, 0);
 /// PostgreSQL 14.21.0 binaries.
-pub const PG_V14: PostgresVersion = PostgresVersion("14.21.0");
+pub const PG_V14: PostgresVersion = PostgresVersion::new(14, 21, 0);
 /// PostgreSQL 13.23.0 binaries.
-pub const PG_V13: PostgresVersion = PostgresVersion("13.23.0");
+pub const PG_V13: PostgresVersion = PostgresVersion::new(13, 23, 0);
 /// PostgreSQL 12.22.0 binaries.
-pub const PG_V12: PostgresVersion = PostgresVersion("12.22.0");
+pub const PG_V12: PostgresVersion = PostgresVersion::new(12, 22, 0);
 /// PostgreSQL 11.22.1 binaries.
-pub const PG_V11: PostgresVersion = PostgresVersion("11.22.1");
+pub const PG_V11: PostgresVersion = PostgresVersion::new(11, 22, 1);
 /// PostgreSQL 10.23.0 binaries.
-pub const PG_V10: PostgresVersion = PostgresVersion("10.23.0");
+pub const PG_V10: PostgresVersion = PostgresVersion::new(10, 23, 0);
+
+/// Where the PostgreSQL binaries come from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum PgBinarySource {
+    /// Download the binaries from [`PgFetchSettings::hosts`] into the cache.
+    #[default]
+    Download,
+    /// Use an existing installation instead of downloading.
+    ///
+    /// The path may be the installation prefix (e.g. `/usr/lib/postgresql/16`)
+    /// or its `bin` directory.  No network access takes place.
+    Directory(PathBuf),
+    /// Use the installation whose `pg_config` is first on `PATH`, as reported
+    /// by `pg_config --bindir`.  No network access takes place.
+    PgConfig,
+    /// Extract binaries embedded into the executable at build time into the
+    /// cache; see [`crate::pg_embedded`].  No network access takes place.
+    Embedded(EmbeddedBundle),
+}
 
 /// Settings that determine which PostgreSQL binary package to download.
 ///
@@ -54,15 +189,17 @@ pub const PG_V10: PostgresVersion = PostgresVersion("10.23.0");
 /// };
 /// ```
 ///
-/// The default target OS and architecture are detected at compile time via
-/// `#[cfg(target_os)]` / `#[cfg(target_arch)]`.
+/// The default target OS and architecture are those of the running system,
+/// including the C library (see [`Platform::current`]), so a glibc build in
+/// an Alpine container still fetches the musl bundle.
 #[derive(Debug, Clone)]
 pub struct PgFetchSettings {
-    /// Base URL of the Maven repository hosting the binaries.
+    /// Base URLs of the Maven repositories hosting the binaries, tried in
+    /// order.
     ///
-    /// Defaults to `https://repo1.maven.org`.  Override to point at a local
-    /// mirror or artifact proxy.
-    pub host: String,
+    /// Defaults to `["https://repo1.maven.org"]`.  Override to point at a
+    /// local mirror or artifact proxy, optionally followed by fallbacks.
+    pub hosts: Vec<String>,
     /// Target operating system.  Determines the package classifier used in the
     /// Maven artifact name.
     pub operating_system: OperationSystem,
@@ -71,15 +208,131 @@ pub struct PgFetchSettings {
     pub architecture: Architecture,
     /// PostgreSQL version to download.  Use one of the `PG_Vxx` constants.
     pub version: PostgresVersion,
+    /// Known-good digest of the bundle.
+    ///
+    /// When `Some`, the download is rejected with
+    /// [`Error::ChecksumMismatch`] unless it matches, regardless of what the
+    /// repository's sidecar files say.  Useful for pinning a bundle that has
+    /// been reviewed once.  Defaults to `None`.
+    pub pinned_checksum: Option<Checksum>,
+    /// Maximum time to wait for another process that is acquiring the same
+    /// binaries into the shared cache.
+    ///
+    /// `None` waits indefinitely.  Exceeding the timeout returns
+    /// [`Error::CacheLockTimeout`].  Defaults to 10 minutes.
+    pub cache_lock_timeout: Option<Duration>,
+    /// Where the binaries come from.  Defaults to
+    /// [`PgBinarySource::Download`].
+    ///
+    /// An [`PgBinarySource::Embedded`] bundle must match [`Self::version`]
+    /// and the platform.  For the other sources [`Self::operating_system`],
+    /// [`Self::architecture`] and [`Self::version`] are ignored; the version
+    /// is detected from the installation instead (see
+    /// [`crate::pg_access::PgAccess::detected_version`]).
+    pub binary_source: PgBinarySource,
+    /// How transient download failures are retried on each host.
+    pub retry_policy: RetryPolicy,
+    /// HTTP client configuration: credentials, headers, root certificates,
+    /// proxy and timeouts, or a pre-built client.
+    ///
+    /// Only available with the `download` feature.
+    #[cfg(feature = "download")]
+    pub http: HttpClientConfig,
+    /// Receives progress events while the binaries are downloaded and
+    /// unpacked.  Defaults to reporting nothing.
+    pub progress: Progress,
+    /// Checks and limits applied to every archive entry when the binaries
+    /// are unpacked or a bundle is imported.  Hardened by default.
+    pub extraction: ExtractionPolicy,
+    /// URL of the bundle, with placeholders expanded per host.
+    ///
+    /// `None` uses the zonky layout on Maven:
+    /// `{host}/maven2/io/zonky/test/postgres/embedded-postgres-binaries-{platform}/{version}/embedded-postgres-binaries-{platform}-{version}.jar`.
+    /// The placeholders are `{host}`, `{version}` (e.g. `17.2.0`),
+    /// `{major}`, `{os}` (e.g. `linux`), `{arch}` (e.g. `amd64`),
+    /// `{platform}` (see [`Self::platform`]) and `{target}` (see
+    /// [`Self::target_triple`]).  Checksum sidecars are looked up next to the
+    /// expanded URL.  Version requirements (see
+    /// [`crate::pg_resolve`]) can only be resolved against the Maven layout.
+    pub url_template: Option<String>,
+    /// Format of the bundle.  `None` detects it from the file's first
+    /// bytes.
+    pub bundle_format: Option<BundleFormat>,
+    /// Leading path components to remove from every archive entry, like
+    /// `tar --strip-components`.  Set to `1` for archives that wrap
+    /// everything in a single top-level directory.  Defaults to `0`.
+    pub strip_components: usize,
+    /// Root of the binary cache.
+    ///
+    /// `None` uses the `PG_EMBED_CACHE_DIR` environment variable if it is set
+    /// and otherwise `pg-embed` in the OS cache directory; see
+    /// [`crate::pg_access::PgAccess::cache_root`].
+    pub cache_dir: Option<PathBuf>,
+    /// How binaries from different sources are kept apart in the cache.
+    /// Defaults to [`CacheKey::Source`].
+    pub cache_key: CacheKey,
+    /// Never access the network.
+    ///
+    /// Binaries must already be in the cache (see
+    /// [`crate::pg_bundle::import_bundle`]) or come from an existing
+    /// installation; otherwise acquiring them fails with
+    /// [`Error::OfflineCacheMiss`].  Version requirements only resolve
+    /// against version lists cached earlier.  Also enabled by the
+    /// [`PG_EMBED_OFFLINE_ENV`] environment variable.  Defaults to `false`.
+    pub offline: bool,
+}
+
+/// Environment variable that enables offline mode for every
+/// [`PgFetchSettings`], e.g. `PG_EMBED_OFFLINE=1`.
+///
+/// Any value except an empty string, `0`, `false`, `no` and `off` (in any
+/// case) counts as enabled.
+pub const PG_EMBED_OFFLINE_ENV: &str = "PG_EMBED_OFFLINE";
+
+/// How the per-version cache directory is named.
+///
+/// The directory is `{os}/{arch}/{version}` below the cache root, optionally
+/// followed by `-{tag}`, so that bundles from different mirrors or custom
+/// builds of the same version never overwrite each other.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CacheKey {
+    /// Tag the directory with the download source: the pinned checksum if
+    /// [`PgFetchSettings::pinned_checksum`] is set (e.g.
+    /// `17.2.0-sha256-3f1c2a9b7d40`), otherwise a hash of
+    /// [`PgFetchSettings::hosts`] and [`PgFetchSettings::url_template`] (e.g.
+    /// `17.2.0-src-91ad03c4e5f2`).  An embedded bundle is tagged with its
+    /// SHA-256 digest.  The zonky bundles on Maven Central are not tagged, so
+    /// caches of earlier releases stay valid.
+    #[default]
+    Source,
+    /// Never tag the directory: every source shares `{version}`.
+    Version,
+    /// Tag the directory with a fixed name, e.g. `17.2.0-corp`.  The name may
+    /// only contain ASCII letters, digits, `.`, `_` and `-`.
+    Custom(String),
 }
 
 impl Default for PgFetchSettings {
     fn default() -> Self {
         PgFetchSettings {
-            host: "https://repo1.maven.org".to_string(),
-            operating_system: OperationSystem::default(),
-            architecture: Architecture::default(),
+            hosts: vec!["https://repo1.maven.org".to_string()],
+            operating_system: Platform::current().operating_system,
+            architecture: Platform::current().architecture,
             version: PG_V18,
+            pinned_checksum: None,
+            cache_lock_timeout: Some(Duration::from_secs(600)),
+            binary_source: PgBinarySource::Download,
+            retry_policy: RetryPolicy::default(),
+            #[cfg(feature = "download")]
+            http: HttpClientConfig::default(),
+            progress: Progress::default(),
+            extraction: ExtractionPolicy::default(),
+            url_template: None,
+            bundle_format: None,
+            strip_components: 0,
+            cache_dir: None,
+            cache_key: CacheKey::default(),
+            offline: false,
         }
     }
 }
@@ -105,49 +358,475 @@ impl PgFetchSettings {
         format!("{}-{}", os, arch)
     }
 
-    /// Initiates an HTTP GET for the Maven artifact and checks the response status.
+    /// Returns the Rust target triple for this OS/architecture combination,
+    /// e.g. `x86_64-unknown-linux-gnu` or `aarch64-apple-darwin`.
+    ///
+    /// Used for the `{target}` placeholder of [`Self::url_template`], which
+    /// matches the naming of e.g. the theseus-rs `postgresql-binaries`
+    /// releases.
+    pub fn target_triple(&self) -> String {
+        let arch = match self.architecture {
+            Architecture::Amd64 => "x86_64",
+            Architecture::I386 => "i686",
+            Architecture::Arm32v6 => "arm",
+            Architecture::Arm32v7 => "armv7",
+            Architecture::Arm64v8 => "aarch64",
+            Architecture::Ppc64le => "powerpc64le",
+        };
+        let system = match (self.operating_system, self.architecture) {
+            (OperationSystem::Darwin, _) => "apple-darwin",
+            (OperationSystem::Windows, _) => "pc-windows-msvc",
+            (OperationSystem::Linux, Architecture::Arm32v6 | Architecture::Arm32v7) => {
+                "unknown-linux-gnueabihf"
+            }
+            (OperationSystem::Linux, _) => "unknown-linux-gnu",
+            (OperationSystem::AlpineLinux, Architecture::Arm32v6 | Architecture::Arm32v7) => {
+                "unknown-linux-musleabihf"
+            }
+            (OperationSystem::AlpineLinux, _) => "unknown-linux-musl",
+        };
+        format!("{}-{}", arch, system)
+    }
+
+    /// Checks that the settings can be used for a download.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSettings`] if [`Self::url_template`] has an
+    /// unknown or unterminated placeholder, a [`CacheKey::Custom`] name is
+    /// empty or not a plain file name, or an [`PgBinarySource::Embedded`]
+    /// bundle was built for another version or platform.
+    pub fn validate(&self) -> Result<()> {
+        if let PgBinarySource::Embedded(bundle) = &self.binary_source {
+            let metadata = bundle.metadata()?;
+            if metadata.version != self.version || metadata.platform != self.platform() {
+                return Err(Error::InvalidSettings(format!(
+                    "the embedded bundle holds PostgreSQL {} for {}, not {} for {}",
+                    metadata.version,
+                    metadata.platform,
+                    self.version,
+                    self.platform()
+                )));
+            }
+        }
+        if let CacheKey::Custom(name) = &self.cache_key {
+            let plain = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+            if name.is_empty() || name.starts_with('.') || !name.chars().all(plain) {
+                return Err(Error::InvalidSettings(format!(
+                    "invalid cache key {:?}: use ASCII letters, digits, '.', '_' and '-'",
+                    name
+                )));
+            }
+        }
+        if let Some(template) = &self.url_template {
+            let mut rest = template.as_str();
+            while let Some(start) = rest.find('{') {
+                let Some(len) = rest[start..].find('}') else {
+                    return Err(Error::InvalidSettings(format!(
+                        "unterminated placeholder in url_template {}",
+                        template
+                    )));
+                };
+                let name = &rest[start + 1..start + len];
+                if self.placeholder(name, "").is_none() {
+                    return Err(Error::InvalidSettings(format!(
+                        "unknown placeholder {{{}}} in url_template {}",
+                        name, template
+                    )));
+                }
+                rest = &rest[start + len + 1..];
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the tag appended to the version directory in the cache, if
+    /// any; see [`CacheKey`].
+    pub(crate) fn cache_tag(&self) -> Option<String> {
+        match &self.cache_key {
+            CacheKey::Version => None,
+            CacheKey::Custom(name) => Some(name.clone()),
+            CacheKey::Source => {
+                if let PgBinarySource::Embedded(bundle) = &self.binary_source {
+                    let sha256 = bundle.metadata().ok()?.sha256;
+                    return Some(format!("sha256-{}", &sha256[..sha256.len().min(12)]));
+                }
+                if let Some(pinned) = &self.pinned_checksum {
+                    return Some(format!(
+                        "{}-{}",
+                        pinned.algorithm,
+                        &pinned.hex[..pinned.hex.len().min(12)]
+                    ));
+                }
+                let default = PgFetchSettings::default();
+                if self.hosts == default.hosts && self.url_template.is_none() {
+                    return None;
+                }
+                let mut hasher = Sha256::new();
+                for host in &self.hosts {
+                    hasher.update(host.as_bytes());
+                    hasher.update(b"\n");
+                }
+                if let Some(template) = &self.url_template {
+                    hasher.update(template.as_bytes());
+                }
+                Some(format!("src-{}", &to_hex(&hasher.finalize())[..12]))
+            }
+        }
+    }
+
+    /// Returns the value of `{name}` in [`Self::url_template`].
+    fn placeholder(&self, name: &str, host: &str) -> Option<String> {
+        Some(match name {
+            "host" => host.to_string(),
+            "version" => self.version.to_string(),
+            "major" => self.version.major().to_string(),
+            "os" => self.operating_system.to_string(),
+            "arch" => self.architecture.to_string(),
+            "platform" => self.platform(),
+            "target" => self.target_triple(),
+            _ => return None,
+        })
+    }
+
+    /// Returns the full URL of the bundle for this platform and version on
+    /// `host`, expanding [`Self::url_template`] if set.
+    ///
+    /// Unknown placeholders are left as they are; [`Self::validate`] rejects
+    /// them before a download starts.
+    #[cfg(feature = "download")]
+    pub(crate) fn artifact_url(&self, host: &str) -> String {
+        let Some(template) = &self.url_template else {
+            let platform = self.platform();
+            return format!(
+                "{}/maven2/io/zonky/test/postgres/embedded-postgres-binaries-{}/{}/embedded-postgres-binaries-{}-{}.jar",
+                host,
+                &platform,
+                self.version,
+                &platform,
+                self.version
+            );
+        };
+        let mut url = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            url.push_str(&rest[..start]);
+            let value = rest[start..].find('}').and_then(|len| {
+                let value = self.placeholder(&rest[start + 1..start + len], host)?;
+                Some((value, len))
+            });
+            match value {
+                Some((value, len)) => {
+                    url.push_str(&value);
+                    rest = &rest[start + len + 1..];
+                }
+                None => {
+                    url.push('{');
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        url.push_str(rest);
+        url
+    }
+
+    /// Returns the [`UnpackOptions`] matching these settings.
+    pub(crate) fn unpack_options(&self) -> UnpackOptions {
+        UnpackOptions {
+            progress: self.progress.clone(),
+            extraction: self.extraction,
+            format: self.bundle_format,
+            strip_components: self.strip_components,
+        }
+    }
+
+    /// Returns `true` if offline mode is enabled by [`Self::offline`] or the
+    /// [`PG_EMBED_OFFLINE_ENV`] environment variable.
+    pub fn is_offline(&self) -> bool {
+        self.offline || offline_from_env(std::env::var_os(PG_EMBED_OFFLINE_ENV))
+    }
+
+    /// Returns why nothing may be downloaded, or `None` if downloads are
+    /// allowed.
+    pub(crate) fn downloads_disabled(&self) -> Option<&'static str> {
+        if !cfg!(feature = "download") {
+            Some("pg-embed was built without the `download` feature")
+        } else if self.offline {
+            Some("offline mode is enabled in PgFetchSettings")
+        } else if self.is_offline() {
+            Some("offline mode is enabled by PG_EMBED_OFFLINE")
+        } else {
+            None
+        }
+    }
+}
+
+/// Interprets the value of [`PG_EMBED_OFFLINE_ENV`].
+fn offline_from_env(value: Option<OsString>) -> bool {
+    let Some(value) = value else {
+        return false;
+    };
+    let value = value.to_string_lossy().trim().to_ascii_lowercase();
+    !matches!(value.as_str(), "" | "0" | "false" | "no" | "off")
+}
+
+#[cfg(feature = "download")]
+impl PgFetchSettings {
+    /// Sends a GET for `url`, retrying transient failures according to
+    /// [`Self::retry_policy`].
     ///
-    /// Constructs the full artifact URL from [`Self::host`], [`Self::platform`],
-    /// and [`Self::version`] and issues the request.  The caller streams the
-    /// response body.
+    /// Used for small documents (sidecars, metadata) that are not worth
+    /// resuming.
     ///
     /// # Errors
     ///
-    /// Returns [`Error::DownloadFailure`] if the request fails or the server
-    /// returns a non-2xx status.
-    async fn start_download(&self) -> Result<reqwest::Response> {
-        let platform = self.platform();
-        let version = self.version.0;
-        let download_url = format!(
-            "{}/maven2/io/zonky/test/postgres/embedded-postgres-binaries-{}/{}/embedded-postgres-binaries-{}-{}.jar",
-            &self.host,
-            &platform,
-            version,
-            &platform,
-            version
+    /// Returns [`Error::DownloadFailure`] if the request still fails after
+    /// the last attempt.  Non-success statuses that are not transient (e.g.
+    /// 404) are returned as a successful [`reqwest::Response`] for the caller
+    /// to inspect.
+    pub(crate) async fn get_with_retry(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+    ) -> Result<reqwest::Response> {
+        let mut attempt = 1;
+        loop {
+            let message = match self.http.get(client, url).send().await {
+                Ok(response) if is_transient_status(response.status()) => {
+                    format!("HTTP {} fetching {}", response.status(), url)
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_builder() => return Err(Error::DownloadFailure(e.to_string())),
+                Err(e) => e.to_string(),
+            };
+            if attempt >= self.retry_policy.max_attempts {
+                return Err(Error::DownloadFailure(message));
+            }
+            self.wait_before_retry(attempt, &message).await;
+            attempt += 1;
+        }
+    }
+
+    /// Logs a transient failure and sleeps for the backoff after `attempt`.
+    async fn wait_before_retry(&self, attempt: u32, message: &str) {
+        let delay = self.retry_policy.backoff(attempt);
+        log::warn!(
+            "{} (attempt {}/{}); retrying in {:?}",
+            message,
+            attempt,
+            self.retry_policy.max_attempts,
+            delay
         );
+        tokio::time::sleep(delay).await;
+    }
 
-        let response = reqwest::get(download_url)
-            .await
-            .map_err(|e| Error::DownloadFailure(e.to_string()))?;
+    /// Fetches the checksum sidecar files published next to `artifact_url`.
+    ///
+    /// Each of `{artifact_url}.sha256`, `.sha1` and `.md5` is requested in
+    /// turn.  Sidecars that do not exist (non-2xx status) or whose body is not
+    /// a well-formed digest are skipped, so the result may be empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DownloadFailure`] if a request cannot be sent or its
+    /// body cannot be read.
+    async fn fetch_sidecar_checksums(
+        &self,
+        client: &reqwest::Client,
+        artifact_url: &str,
+    ) -> Result<Vec<Checksum>> {
+        let mut checksums = Vec::with_capacity(ChecksumAlgorithm::ALL.len());
+        for algorithm in ChecksumAlgorithm::ALL {
+            let url = format!("{}.{}", artifact_url, algorithm.extension());
+            let response = self.get_with_retry(client, &url).await?;
+            if !response.status().is_success() {
+                log::debug!("No {} sidecar at {} (HTTP {})", algorithm, url, response.status());
+                continue;
+            }
+            let body = response
+                .text()
+                .await
+                .map_err(|e| Error::DownloadFailure(e.to_string()))?;
+            match Checksum::parse_sidecar(algorithm, &body) {
+                Some(checksum) => checksums.push(checksum),
+                None => log::warn!("Ignoring malformed {} sidecar at {}", algorithm, url),
+            }
+        }
+        Ok(checksums)
+    }
+
+    /// Verifies `digests` against the pinned checksum and the sidecars
+    /// published next to `artifact_url`.
+    ///
+    /// The pinned checksum (if any) is checked first, then every sidecar that
+    /// could be fetched.  If neither is available a warning is logged and the
+    /// download is accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChecksumMismatch`] on the first digest that does not
+    /// match.
+    /// Returns [`Error::DownloadFailure`] if the sidecar files cannot be
+    /// fetched.
+    async fn verify_download(
+        &self,
+        client: &reqwest::Client,
+        artifact_url: &str,
+        digests: &BundleDigests,
+    ) -> Result<()> {
+        if let Some(pinned) = &self.pinned_checksum {
+            digests.verify(pinned)?;
+        }
+        let sidecars = self.fetch_sidecar_checksums(client, artifact_url).await?;
+        for checksum in &sidecars {
+            digests.verify(checksum)?;
+        }
+        if sidecars.is_empty() && self.pinned_checksum.is_none() {
+            log::warn!(
+                "No checksum published for {}; the download could not be verified",
+                artifact_url
+            );
+        }
+        Ok(())
+    }
+
+    /// Downloads the artifact into `download`, trying each of [`Self::hosts`]
+    /// in turn.
+    ///
+    /// Each host gets [`RetryPolicy::max_attempts`] attempts.  An attempt
+    /// that fails after part of the body was received resumes with a
+    /// `Range` request; the hashes continue from the received bytes.
+    ///
+    /// # Returns
+    ///
+    /// The artifact URL the bundle was downloaded from.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last host if every host fails.
+    /// Returns [`Error::DownloadFailure`] if [`Self::hosts`] is empty.
+    async fn download(
+        &self,
+        client: &reqwest::Client,
+        download: &mut PartialDownload,
+    ) -> Result<String> {
+        self.validate()?;
+        let mut last_error = Error::DownloadFailure("no download hosts configured".into());
+        for host in &self.hosts {
+            let url = self.artifact_url(host);
+            download.restart().await?;
+            match self.download_from(client, &url, download).await {
+                Ok(()) => return Ok(url),
+                Err(e) => {
+                    log::warn!("Downloading {} failed: {}", url, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Downloads `url` into `download`, retrying and resuming transient
+    /// failures according to [`Self::retry_policy`].
+    async fn download_from(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        download: &mut PartialDownload,
+    ) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            match self.download_attempt(client, url, download).await {
+                Ok(()) => return Ok(()),
+                Err(Attempt::Fatal(e)) => return Err(e),
+                Err(Attempt::Transient(message)) => {
+                    if attempt >= self.retry_policy.max_attempts {
+                        return Err(Error::DownloadFailure(message));
+                    }
+                    self.wait_before_retry(attempt, &message).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Performs one request for `url`, resuming after the bytes already in
+    /// `download`.
+    async fn download_attempt(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        download: &mut PartialDownload,
+    ) -> std::result::Result<(), Attempt> {
+        let offset = download.written;
+        let mut request = self.http.get(client, url);
+        if offset > 0 {
+            log::info!("Resuming download of {} at byte {}", url, offset);
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send().await.map_err(|e| {
+            if e.is_builder() {
+                Attempt::Fatal(Error::DownloadFailure(e.to_string()))
+            } else {
+                Attempt::Transient(e.to_string())
+            }
+        })?;
 
         let status = response.status();
-        if !status.is_success() {
-            return Err(Error::DownloadFailure(format!(
-                "HTTP {status} fetching PostgreSQL {version} for platform '{platform}'. \
+        if status == reqwest::StatusCode::PARTIAL_CONTENT && offset > 0 {
+            if content_range_start(&response) != Some(offset) {
+                download.restart().await.map_err(Attempt::Fatal)?;
+                return Err(Attempt::Transient(format!(
+                    "unexpected Content-Range resuming {} at byte {}",
+                    url, offset
+                )));
+            }
+        } else if status.is_success() {
+            // The server ignored the Range header: start over.
+            download.restart().await.map_err(Attempt::Fatal)?;
+        } else if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            download.restart().await.map_err(Attempt::Fatal)?;
+            return Err(Attempt::Transient(format!("HTTP {status} resuming {url}")));
+        } else if is_transient_status(status) {
+            return Err(Attempt::Transient(format!("HTTP {status} fetching {url}")));
+        } else {
+            return Err(Attempt::Fatal(Error::DownloadFailure(format!(
+                "HTTP {status} fetching PostgreSQL {} for platform '{}'. \
                  This version may not be available for the current OS/architecture. \
                  Note: darwin-arm64v8 (Apple Silicon) only has binaries for PG 14 and newer.",
-            )));
+                self.version,
+                self.platform()
+            ))));
         }
 
-        Ok(response)
+        // For a resumed transfer Content-Length only covers the remainder.
+        let resumed_from = download.written;
+        let total = response.content_length().map(|len| len + resumed_from);
+        self.progress.emit(ProgressEvent::DownloadStarted {
+            url: url.to_string(),
+            content_length: total,
+            resumed_from,
+        });
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| Attempt::Transient(format!("reading {}: {}", url, e)))?
+        {
+            download.append(&chunk).await.map_err(Attempt::Fatal)?;
+            self.progress.emit(ProgressEvent::BytesReceived {
+                received: download.written,
+                total,
+            });
+        }
+        Ok(())
     }
 
     /// Downloads the PostgreSQL binaries JAR from Maven Central.
     ///
-    /// Constructs the full artifact URL from [`Self::host`], [`Self::platform`],
-    /// and [`Self::version`], performs an HTTP GET, and returns the raw bytes of
-    /// the JAR file.  The caller is responsible for persisting and unpacking the
+    /// Tries each of [`Self::hosts`] in turn with retries (see
+    /// [`Self::retry_policy`]), verifies the result (see
+    /// [`Self::fetch_postgres_to_file`]), and returns the raw bytes of the
+    /// JAR file.  The caller is responsible for persisting and unpacking the
     /// data (see [`crate::pg_unpack::unpack_postgres`]).
     ///
     /// Prefer [`Self::fetch_postgres_to_file`] when the bytes will be written
@@ -160,72 +839,642 @@ impl PgFetchSettings {
     ///
     /// # Errors
     ///
-    /// Returns [`Error::DownloadFailure`] if the HTTP request fails or the
-    /// server returns a non-2xx status (e.g. 404 when the requested
-    /// PostgreSQL version is not available for the current platform).
-    /// Returns [`Error::ConversionFailure`] if reading the response body fails.
+    /// Returns [`Error::DownloadFailure`] if every host fails, e.g. with a
+    /// non-2xx status such as 404 when the requested PostgreSQL version is not
+    /// available for the current platform, or with transient failures that
+    /// persist past the retry policy.
+    /// Returns [`Error::ChecksumMismatch`] if the bytes do not match the
+    /// pinned checksum or a published sidecar digest.
+    /// Returns [`Error::InvalidHttpConfig`] if [`Self::http`] is invalid.
     pub async fn fetch_postgres(&self) -> Result<Vec<u8>> {
-        let response = self.start_download().await?;
-        let content = response
-            .bytes()
-            .await
-            .map_err(|e| Error::ConversionFailure(e.to_string()))?;
+        let client = self.http.build_client()?;
+        let mut download = PartialDownload::new(DownloadSink::Memory(Vec::new()));
+        let url = self.download(&client, &mut download).await?;
+        self.progress.emit(ProgressEvent::DownloadFinished {
+            bytes: download.written,
+        });
+        let (sink, digests) = download.finish().await?;
+        let DownloadSink::Memory(content) = sink else {
+            unreachable!("in-memory download");
+        };
 
         log::debug!("Downloaded {} bytes", content.len());
         log::trace!(
             "First 1024 bytes: {:?}",
             &String::from_utf8_lossy(&content[..content.len().min(1024)])
         );
+        self.verify_download(&client, &url, &digests).await?;
 
-        Ok(content.to_vec())
+        Ok(content)
     }
 
     /// Downloads the PostgreSQL binaries JAR and streams it directly to `zip_path`.
     ///
     /// Unlike [`Self::fetch_postgres`], this method never loads the full archive
-    /// into memory — each HTTP chunk is written to the file as it arrives.
-    /// Use this method when you intend to write the JAR to disk (as
+    /// into memory — each HTTP chunk is hashed and written to the file as it
+    /// arrives.  Use this method when you intend to write the JAR to disk (as
     /// [`crate::pg_access::PgAccess`] does), since it avoids a 100–200 MB
     /// in-memory buffer.
     ///
+    /// Transient failures are retried and interrupted transfers resume where
+    /// they stopped; see [`Self::retry_policy`] and [`Self::hosts`].  Once the
+    /// transfer completes the digests are checked against
+    /// [`Self::pinned_checksum`] and the sidecar files of the host that served
+    /// the bundle.  On a mismatch the file is removed so it can never be
+    /// unpacked.
+    ///
     /// # Arguments
     ///
     /// * `zip_path` — Destination file path for the downloaded JAR.
     ///
+    /// # Returns
+    ///
+    /// The digests of the downloaded file and the URL it was downloaded from.
+    ///
     /// # Errors
     ///
-    /// Returns [`Error::DownloadFailure`] if the HTTP request fails or the
-    /// server returns a non-2xx status.
+    /// Returns [`Error::DownloadFailure`] if every host fails.
     /// Returns [`Error::WriteFileError`] if the file cannot be created or a
     /// chunk cannot be written.
-    /// Returns [`Error::ConversionFailure`] if reading a response chunk fails.
-    pub(crate) async fn fetch_postgres_to_file(&self, zip_path: &Path) -> Result<()> {
-        let mut response = self.start_download().await?;
-        let mut file = tokio::fs::File::create(zip_path)
+    /// Returns [`Error::ChecksumMismatch`] if the file does not match the
+    /// pinned checksum or a published sidecar digest.
+    /// Returns [`Error::InvalidHttpConfig`] if [`Self::http`] is invalid.
+    pub(crate) async fn fetch_postgres_to_file(
+        &self,
+        zip_path: &Path,
+    ) -> Result<(BundleDigests, String)> {
+        let client = self.http.build_client()?;
+        let file = tokio::fs::File::create(zip_path)
             .await
             .map_err(|e| Error::WriteFileError(e.to_string()))?;
-        let mut total = 0u64;
-        while let Some(chunk) = response
-            .chunk()
-            .await
-            .map_err(|e| Error::ConversionFailure(e.to_string()))?
-        {
-            file.write_all(&chunk)
+        let mut download = PartialDownload::new(DownloadSink::File(file));
+        let result = self.download(&client, &mut download).await;
+        let verified = match result {
+            Ok(url) => {
+                let total = download.written;
+                self.progress
+                    .emit(ProgressEvent::DownloadFinished { bytes: total });
+                let (_, digests) = download.finish().await?;
+                log::debug!("Downloaded and wrote {} bytes to disk", total);
+                self.verify_download(&client, &url, &digests)
+                    .await
+                    .map(|()| (digests, url))
+            }
+            Err(e) => Err(e),
+        };
+        if verified.is_err() {
+            let _ = tokio::fs::remove_file(zip_path).await;
+        }
+        verified
+    }
+}
+
+#[cfg(not(feature = "download"))]
+impl PgFetchSettings {
+    /// Stand-in for the download used without the `download` feature.
+    ///
+    /// Never reached: [`crate::pg_access::PgAccess::maybe_acquire_postgres`]
+    /// checks [`Self::downloads_disabled`] first.
+    ///
+    /// # Errors
+    ///
+    /// Always returns [`Error::DownloadFailure`].
+    pub(crate) async fn fetch_postgres_to_file(
+        &self,
+        _zip_path: &Path,
+    ) -> Result<(BundleDigests, String)> {
+        Err(Error::DownloadFailure(
+            "pg-embed was built without the `download` feature".into(),
+        ))
+    }
+}
+
+/// Limits how often and how quickly failed downloads are retried.
+///
+/// Connection errors, interrupted transfers, HTTP 5xx, 408 and 429 are
+/// retried on the same host; other HTTP errors (e.g. 404) move on to the
+/// next host in [`PgFetchSettings::hosts`] immediately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per host, including the first.  `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry.  Doubles for every further retry.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between retries.
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the delay after failed attempt number `attempt` (1-based).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+/// Returns `true` for statuses worth retrying on the same host.
+#[cfg(feature = "download")]
+fn is_transient_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error()
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Returns the start offset of a `Content-Range: bytes start-end/total`
+/// response header.
+#[cfg(feature = "download")]
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Outcome of a failed download attempt.
+#[cfg(feature = "download")]
+enum Attempt {
+    /// Worth retrying on the same host; carries a description.
+    Transient(String),
+    /// Retrying the same host will not help.
+    Fatal(Error),
+}
+
+/// Destination of a download.
+#[cfg(feature = "download")]
+enum DownloadSink {
+    File(tokio::fs::File),
+    Memory(Vec<u8>),
+}
+
+/// A download in progress: the bytes received so far and their hashes.
+#[cfg(feature = "download")]
+struct PartialDownload {
+    sink: DownloadSink,
+    hasher: BundleHasher,
+    written: u64,
+}
+
+#[cfg(feature = "download")]
+impl PartialDownload {
+    fn new(sink: DownloadSink) -> Self {
+        PartialDownload {
+            sink,
+            hasher: BundleHasher::new(),
+            written: 0,
+        }
+    }
+
+    /// Appends `chunk` to the sink and the hashes.
+    async fn append(&mut self, chunk: &[u8]) -> Result<()> {
+        match &mut self.sink {
+            DownloadSink::File(file) => file
+                .write_all(chunk)
                 .await
-                .map_err(|e| Error::WriteFileError(e.to_string()))?;
-            total += chunk.len() as u64;
+                .map_err(|e| Error::WriteFileError(e.to_string()))?,
+            DownloadSink::Memory(buffer) => buffer.extend_from_slice(chunk),
         }
-        file.sync_data()
-            .await
-            .map_err(|e| Error::WriteFileError(e.to_string()))?;
-        log::debug!("Downloaded and wrote {} bytes to disk", total);
+        self.hasher.update(chunk);
+        self.written += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Discards everything received so far.
+    async fn restart(&mut self) -> Result<()> {
+        if self.written == 0 {
+            return Ok(());
+        }
+        match &mut self.sink {
+            DownloadSink::File(file) => {
+                file.set_len(0)
+                    .await
+                    .map_err(|e| Error::WriteFileError(e.to_string()))?;
+                file.seek(SeekFrom::Start(0))
+                    .await
+                    .map_err(|e| Error::WriteFileError(e.to_string()))?;
+            }
+            DownloadSink::Memory(buffer) => buffer.clear(),
+        }
+        self.hasher = BundleHasher::new();
+        self.written = 0;
         Ok(())
     }
+
+    /// Flushes the sink and returns it with the final digests.
+    async fn finish(self) -> Result<(DownloadSink, BundleDigests)> {
+        let mut sink = self.sink;
+        if let DownloadSink::File(file) = &mut sink {
+            file.sync_data()
+                .await
+                .map_err(|e| Error::WriteFileError(e.to_string()))?;
+        }
+        Ok((sink, self.hasher.finalize()))
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "download"))]
 mod tests {
     use super::*;
+    use crate::test_http::{self, Response};
+
+    /// Settings for tests against the local stand-in: no waiting between retries.
+    fn stub_settings(hosts: Vec<String>) -> PgFetchSettings {
+        PgFetchSettings {
+            hosts,
+            retry_policy: RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(1),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn bundle() -> Vec<u8> {
+        (0..100_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Serves `bundle()` with a correct `.sha256` sidecar, honouring `Range`.
+    fn serve_bundle(request: &test_http::Request) -> Response {
+        let content = bundle();
+        if request.path.ends_with(".sha256") {
+            return Response::ok(BundleDigests::of(&content).sha256);
+        }
+        if !request.path.ends_with(".jar") {
+            return Response::status(404);
+        }
+        match request.range_start() {
+            Some(start) => Response::status(206)
+                .header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, content.len() - 1, content.len()),
+                )
+                .body(content[start as usize..].to_vec()),
+            None => Response::ok(content),
+        }
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(350));
+        assert_eq!(policy.backoff(40), Duration::from_millis(350));
+    }
+
+    #[tokio::test]
+    async fn test_download_retries_transient_status() -> Result<()> {
+        let server = test_http::serve(|request, n| {
+            if n == 0 {
+                Response::status(503)
+            } else {
+                serve_bundle(request)
+            }
+        })
+        .await;
+        let content = stub_settings(vec![server.url.clone()]).fetch_postgres().await?;
+        assert_eq!(content, bundle());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_interrupted_transfer() -> Result<()> {
+        let server = test_http::serve(|request, n| {
+            if n == 0 {
+                Response::ok(bundle()).truncated(40_000)
+            } else {
+                serve_bundle(request)
+            }
+        })
+        .await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let zip_path = dir.path().join("pg.jar");
+        let settings = stub_settings(vec![server.url.clone()]);
+
+        let (digests, url) = settings.fetch_postgres_to_file(&zip_path).await?;
+        assert_eq!(std::fs::read(&zip_path).unwrap(), bundle());
+        assert_eq!(digests, BundleDigests::of(&bundle()));
+        assert_eq!(url, settings.artifact_url(&server.url));
+
+        let requests = server.requests();
+        assert_eq!(requests[0].range_start(), None);
+        assert!(requests[1].range_start().unwrap() > 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_reports_progress() -> Result<()> {
+        let server = test_http::serve(|request, n| {
+            if n == 0 {
+                Response::ok(bundle()).truncated(40_000)
+            } else {
+                serve_bundle(request)
+            }
+        })
+        .await;
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let settings = PgFetchSettings {
+            progress: Progress::new(sender),
+            ..stub_settings(vec![server.url.clone()])
+        };
+        settings.fetch_postgres().await?;
+        drop(settings);
+
+        let mut events = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            events.push(event);
+        }
+        let total = bundle().len() as u64;
+        let starts: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                ProgressEvent::DownloadStarted {
+                    content_length,
+                    resumed_from,
+                    ..
+                } => Some((*content_length, *resumed_from)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(starts.len(), 2);
+        assert_eq!(starts[0], (Some(total), 0));
+        assert_eq!(starts[1].0, Some(total));
+        assert!(starts[1].1 > 0);
+
+        let received: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                ProgressEvent::BytesReceived { received, total } => Some((*received, *total)),
+                _ => None,
+            })
+            .collect();
+        assert!(received.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(received.last(), Some(&(total, Some(total))));
+        assert_eq!(
+            events.last(),
+            Some(&ProgressEvent::DownloadFinished { bytes: total })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_restarts_when_range_is_ignored() -> Result<()> {
+        let server = test_http::serve(|request, n| match n {
+            0 => Response::ok(bundle()).truncated(40_000),
+            _ if request.path.ends_with(".jar") => Response::ok(bundle()),
+            _ => serve_bundle(request),
+        })
+        .await;
+        let content = stub_settings(vec![server.url.clone()]).fetch_postgres().await?;
+        assert_eq!(content, bundle());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_falls_back_to_next_host() -> Result<()> {
+        let missing = test_http::serve(|_, _| Response::status(404)).await;
+        let mirror = test_http::serve(|request, _| serve_bundle(request)).await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let zip_path = dir.path().join("pg.jar");
+        let settings = stub_settings(vec![missing.url.clone(), mirror.url.clone()]);
+
+        let (_, url) = settings.fetch_postgres_to_file(&zip_path).await?;
+        assert_eq!(url, settings.artifact_url(&mirror.url));
+        // A 404 is not transient: the first host is asked exactly once.
+        assert_eq!(missing.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_sends_credentials_and_headers() -> Result<()> {
+        use crate::pg_http::HttpAuth;
+
+        let server = test_http::serve(|request, _| {
+            if request.header("authorization") != Some("Bearer s3cr3t")
+                || request.header("x-team") != Some("db")
+            {
+                return Response::status(401);
+            }
+            serve_bundle(request)
+        })
+        .await;
+        let settings = PgFetchSettings {
+            http: HttpClientConfig {
+                auth: Some(HttpAuth::Bearer("s3cr3t".to_string())),
+                headers: vec![("X-Team".to_string(), "db".to_string())],
+                client: Some(reqwest::Client::new()),
+                ..Default::default()
+            },
+            ..stub_settings(vec![server.url.clone()])
+        };
+        assert_eq!(settings.fetch_postgres().await?, bundle());
+        // The sidecar requests carry the credentials as well.
+        assert!(server
+            .requests()
+            .iter()
+            .all(|r| r.header("authorization") == Some("Bearer s3cr3t")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_through_proxy() -> Result<()> {
+        let proxy = test_http::serve(|request, _| serve_bundle(request)).await;
+        let settings = PgFetchSettings {
+            http: HttpClientConfig {
+                proxy: Some(proxy.url.clone()),
+                ..Default::default()
+            },
+            ..stub_settings(vec!["http://repo.invalid".to_string()])
+        };
+        assert_eq!(settings.fetch_postgres().await?, bundle());
+        // Requests to a proxy carry the absolute target URL.
+        assert!(proxy.requests()[0].path.starts_with("http://repo.invalid/maven2/"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_gives_up_after_max_attempts() {
+        let server = test_http::serve(|_, _| Response::status(500)).await;
+        let dir = tempfile::TempDir::new().unwrap();
+        let zip_path = dir.path().join("pg.jar");
+
+        let result = stub_settings(vec![server.url.clone()])
+            .fetch_postgres_to_file(&zip_path)
+            .await;
+        assert!(matches!(result, Err(Error::DownloadFailure(_))));
+        assert_eq!(server.requests().len(), 3);
+        assert!(!zip_path.exists());
+    }
+
+    #[test]
+    fn test_url_template() {
+        let settings = PgFetchSettings {
+            operating_system: OperationSystem::Linux,
+            architecture: Architecture::Arm64v8,
+            version: PostgresVersion::new(16, 4, 0),
+            url_template: Some(
+                "{host}/{version}/postgresql-{version}-{target}.tar.gz?major={major}&p={platform}"
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_ok());
+        assert_eq!(
+            settings.artifact_url("https://example.com/releases"),
+            "https://example.com/releases/16.4.0/postgresql-16.4.0-aarch64-unknown-linux-gnu.tar.gz?major=16&p=linux-arm64v8"
+        );
+
+        let alpine = PgFetchSettings {
+            operating_system: OperationSystem::AlpineLinux,
+            architecture: Architecture::Amd64,
+            ..Default::default()
+        };
+        assert_eq!(alpine.target_triple(), "x86_64-unknown-linux-musl");
+        assert!(alpine.artifact_url("https://repo1.maven.org").ends_with(".jar"));
+
+        for template in ["{host}/{flavour}.tar.gz", "{host}/{version.tar.gz"] {
+            let invalid = PgFetchSettings {
+                url_template: Some(template.to_string()),
+                ..Default::default()
+            };
+            assert!(matches!(invalid.validate(), Err(Error::InvalidSettings(_))));
+        }
+    }
+
+    #[test]
+    fn test_offline_switch() {
+        assert!(!offline_from_env(None));
+        for value in ["", "0", "false", "No", " off "] {
+            assert!(!offline_from_env(Some(value.into())), "{:?}", value);
+        }
+        for value in ["1", "true", "YES", "on"] {
+            assert!(offline_from_env(Some(value.into())), "{:?}", value);
+        }
+
+        let settings = PgFetchSettings {
+            offline: true,
+            ..Default::default()
+        };
+        assert!(settings.is_offline());
+        assert!(settings.downloads_disabled().is_some());
+    }
+
+    #[test]
+    fn test_cache_tag() {
+        assert_eq!(PgFetchSettings::default().cache_tag(), None);
+
+        let mirror = PgFetchSettings {
+            hosts: vec!["https://mirror.example.com".to_string()],
+            ..Default::default()
+        };
+        let tag = mirror.cache_tag().unwrap();
+        assert!(tag.starts_with("src-") && tag.len() == 16, "{}", tag);
+        assert_eq!(mirror.cache_tag(), mirror.clone().cache_tag());
+        let templated = PgFetchSettings {
+            url_template: Some("{host}/{version}.tar.gz".to_string()),
+            ..mirror.clone()
+        };
+        assert_ne!(templated.cache_tag(), Some(tag));
+
+        let pinned = PgFetchSettings {
+            pinned_checksum: Some(Checksum::new(
+                ChecksumAlgorithm::Sha256,
+                "3f1c2a9b7d40e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0c1d2e3f4a5b6c7d8e9f0",
+            )),
+            ..mirror.clone()
+        };
+        assert_eq!(pinned.cache_tag().as_deref(), Some("sha256-3f1c2a9b7d40"));
+
+        let version_only = PgFetchSettings {
+            cache_key: CacheKey::Version,
+            ..mirror.clone()
+        };
+        assert_eq!(version_only.cache_tag(), None);
+
+        let custom = PgFetchSettings {
+            cache_key: CacheKey::Custom("corp-build.2".to_string()),
+            ..mirror
+        };
+        assert!(custom.validate().is_ok());
+        assert_eq!(custom.cache_tag().as_deref(), Some("corp-build.2"));
+
+        for name in ["", "..", "../escape", "a/b", "with space"] {
+            let invalid = PgFetchSettings {
+                cache_key: CacheKey::Custom(name.to_string()),
+                ..Default::default()
+            };
+            assert!(
+                matches!(invalid.validate(), Err(Error::InvalidSettings(_))),
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_uses_url_template() -> Result<()> {
+        let server = test_http::serve(|request, _| {
+            if request.path == "/16.4.0/postgresql-16.4.0-x86_64-apple-darwin.tar.gz" {
+                Response::ok(bundle())
+            } else {
+                Response::status(404)
+            }
+        })
+        .await;
+        let settings = PgFetchSettings {
+            operating_system: OperationSystem::Darwin,
+            architecture: Architecture::Amd64,
+            version: PostgresVersion::new(16, 4, 0),
+            url_template: Some("{host}/{version}/postgresql-{version}-{target}.tar.gz".into()),
+            ..stub_settings(vec![server.url.clone()])
+        };
+        assert_eq!(settings.fetch_postgres().await?, bundle());
+        Ok(())
+    }
+
+    #[test]
+    fn test_postgres_version_parse_and_order() {
+        let version: PostgresVersion = "11.22.1".parse().unwrap();
+        assert_eq!(version, PG_V11);
+        assert_eq!(version.major(), 11);
+        assert_eq!(version.to_string(), "11.22.1");
+        assert!(PG_V16 < PG_V17);
+        assert!("16.10.0".parse::<PostgresVersion>().unwrap() > "16.9.0".parse().unwrap());
+
+        for invalid in ["17", "17.8", "17.8.0.1", "10.0.0-beta", "latest"] {
+            assert_eq!(
+                invalid.parse::<PostgresVersion>(),
+                Err(Error::InvalidPostgresVersion(invalid.to_string()))
+            );
+        }
+
+        let json = serde_json::to_string(&PG_V17).unwrap();
+        assert_eq!(json, "\"17.8.0\"");
+        assert_eq!(serde_json::from_str::<PostgresVersion>(&json).unwrap(), PG_V17);
+        assert!(serde_json::from_str::<PostgresVersion>("\"17\"").is_err());
+    }
 
 #[tokio::test]
     async fn fetch_postgres() -> Result<()> {
@@ -236,7 +1485,7 @@ mod tests {
     }
 
     /// Verify that every bundled `PG_Vxx` constant can actually be downloaded
-    /// for the compile-time platform.
+    /// for the detected platform.
     ///
     /// Each version is fetched in full and the byte count is printed.  This
     /// test is marked `#[ignore]` because it downloads several hundred MB and
@@ -284,11 +1533,11 @@ mod tests {
                 ..Default::default()
             };
             let bytes = settings.fetch_postgres().await?;
-            println!("{name} ({}): {} bytes", version.0, bytes.len());
+            println!("{name} ({}): {} bytes", version, bytes.len());
             assert!(
                 bytes.len() > 1_000_000,
                 "{name} ({}) returned only {} bytes — likely missing for platform '{}'",
-                version.0,
+                version,
                 bytes.len(),
                 settings.platform(),
             );
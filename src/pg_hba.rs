@@ -0,0 +1,353 @@
+//! Client authentication rules written to `pg_hba.conf`.
+//!
+//! By default `initdb` writes `pg_hba.conf` from
+//! [`PgSettings::auth_method`](crate::postgres::PgSettings::auth_method) and
+//! the `auth_local`/`auth_host` options of
+//! [`InitDbOptions`](crate::postgres::InitDbOptions).  When
+//! [`PgSettings::hba_rules`](crate::postgres::PgSettings::hba_rules) is not
+//! empty, pg-embed replaces the file with exactly these rules before every
+//! start and on
+//! [`PgEmbed::set_hba_rules`](crate::postgres::PgEmbed::set_hba_rules).  The
+//! file `initdb` wrote is kept as `pg_hba.conf.initdb` and restored when the
+//! rules are cleared.
+//!
+//! The server uses the first rule matching a connection, so put narrow rules
+//! first:
+//!
+//! ```rust
+//! use pg_embed::pg_enums::PgAuthMethod;
+//! use pg_embed::pg_hba::HbaRule;
+//!
+//! let rules = vec![
+//!     HbaRule::local(PgAuthMethod::Trust),
+//!     HbaRule::host("127.0.0.1/32", PgAuthMethod::Reject).user("intruder"),
+//!     HbaRule::host("127.0.0.1/32", PgAuthMethod::ScramSha256),
+//!     HbaRule::host("::1/128", PgAuthMethod::ScramSha256),
+//! ];
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::pg_enums::PgAuthMethod;
+use crate::pg_errors::{Error, Result};
+use crate::pg_fetch::PostgresVersion;
+
+/// Name of the authentication file in the cluster directory.
+pub const HBA_FILE_NAME: &str = "pg_hba.conf";
+
+/// Copy of the `pg_hba.conf` written by `initdb`, made before pg-embed first
+/// replaces it.
+pub const ORIGINAL_HBA_FILE_NAME: &str = "pg_hba.conf.initdb";
+
+/// Kind of connection a [`HbaRule`] matches.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HbaConnectionType {
+    /// Unix-domain socket connections (`local`).
+    Local,
+    /// TCP/IP connections, with or without SSL (`host`).
+    Host,
+    /// TCP/IP connections using SSL (`hostssl`).  Requires `ssl = on`.
+    HostSsl,
+    /// TCP/IP connections not using SSL (`hostnossl`).
+    HostNoSsl,
+}
+
+impl fmt::Display for HbaConnectionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HbaConnectionType::Local => write!(f, "local"),
+            HbaConnectionType::Host => write!(f, "host"),
+            HbaConnectionType::HostSsl => write!(f, "hostssl"),
+            HbaConnectionType::HostNoSsl => write!(f, "hostnossl"),
+        }
+    }
+}
+
+/// One line of `pg_hba.conf`.
+///
+/// Build rules with [`Self::local`], [`Self::host`], [`Self::hostssl`] or
+/// [`Self::hostnossl`]; they match all databases and users until narrowed
+/// with [`Self::database`] and [`Self::user`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HbaRule {
+    /// Connection type.
+    pub conn_type: HbaConnectionType,
+    /// Database name, comma-separated list, or a keyword such as `all`,
+    /// `sameuser` or `replication`.
+    pub database: String,
+    /// User name, comma-separated list, `+group` or `all`.
+    pub user: String,
+    /// Client address in CIDR notation (`127.0.0.1/32`, `::1/128`), a host
+    /// name, or a keyword such as `samehost`.  `None` for
+    /// [`HbaConnectionType::Local`], required otherwise.
+    pub address: Option<String>,
+    /// Authentication method.
+    pub method: PgAuthMethod,
+}
+
+impl HbaRule {
+    /// A rule for Unix-domain socket connections to all databases by all
+    /// users.
+    pub fn local(method: PgAuthMethod) -> Self {
+        HbaRule {
+            conn_type: HbaConnectionType::Local,
+            database: "all".to_string(),
+            user: "all".to_string(),
+            address: None,
+            method,
+        }
+    }
+
+    /// A rule for TCP/IP connections from `address` to all databases by all
+    /// users.
+    pub fn host(address: impl Into<String>, method: PgAuthMethod) -> Self {
+        Self::tcp(HbaConnectionType::Host, address.into(), method)
+    }
+
+    /// Like [`Self::host`], but only for SSL connections.
+    pub fn hostssl(address: impl Into<String>, method: PgAuthMethod) -> Self {
+        Self::tcp(HbaConnectionType::HostSsl, address.into(), method)
+    }
+
+    /// Like [`Self::host`], but only for connections not using SSL.
+    pub fn hostnossl(address: impl Into<String>, method: PgAuthMethod) -> Self {
+        Self::tcp(HbaConnectionType::HostNoSsl, address.into(), method)
+    }
+
+    /// Restricts the rule to `database`.
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        self.database = database.into();
+        self
+    }
+
+    /// Restricts the rule to `user`.
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = user.into();
+        self
+    }
+
+    fn tcp(conn_type: HbaConnectionType, address: String, method: PgAuthMethod) -> Self {
+        HbaRule {
+            conn_type,
+            database: "all".to_string(),
+            user: "all".to_string(),
+            address: Some(address),
+            method,
+        }
+    }
+
+    /// Checks that the server will accept this rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` — PostgreSQL version of the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSettings`] if
+    /// - the database, user or address is empty or contains white space,
+    ///   `"` or `#`,
+    /// - a `local` rule has an address, or another rule has none,
+    /// - [`PgAuthMethod::Peer`] is used for TCP/IP connections, or
+    ///   [`PgAuthMethod::Cert`] for anything but `hostssl`, or
+    /// - [`PgAuthMethod::ScramSha256`] is used with PostgreSQL older than 10.
+    pub fn validate(&self, version: &PostgresVersion) -> Result<()> {
+        let invalid = |reason: &str| {
+            Err(Error::InvalidSettings(format!(
+                "pg_hba.conf rule \"{}\": {}",
+                self, reason
+            )))
+        };
+        let fields = [
+            ("database", Some(&self.database)),
+            ("user", Some(&self.user)),
+            ("address", self.address.as_ref()),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value
+                && (value.is_empty()
+                    || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '#'))
+            {
+                return invalid(&format!("invalid {} {:?}", name, value));
+            }
+        }
+        match (self.conn_type, &self.address) {
+            (HbaConnectionType::Local, Some(_)) => {
+                return invalid("local rules take no address");
+            }
+            (HbaConnectionType::Local, None) => {}
+            (_, None) => return invalid("an address is required"),
+            (_, Some(_)) => {}
+        }
+        match self.method {
+            PgAuthMethod::Peer if self.conn_type != HbaConnectionType::Local => {
+                invalid("peer authentication only works for local connections")
+            }
+            PgAuthMethod::Cert if self.conn_type != HbaConnectionType::HostSsl => {
+                invalid("cert authentication only works for hostssl connections")
+            }
+            PgAuthMethod::ScramSha256 if version.major() < 10 => invalid(&format!(
+                "{} authentication requires PostgreSQL 10 or newer, got {}",
+                self.method, version
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for HbaRule {
+    /// Formats the rule as a `pg_hba.conf` line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}\t{}", self.conn_type, self.database, self.user)?;
+        if let Some(address) = &self.address {
+            write!(f, "\t{}", address)?;
+        }
+        write!(f, "\t{}", self.method)
+    }
+}
+
+/// Renders the contents of `pg_hba.conf`.
+fn render(rules: &[HbaRule]) -> String {
+    let mut out = String::from(
+        "# Written by pg-embed from PgSettings::hba_rules; changes are overwritten.\n\
+         # TYPE\tDATABASE\tUSER\tADDRESS\tMETHOD\n",
+    );
+    for rule in rules {
+        out.push_str(&format!("{}\n", rule));
+    }
+    out
+}
+
+/// Writes `rules` to `pg_hba.conf` in `database_dir`.
+///
+/// The first time, the file written by `initdb` is copied to
+/// [`ORIGINAL_HBA_FILE_NAME`].  Empty `rules` restore that copy, if any.
+///
+/// # Errors
+///
+/// Returns [`Error::ReadFileError`] if a file cannot be inspected.
+/// Returns [`Error::WriteFileError`] if a file cannot be copied, written or
+/// renamed.
+pub(crate) async fn write(database_dir: &Path, rules: &[HbaRule]) -> Result<()> {
+    let path = database_dir.join(HBA_FILE_NAME);
+    let original = database_dir.join(ORIGINAL_HBA_FILE_NAME);
+    let original_exists = tokio::fs::try_exists(&original)
+        .await
+        .map_err(|e| Error::ReadFileError(e.to_string()))?;
+    if rules.is_empty() {
+        if original_exists {
+            tokio::fs::rename(&original, &path)
+                .await
+                .map_err(|e| Error::WriteFileError(e.to_string()))?;
+        }
+        return Ok(());
+    }
+    if !original_exists {
+        tokio::fs::copy(&path, &original)
+            .await
+            .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    }
+    let partial = PathBuf::from(format!("{}.partial", path.display()));
+    tokio::fs::write(&partial, render(rules))
+        .await
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    tokio::fs::rename(&partial, &path)
+        .await
+        .map_err(|e| Error::WriteFileError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pg_fetch::PG_V17;
+
+    #[test]
+    fn test_render() {
+        let rules = [
+            HbaRule::local(PgAuthMethod::Peer),
+            HbaRule::host("127.0.0.1/32", PgAuthMethod::Reject)
+                .database("blocked")
+                .user("alice,bob"),
+            HbaRule::hostssl("0.0.0.0/0", PgAuthMethod::Cert),
+            HbaRule::hostnossl("::1/128", PgAuthMethod::Trust),
+        ];
+        assert_eq!(
+            render(&rules).lines().skip(2).collect::<Vec<_>>(),
+            [
+                "local\tall\tall\tpeer",
+                "host\tblocked\talice,bob\t127.0.0.1/32\treject",
+                "hostssl\tall\tall\t0.0.0.0/0\tcert",
+                "hostnossl\tall\tall\t::1/128\ttrust",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let pg_v9 = PostgresVersion::new(9, 6, 24);
+        let valid = [
+            HbaRule::local(PgAuthMethod::Peer),
+            HbaRule::hostssl("samehost", PgAuthMethod::Cert),
+            HbaRule::host("::1/128", PgAuthMethod::MD5).database("replication"),
+        ];
+        for rule in valid {
+            rule.validate(&PG_V17).unwrap();
+        }
+        let invalid = [
+            (HbaRule::host("127.0.0.1/32", PgAuthMethod::Peer), PG_V17),
+            (HbaRule::host("127.0.0.1/32", PgAuthMethod::Cert), PG_V17),
+            (HbaRule::local(PgAuthMethod::Cert), PG_V17),
+            (HbaRule::host("127.0.0.1/32", PgAuthMethod::ScramSha256), pg_v9),
+            (HbaRule::host("", PgAuthMethod::Trust), PG_V17),
+            (HbaRule::host("10.0.0.0 255.0.0.0", PgAuthMethod::Trust), PG_V17),
+            (HbaRule::local(PgAuthMethod::Trust).user("a b"), PG_V17),
+            (HbaRule::local(PgAuthMethod::Trust).database("#"), PG_V17),
+            (
+                HbaRule {
+                    address: Some("::1/128".to_string()),
+                    ..HbaRule::local(PgAuthMethod::Trust)
+                },
+                PG_V17,
+            ),
+            (
+                HbaRule {
+                    address: None,
+                    ..HbaRule::host("::1/128", PgAuthMethod::Trust)
+                },
+                PG_V17,
+            ),
+        ];
+        for (rule, version) in invalid {
+            assert!(
+                matches!(rule.validate(&version), Err(Error::InvalidSettings(_))),
+                "{rule}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_and_restore() -> Result<()> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(HBA_FILE_NAME);
+        let original = dir.path().join(ORIGINAL_HBA_FILE_NAME);
+        tokio::fs::write(&path, "local all all md5\n").await.unwrap();
+
+        write(dir.path(), &[HbaRule::local(PgAuthMethod::Trust)]).await?;
+        write(dir.path(), &[HbaRule::local(PgAuthMethod::Reject)]).await?;
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        assert!(contents.ends_with("local\tall\tall\treject\n"));
+        assert_eq!(
+            tokio::fs::read_to_string(&original).await.unwrap(),
+            "local all all md5\n"
+        );
+
+        write(dir.path(), &[]).await?;
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "local all all md5\n");
+        assert!(!original.exists());
+        // Without a copy, empty rules leave the file alone.
+        write(dir.path(), &[]).await?;
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "local all all md5\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! HTTP client configuration for downloads from private repositories.
+//!
+//! [`HttpClientConfig`] is carried by
+//! [`PgFetchSettings::http`](crate::pg_fetch::PgFetchSettings::http) and
+//! applies to every request pg-embed makes: the binary bundle, its checksum
+//! sidecars and `maven-metadata.xml`.  It covers credentials, extra headers,
+//! corporate root certificates, an explicit proxy and timeouts, or can hand
+//! over a pre-built [`reqwest::Client`] instead.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::pg_errors::{Error, Result};
+
+/// Credentials sent with every request.
+///
+/// The `Debug` output redacts secrets so settings can be logged safely.
+#[derive(Clone, PartialEq, Eq)]
+pub enum HttpAuth {
+    /// HTTP basic authentication.
+    Basic {
+        /// User name.
+        username: String,
+        /// Password, if any.
+        password: Option<String>,
+    },
+    /// `Authorization: Bearer {token}`.
+    Bearer(String),
+}
+
+impl fmt::Debug for HttpAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpAuth::Basic { username, password } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &password.as_ref().map(|_| "<redacted>"))
+                .finish(),
+            HttpAuth::Bearer(_) => f.debug_tuple("Bearer").field(&"<redacted>").finish(),
+        }
+    }
+}
+
+/// Configuration of the HTTP client used for downloads.
+///
+/// The default uses a plain client with the platform's root certificates,
+/// proxies from the standard environment variables, and no timeouts.
+///
+/// ```rust
+/// use pg_embed::pg_fetch::PgFetchSettings;
+/// use pg_embed::pg_http::{HttpAuth, HttpClientConfig};
+///
+/// let settings = PgFetchSettings {
+///     hosts: vec!["https://artifactory.example.com/artifactory".to_string()],
+///     http: HttpClientConfig {
+///         auth: Some(HttpAuth::Bearer("token".to_string())),
+///         root_certificates: vec!["/etc/ssl/corp-ca.pem".into()],
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HttpClientConfig {
+    /// Credentials sent with every request.
+    pub auth: Option<HttpAuth>,
+    /// Extra headers sent with every request, as `(name, value)` pairs.
+    pub headers: Vec<(String, String)>,
+    /// PEM files with additional root certificates to trust, e.g. a
+    /// corporate CA.  A file may contain several certificates.
+    pub root_certificates: Vec<PathBuf>,
+    /// Proxy URL for all requests, e.g. `http://proxy.example.com:3128`.
+    ///
+    /// `None` uses the `HTTP_PROXY`/`HTTPS_PROXY`/`NO_PROXY` environment
+    /// variables.
+    pub proxy: Option<String>,
+    /// Maximum time to establish a connection.
+    pub connect_timeout: Option<Duration>,
+    /// Maximum time between two reads of a response.  Unlike a total
+    /// timeout this does not limit large but progressing downloads.
+    pub read_timeout: Option<Duration>,
+    /// Use this client instead of building one.
+    ///
+    /// [`Self::root_certificates`], [`Self::proxy`] and the timeouts are then
+    /// ignored, since they can only be set when a client is built;
+    /// [`Self::auth`] and [`Self::headers`] are still added to every request.
+    pub client: Option<reqwest::Client>,
+}
+
+impl HttpClientConfig {
+    /// Returns the injected client or builds one from this configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidHttpConfig`] if a certificate file cannot be
+    /// read or parsed, the proxy URL is invalid, or the client cannot be
+    /// built.
+    pub fn build_client(&self) -> Result<reqwest::Client> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        let mut builder = reqwest::Client::builder();
+        for path in &self.root_certificates {
+            let pem = std::fs::read(path).map_err(|e| {
+                Error::InvalidHttpConfig(format!("reading {}: {}", path.display(), e))
+            })?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| {
+                Error::InvalidHttpConfig(format!("parsing {}: {}", path.display(), e))
+            })?;
+            if certificates.is_empty() {
+                return Err(Error::InvalidHttpConfig(format!(
+                    "{} contains no PEM certificates",
+                    path.display()
+                )));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| Error::InvalidHttpConfig(format!("proxy {}: {}", proxy, e)))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        builder
+            .build()
+            .map_err(|e| Error::InvalidHttpConfig(e.to_string()))
+    }
+
+    /// Starts a GET request for `url` on `client` with the configured
+    /// credentials and headers.
+    pub(crate) fn get(&self, client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+        let mut request = client.get(url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        match &self.auth {
+            Some(HttpAuth::Basic { username, password }) => {
+                request = request.basic_auth(username, password.as_ref())
+            }
+            Some(HttpAuth::Bearer(token)) => request = request.bearer_auth(token),
+            None => {}
+        }
+        request
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_debug_is_redacted() {
+        let basic = HttpAuth::Basic {
+            username: "ci".to_string(),
+            password: Some("hunter2".to_string()),
+        };
+        let bearer = HttpAuth::Bearer("s3cr3t".to_string());
+        let debug = format!("{:?} {:?}", basic, bearer);
+        assert!(debug.contains("ci"));
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains("s3cr3t"));
+    }
+
+    #[test]
+    fn test_invalid_configuration() {
+        let missing_ca = HttpClientConfig {
+            root_certificates: vec!["/nonexistent/ca.pem".into()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            missing_ca.build_client(),
+            Err(Error::InvalidHttpConfig(_))
+        ));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let not_pem = dir.path().join("ca.pem");
+        std::fs::write(&not_pem, "not a certificate").unwrap();
+        let bad_ca = HttpClientConfig {
+            root_certificates: vec![not_pem],
+            ..Default::default()
+        };
+        assert!(matches!(
+            bad_ca.build_client(),
+            Err(Error::InvalidHttpConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,175 @@
+//! Discovery of existing PostgreSQL installations.
+//!
+//! Used when [`crate::pg_fetch::PgFetchSettings::binary_source`] points at
+//! binaries that are already on disk instead of downloading them, e.g.
+//! `/usr/lib/postgresql/16/bin` on a Debian-based CI image.  The installation
+//! can be given explicitly ([`locate_bin_dir`]) or discovered through
+//! `pg_config` on `PATH` ([`pg_config_bin_dir`]); its version is read from
+//! `postgres --version` ([`detect_version`]).
+
+use std::path::{Path, PathBuf};
+
+use crate::pg_errors::{Error, Result};
+use crate::pg_fetch::PostgresVersion;
+
+/// Returns the platform-specific file name of the executable `name`.
+///
+/// Appends `.exe` on Windows and leaves `name` unchanged elsewhere.
+pub(crate) fn exe_name(name: &str) -> String {
+    format!("{}{}", name, std::env::consts::EXE_SUFFIX)
+}
+
+/// Resolves the `bin` directory of an installation.
+///
+/// `dir` may be either the installation prefix (containing `bin/initdb`) or
+/// the `bin` directory itself (containing `initdb`).
+///
+/// # Errors
+///
+/// Returns [`Error::BinariesNotFound`] if neither layout contains `initdb`,
+/// `pg_ctl` and `postgres`.
+pub async fn locate_bin_dir(dir: &Path) -> Result<PathBuf> {
+    for candidate in [dir.join("bin"), dir.to_path_buf()] {
+        if has_executables(&candidate).await {
+            return Ok(candidate);
+        }
+    }
+    Err(Error::BinariesNotFound(format!(
+        "no initdb, pg_ctl and postgres executables in {} or {}",
+        dir.display(),
+        dir.join("bin").display()
+    )))
+}
+
+/// Returns `true` if `bin_dir` contains all executables pg-embed runs.
+async fn has_executables(bin_dir: &Path) -> bool {
+    for name in ["initdb", "pg_ctl", "postgres"] {
+        if !tokio::fs::try_exists(bin_dir.join(exe_name(name)))
+            .await
+            .unwrap_or(false)
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// Discovers the `bin` directory of the installation whose `pg_config` is
+/// first on `PATH`.
+///
+/// # Errors
+///
+/// Returns [`Error::BinariesNotFound`] if `pg_config` cannot be run, exits
+/// with a non-zero status, or reports a directory without the required
+/// executables.
+pub async fn pg_config_bin_dir() -> Result<PathBuf> {
+    let output = tokio::process::Command::new("pg_config")
+        .arg("--bindir")
+        .output()
+        .await
+        .map_err(|e| Error::BinariesNotFound(format!("could not run pg_config: {}", e)))?;
+    if !output.status.success() {
+        return Err(Error::BinariesNotFound(format!(
+            "pg_config --bindir failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let bin_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    locate_bin_dir(&bin_dir).await
+}
+
+/// Runs `postgres --version` and returns the reported version.
+///
+/// Two-part versions (PostgreSQL 10 and newer) get a zero patch component to
+/// match the versions of downloaded binaries: `15.18` becomes `15.18.0`.
+///
+/// # Errors
+///
+/// Returns [`Error::BinariesNotFound`] if the executable cannot be run or its
+/// output contains no version number.
+pub async fn detect_version(postgres_exe: &Path) -> Result<PostgresVersion> {
+    let output = tokio::process::Command::new(postgres_exe)
+        .arg("--version")
+        .output()
+        .await
+        .map_err(|e| {
+            Error::BinariesNotFound(format!("could not run {}: {}", postgres_exe.display(), e))
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_version_output(&stdout).ok_or_else(|| {
+        Error::BinariesNotFound(format!(
+            "unrecognised output from {} --version: {}",
+            postgres_exe.display(),
+            stdout.trim()
+        ))
+    })
+}
+
+/// Extracts the version from `postgres --version` output.
+///
+/// Handles both upstream (`postgres (PostgreSQL) 16.4`) and distribution
+/// builds (`postgres (PostgreSQL) 15.18 (Debian 15.18-0+deb12u1)`).
+/// Pre-release suffixes such as `17beta1` are dropped.
+fn parse_version_output(output: &str) -> Option<PostgresVersion> {
+    let token = output
+        .split_whitespace()
+        .find(|t| t.starts_with(|c: char| c.is_ascii_digit()))?;
+    let mut parts: Vec<u32> = token
+        .split('.')
+        .map(|part| {
+            let end = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..end].parse().ok()
+        })
+        .take_while(Option::is_some)
+        .flatten()
+        .collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    parts.resize(3, 0);
+    Some(PostgresVersion::new(parts[0], parts[1], parts[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_output() {
+        assert_eq!(
+            parse_version_output("postgres (PostgreSQL) 15.18 (Debian 15.18-0+deb12u1)\n"),
+            Some(PostgresVersion::new(15, 18, 0))
+        );
+        assert_eq!(
+            parse_version_output("postgres (PostgreSQL) 9.6.24"),
+            Some(PostgresVersion::new(9, 6, 24))
+        );
+        assert_eq!(
+            parse_version_output("postgres (PostgreSQL) 17beta1"),
+            Some(PostgresVersion::new(17, 0, 0))
+        );
+        assert_eq!(parse_version_output("command not found"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_locate_bin_dir() -> Result<()> {
+        let prefix = tempfile::TempDir::new().unwrap();
+        let bin_dir = prefix.path().join("bin");
+        std::fs::create_dir_all(&bin_dir).unwrap();
+        for name in ["initdb", "pg_ctl"] {
+            std::fs::write(bin_dir.join(name), b"").unwrap();
+        }
+        assert!(matches!(
+            locate_bin_dir(prefix.path()).await,
+            Err(Error::BinariesNotFound(_))
+        ));
+
+        std::fs::write(bin_dir.join("postgres"), b"").unwrap();
+        assert_eq!(locate_bin_dir(prefix.path()).await?, bin_dir);
+        assert_eq!(locate_bin_dir(&bin_dir).await?, bin_dir);
+        Ok(())
+    }
+}
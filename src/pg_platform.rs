@@ -0,0 +1,434 @@
+//! Run-time detection of the platform the PostgreSQL binaries must run on.
+//!
+//! [`OperationSystem::default`] and [`Architecture::default`] describe the
+//! target pg-embed was compiled for.  That is not always the system it runs
+//! on: a glibc build may run in an Alpine container, a cross-compiled binary
+//! may run under emulation, and a 32-bit userland may sit on a 64-bit kernel.
+//! The downloaded binaries are separate executables, so what matters is the
+//! C library and CPU of the host system.
+//!
+//! [`Platform::current`] probes them once per process:
+//!
+//! * On Linux, the ELF header of the system shell (`/bin/sh`) gives the CPU
+//!   and its program interpreter the C library: `ld-musl-*` means musl, i.e.
+//!   [`OperationSystem::AlpineLinux`].  If the shell cannot be read, a musl
+//!   loader in `/lib` without a glibc loader next to it also means musl.
+//!   ARMv6 and ARMv7 are told apart through `/proc/cpuinfo`.
+//! * On macOS, `sysctl hw.optional.arm64` detects Apple silicon, also when
+//!   running translated by Rosetta.
+//! * On Windows, the `PROCESSOR_ARCHITEW6432` and `PROCESSOR_ARCHITECTURE`
+//!   environment variables give the CPU.
+//!
+//! Whatever cannot be detected falls back to the compile-time value.
+//! [`PgFetchSettings::default`](crate::pg_fetch::PgFetchSettings) uses the
+//! detected platform; set
+//! [`PgFetchSettings::operating_system`](crate::pg_fetch::PgFetchSettings::operating_system)
+//! and
+//! [`PgFetchSettings::architecture`](crate::pg_fetch::PgFetchSettings::architecture)
+//! to override it.
+
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::pg_enums::{Architecture, OperationSystem};
+
+/// An operating system and CPU architecture combination.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Platform {
+    /// Operating system, including the C library on Linux.
+    pub operating_system: OperationSystem,
+    /// CPU architecture.
+    pub architecture: Architecture,
+}
+
+impl Platform {
+    /// Returns the platform pg-embed was compiled for.
+    pub fn compile_time() -> Self {
+        Platform {
+            operating_system: OperationSystem::default(),
+            architecture: Architecture::default(),
+        }
+    }
+
+    /// Returns the platform of the running system.
+    ///
+    /// Probed on the first call and cached for the lifetime of the process.
+    /// Falls back to [`Self::compile_time`] for anything that cannot be
+    /// detected.
+    pub fn current() -> Self {
+        static CURRENT: OnceLock<Platform> = OnceLock::new();
+        *CURRENT.get_or_init(|| {
+            let compiled = Self::compile_time();
+            let detected = Self::detect(compiled);
+            if detected != compiled {
+                log::info!(
+                    "Running on {:?}/{:?}, not the compile-time {:?}/{:?}",
+                    detected.operating_system,
+                    detected.architecture,
+                    compiled.operating_system,
+                    compiled.architecture
+                );
+            }
+            detected
+        })
+    }
+
+    /// Probes the running system, keeping `fallback` for what cannot be
+    /// detected.
+    fn detect(fallback: Platform) -> Platform {
+        match fallback.operating_system {
+            OperationSystem::Linux | OperationSystem::AlpineLinux => detect_linux(fallback),
+            OperationSystem::Darwin => Platform {
+                architecture: detect_darwin_arch().unwrap_or(fallback.architecture),
+                ..fallback
+            },
+            OperationSystem::Windows => Platform {
+                architecture: detect_windows_arch().unwrap_or(fallback.architecture),
+                ..fallback
+            },
+        }
+    }
+}
+
+/// The C library a Linux program interpreter belongs to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Libc {
+    Glibc,
+    Musl,
+}
+
+impl Libc {
+    /// Classifies a program interpreter path such as
+    /// `/lib/ld-musl-x86_64.so.1` or `/lib64/ld-linux-x86-64.so.2`.
+    fn of_interpreter(interpreter: &str) -> Option<Libc> {
+        let name = interpreter.rsplit('/').next().unwrap_or(interpreter);
+        if name.starts_with("ld-musl-") {
+            Some(Libc::Musl)
+        } else if name.starts_with("ld-linux") || name.starts_with("ld64.so") {
+            Some(Libc::Glibc)
+        } else {
+            None
+        }
+    }
+}
+
+/// What [`parse_elf`] extracts from an executable.
+#[derive(Debug, Clone, PartialEq)]
+struct ElfInfo {
+    /// `e_machine` of the ELF header.
+    machine: u16,
+    /// Whether the file is 64-bit.
+    is_64: bool,
+    /// Whether the file is little-endian.
+    little_endian: bool,
+    /// The `PT_INTERP` program interpreter, if any.
+    interpreter: Option<String>,
+}
+
+/// ELF machine numbers of the architectures zonky publishes.
+const EM_386: u16 = 3;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_PPC64: u16 = 21;
+const EM_AARCH64: u16 = 183;
+
+/// Program header type of the interpreter path.
+const PT_INTERP: u32 = 3;
+
+/// Executables probed for the system C library and CPU, in order.
+const PROBED_EXECUTABLES: [&str; 3] = ["/bin/sh", "/usr/bin/env", "/bin/ls"];
+
+/// Detects the C library and CPU of a Linux system.
+fn detect_linux(fallback: Platform) -> Platform {
+    let elf = PROBED_EXECUTABLES
+        .iter()
+        .find_map(|path| read_elf(Path::new(path)));
+    let libc = elf
+        .as_ref()
+        .and_then(|elf| elf.interpreter.as_deref())
+        .and_then(Libc::of_interpreter)
+        .or_else(scan_loaders);
+    let operating_system = match libc {
+        Some(Libc::Musl) => OperationSystem::AlpineLinux,
+        Some(Libc::Glibc) => OperationSystem::Linux,
+        None => fallback.operating_system,
+    };
+    let architecture = elf
+        .and_then(|elf| {
+            let arm_version = || {
+                std::fs::read_to_string("/proc/cpuinfo")
+                    .ok()
+                    .and_then(|cpuinfo| arm_version(&cpuinfo))
+            };
+            architecture_of(&elf, arm_version)
+        })
+        .unwrap_or(fallback.architecture);
+    Platform {
+        operating_system,
+        architecture,
+    }
+}
+
+/// Reads the ELF header and interpreter of `path`.
+fn read_elf(path: &Path) -> Option<ElfInfo> {
+    let mut head = Vec::with_capacity(4096);
+    std::fs::File::open(path)
+        .ok()?
+        .take(4096)
+        .read_to_end(&mut head)
+        .ok()?;
+    parse_elf(&head)
+}
+
+/// Parses the ELF header in `data` and, if the program headers are within
+/// `data`, the `PT_INTERP` path.
+fn parse_elf(data: &[u8]) -> Option<ElfInfo> {
+    if data.len() < 52 || &data[..4] != b"\x7fELF" {
+        return None;
+    }
+    let is_64 = match data[4] {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let little_endian = match data[5] {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let u64_at = |offset: usize| -> Option<u64> {
+        let bytes = data.get(offset..offset + 8)?.try_into().ok()?;
+        Some(if little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    };
+    // Offsets of the word-sized fields differ between ELF32 and ELF64.
+    let word_at = |offset: usize| -> Option<usize> {
+        if is_64 {
+            usize::try_from(u64_at(offset)?).ok()
+        } else {
+            usize::try_from(u32_at(offset)?).ok()
+        }
+    };
+
+    let machine = u16_at(18)?;
+    let (phoff, phentsize, phnum) = if is_64 {
+        (word_at(32)?, u16_at(54)?, u16_at(56)?)
+    } else {
+        (word_at(28)?, u16_at(42)?, u16_at(44)?)
+    };
+    let interpreter = (0..usize::from(phnum)).find_map(|i| {
+        let header = phoff.checked_add(i.checked_mul(usize::from(phentsize))?)?;
+        if u32_at(header)? != PT_INTERP {
+            return None;
+        }
+        let (offset, size) = if is_64 {
+            (word_at(header + 8)?, word_at(header + 32)?)
+        } else {
+            (word_at(header + 4)?, word_at(header + 16)?)
+        };
+        let raw = data.get(offset..offset.checked_add(size)?)?;
+        let raw = raw.split(|&b| b == 0).next()?;
+        String::from_utf8(raw.to_vec()).ok()
+    });
+    Some(ElfInfo {
+        machine,
+        is_64,
+        little_endian,
+        interpreter,
+    })
+}
+
+/// Maps an ELF machine to an [`Architecture`].  `arm_version` is consulted
+/// for 32-bit ARM only.
+fn architecture_of(elf: &ElfInfo, arm_version: impl FnOnce() -> Option<u32>) -> Option<Architecture> {
+    match elf.machine {
+        EM_X86_64 if elf.is_64 => Some(Architecture::Amd64),
+        EM_386 => Some(Architecture::I386),
+        EM_AARCH64 if elf.is_64 => Some(Architecture::Arm64v8),
+        EM_PPC64 if elf.is_64 && elf.little_endian => Some(Architecture::Ppc64le),
+        EM_ARM => match arm_version() {
+            Some(version) if version < 7 => Some(Architecture::Arm32v6),
+            _ => Some(Architecture::Arm32v7),
+        },
+        _ => None,
+    }
+}
+
+/// Extracts the ARM architecture version from `/proc/cpuinfo`.
+///
+/// The `(v6l)` suffix of the model name is preferred, since ARM11 cores
+/// report `CPU architecture: 7` although they only implement ARMv6.
+fn arm_version(cpuinfo: &str) -> Option<u32> {
+    let field = |name: &str| {
+        cpuinfo.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim())
+        })
+    };
+    let leading_number = |s: &str| -> Option<u32> {
+        let digits: String = s.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse().ok()
+    };
+    field("model name")
+        .and_then(|model| model.rsplit_once("(v"))
+        .and_then(|(_, suffix)| leading_number(suffix))
+        .or_else(|| field("CPU architecture").and_then(leading_number))
+}
+
+/// Looks for the program interpreters of musl and glibc in the usual
+/// directories.  Only a musl loader without a glibc loader counts as musl,
+/// since glibc systems may have musl installed as well.
+fn scan_loaders() -> Option<Libc> {
+    let mut found = None;
+    for dir in ["/lib", "/lib64", "/usr/lib"] {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            match Libc::of_interpreter(&entry.file_name().to_string_lossy()) {
+                Some(Libc::Glibc) => return Some(Libc::Glibc),
+                Some(Libc::Musl) => found = Some(Libc::Musl),
+                None => {}
+            }
+        }
+    }
+    found
+}
+
+/// Detects Apple silicon, also for x86_64 processes translated by Rosetta.
+fn detect_darwin_arch() -> Option<Architecture> {
+    let output = std::process::Command::new("/usr/sbin/sysctl")
+        .args(["-n", "hw.optional.arm64"])
+        .output()
+        .ok()?;
+    // The key does not exist on Intel Macs, so sysctl fails there.
+    if !output.status.success() {
+        return Some(Architecture::Amd64);
+    }
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "1" => Some(Architecture::Arm64v8),
+        "0" => Some(Architecture::Amd64),
+        _ => None,
+    }
+}
+
+/// Detects the CPU of a Windows system.  `PROCESSOR_ARCHITEW6432` is only set
+/// for 32-bit processes on a 64-bit system and names the real CPU.
+fn detect_windows_arch() -> Option<Architecture> {
+    let arch = std::env::var("PROCESSOR_ARCHITEW6432")
+        .or_else(|_| std::env::var("PROCESSOR_ARCHITECTURE"))
+        .ok()?;
+    match arch.to_ascii_uppercase().as_str() {
+        "AMD64" => Some(Architecture::Amd64),
+        "ARM64" => Some(Architecture::Arm64v8),
+        "X86" => Some(Architecture::I386),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a minimal little-endian ELF64 header with one `PT_INTERP`
+    /// program header.
+    fn elf64(machine: u16, interpreter: &str) -> Vec<u8> {
+        let mut data = vec![0u8; 64 + 56];
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[4] = 2;
+        data[5] = 1;
+        data[18..20].copy_from_slice(&machine.to_le_bytes());
+        data[32..40].copy_from_slice(&64u64.to_le_bytes());
+        data[54..56].copy_from_slice(&56u16.to_le_bytes());
+        data[56..58].copy_from_slice(&1u16.to_le_bytes());
+        let offset = data.len() as u64;
+        let size = interpreter.len() as u64 + 1;
+        data[64..68].copy_from_slice(&PT_INTERP.to_le_bytes());
+        data[72..80].copy_from_slice(&offset.to_le_bytes());
+        data[96..104].copy_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(interpreter.as_bytes());
+        data.push(0);
+        data
+    }
+
+    #[test]
+    fn test_parse_elf() {
+        let elf = parse_elf(&elf64(EM_X86_64, "/lib/ld-musl-x86_64.so.1")).unwrap();
+        assert_eq!(
+            elf,
+            ElfInfo {
+                machine: EM_X86_64,
+                is_64: true,
+                little_endian: true,
+                interpreter: Some("/lib/ld-musl-x86_64.so.1".to_string()),
+            }
+        );
+        assert_eq!(architecture_of(&elf, || None), Some(Architecture::Amd64));
+        assert_eq!(parse_elf(b"#!/bin/sh\n"), None);
+        // Program headers beyond the data leave the interpreter unknown.
+        let truncated = elf64(EM_AARCH64, "/lib/ld-linux-aarch64.so.1");
+        let elf = parse_elf(&truncated[..64]).unwrap();
+        assert_eq!(elf.interpreter, None);
+        assert_eq!(architecture_of(&elf, || None), Some(Architecture::Arm64v8));
+    }
+
+    #[test]
+    fn test_libc_of_interpreter() {
+        assert_eq!(Libc::of_interpreter("/lib/ld-musl-aarch64.so.1"), Some(Libc::Musl));
+        assert_eq!(Libc::of_interpreter("/lib64/ld-linux-x86-64.so.2"), Some(Libc::Glibc));
+        assert_eq!(Libc::of_interpreter("/lib/ld-linux-armhf.so.3"), Some(Libc::Glibc));
+        assert_eq!(Libc::of_interpreter("/lib64/ld64.so.2"), Some(Libc::Glibc));
+        assert_eq!(Libc::of_interpreter("/system/bin/linker64"), None);
+    }
+
+    #[test]
+    fn test_arm_version() {
+        let cpuinfo = "processor\t: 0\nmodel name\t: ARMv6-compatible processor rev 7 (v6l)\nCPU architecture: 7\n";
+        assert_eq!(arm_version(cpuinfo), Some(6));
+        assert_eq!(arm_version("CPU architecture: 7\n"), Some(7));
+        assert_eq!(arm_version("CPU architecture: 6TEJ\n"), Some(6));
+        assert_eq!(arm_version("processor\t: 0\n"), None);
+
+        let arm = ElfInfo {
+            machine: EM_ARM,
+            is_64: false,
+            little_endian: true,
+            interpreter: None,
+        };
+        assert_eq!(architecture_of(&arm, || Some(6)), Some(Architecture::Arm32v6));
+        assert_eq!(architecture_of(&arm, || Some(8)), Some(Architecture::Arm32v7));
+        assert_eq!(architecture_of(&arm, || None), Some(Architecture::Arm32v7));
+    }
+
+    #[test]
+    fn test_current_platform_is_stable() {
+        let current = Platform::current();
+        assert_eq!(Platform::current(), current);
+        if cfg!(target_os = "linux") {
+            assert!(matches!(
+                current.operating_system,
+                OperationSystem::Linux | OperationSystem::AlpineLinux
+            ));
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! Preflight checks that the PostgreSQL binaries can run on this host.
+//!
+//! A bundle that lacks a shared library, binaries without execute
+//! permission, a full disk or a tiny `/dev/shm` all surface as a bare
+//! [`Error::PgInitFailure`](crate::pg_errors::Error::PgInitFailure) or
+//! [`Error::PgStartFailure`](crate::pg_errors::Error::PgStartFailure).
+//! [`PgAccess::preflight`](crate::pg_access::PgAccess::preflight) runs each
+//! [`PreflightCheck`] and returns a [`PreflightReport`] naming the ones that
+//! failed:
+//!
+//! ```rust,no_run
+//! # use pg_embed::pg_access::PgAccess;
+//! # async fn example(pg_access: &PgAccess) -> pg_embed::pg_errors::Result<()> {
+//! let report = pg_access.preflight().await;
+//! for failure in report.failures() {
+//!     eprintln!("{}: {}", failure.check, failure.detail);
+//! }
+//! report.ensure_ok()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`PgEmbed::init_db`](crate::postgres::PgEmbed::init_db) runs the checks
+//! itself when `initdb` fails and logs the failures.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::pg_errors::{Error, Result};
+use crate::pg_fetch::PostgresVersion;
+use crate::pg_install;
+
+/// Free space required in the file system of the cluster directory: a fresh
+/// cluster takes about 40 MiB plus at least one 16 MiB WAL segment.
+pub const MIN_FREE_DISK_SPACE: u64 = 128 * 1024 * 1024;
+
+/// Free space required in `/dev/shm` on Linux, where PostgreSQL allocates
+/// dynamic shared memory.
+pub const MIN_SHARED_MEMORY: u64 = 32 * 1024 * 1024;
+
+/// Maximum time `postgres --version` and `ldd` may take.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A single preflight check.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PreflightCheck {
+    /// `initdb`, `pg_ctl` and `postgres` exist and are executable.
+    Executables,
+    /// `postgres --version` runs and reports the expected version.
+    Version,
+    /// The dynamic linker resolves every shared library of `postgres`.
+    /// Linux only.
+    SharedLibraries,
+    /// The file system of the cluster directory has at least
+    /// [`MIN_FREE_DISK_SPACE`] free.
+    DiskSpace,
+    /// `/dev/shm` exists and has at least [`MIN_SHARED_MEMORY`] free.  Linux
+    /// only.
+    SharedMemory,
+}
+
+impl fmt::Display for PreflightCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreflightCheck::Executables => write!(f, "executables"),
+            PreflightCheck::Version => write!(f, "version"),
+            PreflightCheck::SharedLibraries => write!(f, "shared-libraries"),
+            PreflightCheck::DiskSpace => write!(f, "disk-space"),
+            PreflightCheck::SharedMemory => write!(f, "shared-memory"),
+        }
+    }
+}
+
+/// Outcome of a [`PreflightCheck`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The check passed.
+    Passed,
+    /// The check failed; the binaries are unlikely to work.
+    Failed,
+    /// The check does not apply to this platform or could not be run.
+    Skipped,
+}
+
+/// Result of one [`PreflightCheck`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// The check that ran.
+    pub check: PreflightCheck,
+    /// Its outcome.
+    pub status: CheckStatus,
+    /// What was found, e.g. the missing libraries or the free space.
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(check: PreflightCheck, status: CheckStatus, detail: impl Into<String>) -> Self {
+        CheckResult {
+            check,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Results of all preflight checks, in the order they ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PreflightReport {
+    /// One entry per [`PreflightCheck`].
+    pub results: Vec<CheckResult>,
+}
+
+impl PreflightReport {
+    /// Returns `true` if no check failed.
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Returns the failed checks.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results
+            .iter()
+            .filter(|result| result.status == CheckStatus::Failed)
+    }
+
+    /// Returns the result of `check`, if it ran.
+    pub fn get(&self, check: PreflightCheck) -> Option<&CheckResult> {
+        self.results.iter().find(|result| result.check == check)
+    }
+
+    /// Turns failed checks into an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PreflightFailed`] naming every failed check and its
+    /// detail.
+    pub fn ensure_ok(&self) -> Result<()> {
+        if self.is_ok() {
+            return Ok(());
+        }
+        let failures: Vec<String> = self
+            .failures()
+            .map(|failure| format!("{}: {}", failure.check, failure.detail))
+            .collect();
+        Err(Error::PreflightFailed(failures.join("; ")))
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let status = match result.status {
+                CheckStatus::Passed => "ok",
+                CheckStatus::Failed => "FAILED",
+                CheckStatus::Skipped => "skipped",
+            };
+            writeln!(f, "[{}] {}: {}", status, result.check, result.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Paths and expectations the checks run against.
+pub(crate) struct PreflightTarget<'a> {
+    pub(crate) executables: [&'a Path; 3],
+    pub(crate) postgres_exe: &'a Path,
+    pub(crate) expected_version: PostgresVersion,
+    pub(crate) database_dir: &'a Path,
+}
+
+/// Runs every check against `target`.
+pub(crate) async fn run(target: &PreflightTarget<'_>) -> PreflightReport {
+    let results = vec![
+        check_executables(&target.executables).await,
+        check_version(target.postgres_exe, &target.expected_version).await,
+        check_shared_libraries(target.postgres_exe).await,
+        check_space(
+            PreflightCheck::DiskSpace,
+            &existing_ancestor(target.database_dir),
+            MIN_FREE_DISK_SPACE,
+        ),
+        check_shared_memory(),
+    ];
+    PreflightReport { results }
+}
+
+/// Checks that every executable exists and, on Unix, has an execute bit.
+async fn check_executables(executables: &[&Path]) -> CheckResult {
+    let mut problems = Vec::new();
+    for exe in executables {
+        match tokio::fs::metadata(exe).await {
+            Err(_) => problems.push(format!("{} is missing", exe.display())),
+            Ok(metadata) if !metadata.is_file() => {
+                problems.push(format!("{} is not a file", exe.display()))
+            }
+            Ok(metadata) => {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    if metadata.permissions().mode() & 0o111 == 0 {
+                        problems.push(format!("{} is not executable", exe.display()));
+                    }
+                }
+                #[cfg(not(unix))]
+                let _ = metadata;
+            }
+        }
+    }
+    if problems.is_empty() {
+        CheckResult::new(
+            PreflightCheck::Executables,
+            CheckStatus::Passed,
+            format!("{} executables found", executables.len()),
+        )
+    } else {
+        CheckResult::new(PreflightCheck::Executables, CheckStatus::Failed, problems.join(", "))
+    }
+}
+
+/// Runs `postgres --version` and compares the result with `expected`.
+async fn check_version(postgres_exe: &Path, expected: &PostgresVersion) -> CheckResult {
+    let detected = tokio::time::timeout(COMMAND_TIMEOUT, pg_install::detect_version(postgres_exe))
+        .await
+        .unwrap_or_else(|_| {
+            Err(Error::BinariesNotFound(format!(
+                "{} --version did not finish within {:?}",
+                postgres_exe.display(),
+                COMMAND_TIMEOUT
+            )))
+        });
+    match detected {
+        Ok(version) if version == *expected => CheckResult::new(
+            PreflightCheck::Version,
+            CheckStatus::Passed,
+            format!("PostgreSQL {}", version),
+        ),
+        Ok(version) => CheckResult::new(
+            PreflightCheck::Version,
+            CheckStatus::Failed,
+            format!("expected PostgreSQL {}, found {}", expected, version),
+        ),
+        Err(e) => CheckResult::new(PreflightCheck::Version, CheckStatus::Failed, e.to_string()),
+    }
+}
+
+/// Runs `ldd` on `postgres_exe` and reports unresolved libraries.
+async fn check_shared_libraries(postgres_exe: &Path) -> CheckResult {
+    let check = PreflightCheck::SharedLibraries;
+    if !cfg!(target_os = "linux") {
+        return CheckResult::new(check, CheckStatus::Skipped, "only checked on Linux");
+    }
+    let output = tokio::process::Command::new("ldd")
+        .arg(postgres_exe)
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(COMMAND_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            return CheckResult::new(check, CheckStatus::Skipped, format!("could not run ldd: {}", e));
+        }
+        Err(_) => {
+            return CheckResult::new(
+                check,
+                CheckStatus::Skipped,
+                format!("ldd did not finish within {:?}", COMMAND_TIMEOUT),
+            );
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let missing = missing_libraries(&stdout);
+    if !missing.is_empty() {
+        return CheckResult::new(
+            check,
+            CheckStatus::Failed,
+            format!("not found: {}", missing.join(", ")),
+        );
+    }
+    if output.status.success() {
+        return CheckResult::new(check, CheckStatus::Passed, "all libraries resolved");
+    }
+    if format!("{}{}", stdout, stderr).contains("not a dynamic executable") {
+        return CheckResult::new(check, CheckStatus::Passed, "not dynamically linked");
+    }
+    CheckResult::new(
+        check,
+        CheckStatus::Failed,
+        format!("ldd failed: {}", stderr.trim()),
+    )
+}
+
+/// Extracts the libraries `ldd` reports as `not found`.
+fn missing_libraries(ldd_output: &str) -> Vec<String> {
+    ldd_output
+        .lines()
+        .filter(|line| line.contains("not found"))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Checks that `/dev/shm` has at least [`MIN_SHARED_MEMORY`] free.
+fn check_shared_memory() -> CheckResult {
+    let check = PreflightCheck::SharedMemory;
+    if !cfg!(target_os = "linux") {
+        return CheckResult::new(check, CheckStatus::Skipped, "only checked on Linux");
+    }
+    let shm = Path::new("/dev/shm");
+    if !shm.is_dir() {
+        return CheckResult::new(
+            check,
+            CheckStatus::Failed,
+            "/dev/shm does not exist; PostgreSQL needs POSIX shared memory",
+        );
+    }
+    check_space(check, shm, MIN_SHARED_MEMORY)
+}
+
+/// Checks that the file system of `path` has at least `required` bytes free.
+fn check_space(check: PreflightCheck, path: &Path, required: u64) -> CheckResult {
+    match fs4::available_space(path) {
+        Ok(available) if available >= required => CheckResult::new(
+            check,
+            CheckStatus::Passed,
+            format!("{} MiB free in {}", mib(available), path.display()),
+        ),
+        Ok(available) => CheckResult::new(
+            check,
+            CheckStatus::Failed,
+            format!(
+                "{} MiB free in {}, at least {} MiB required",
+                mib(available),
+                path.display(),
+                mib(required)
+            ),
+        ),
+        Err(e) => CheckResult::new(
+            check,
+            CheckStatus::Skipped,
+            format!("could not query {}: {}", path.display(), e),
+        ),
+    }
+}
+
+/// Returns `path` or its closest ancestor that exists.
+fn existing_ancestor(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|p| p.exists())
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+/// Converts bytes to whole MiB for display.
+fn mib(bytes: u64) -> u64 {
+    bytes / (1024 * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_libraries() {
+        let output = "\tlinux-vdso.so.1 (0x00007ffd)\n\
+                      \tlibicuuc.so.72 => not found\n\
+                      \tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f)\n\
+                      \tlibssl.so.3 => not found\n";
+        assert_eq!(missing_libraries(output), vec!["libicuuc.so.72", "libssl.so.3"]);
+        assert!(missing_libraries("\tlibc.so.6 => /lib/libc.so.6\n").is_empty());
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = PreflightReport {
+            results: vec![CheckResult::new(
+                PreflightCheck::Version,
+                CheckStatus::Passed,
+                "PostgreSQL 17.2.0",
+            )],
+        };
+        assert!(report.is_ok());
+        report.ensure_ok().unwrap();
+
+        report.results.push(CheckResult::new(
+            PreflightCheck::SharedLibraries,
+            CheckStatus::Failed,
+            "not found: libssl.so.3",
+        ));
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.get(PreflightCheck::SharedLibraries).unwrap().status,
+            CheckStatus::Failed
+        );
+        assert_eq!(report.get(PreflightCheck::DiskSpace), None);
+        let Err(Error::PreflightFailed(message)) = report.ensure_ok() else {
+            panic!("expected a preflight failure");
+        };
+        assert_eq!(message, "shared-libraries: not found: libssl.so.3");
+        assert_eq!(
+            report.to_string(),
+            "[ok] version: PostgreSQL 17.2.0\n[FAILED] shared-libraries: not found: libssl.so.3\n"
+        );
+    }
+
+    #[test]
+    fn test_check_space() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let missing = dir.path().join("not/yet/created");
+        let path = existing_ancestor(&missing);
+        assert_eq!(path, dir.path());
+
+        let passed = check_space(PreflightCheck::DiskSpace, &path, 0);
+        assert_eq!(passed.status, CheckStatus::Passed);
+        let failed = check_space(PreflightCheck::DiskSpace, &path, u64::MAX);
+        assert_eq!(failed.status, CheckStatus::Failed);
+        assert!(failed.detail.contains("required"), "{}", failed.detail);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_check_executables() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let runnable = dir.path().join("initdb");
+        let plain = dir.path().join("postgres");
+        std::fs::write(&runnable, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&runnable, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(&plain, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&plain, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let absent = dir.path().join("pg_ctl");
+
+        let result = check_executables(&[&runnable]).await;
+        assert_eq!(result.status, CheckStatus::Passed);
+
+        let result = check_executables(&[&runnable, &plain, &absent]).await;
+        assert_eq!(result.status, CheckStatus::Failed);
+        assert_eq!(
+            result.detail,
+            format!(
+                "{} is not executable, {} is missing",
+                plain.display(),
+                absent.display()
+            )
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Progress reporting for downloading and unpacking PostgreSQL binaries.
+//!
+//! Set [`PgFetchSettings::progress`](crate::pg_fetch::PgFetchSettings::progress)
+//! to receive a [`ProgressEvent`] for every step of
+//! [`PgAccess::maybe_acquire_postgres`](crate::pg_access::PgAccess::maybe_acquire_postgres):
+//! the download starting (with its content length), every chunk received,
+//! the download finishing, and the archive being unpacked entry by entry.
+//!
+//! Handlers are plain closures or anything implementing [`ProgressHandler`];
+//! an unbounded tokio channel sender works out of the box:
+//!
+//! ```rust
+//! use pg_embed::pg_fetch::PgFetchSettings;
+//! use pg_embed::pg_progress::{Progress, ProgressEvent};
+//!
+//! let settings = PgFetchSettings {
+//!     progress: Progress::new(|event: &ProgressEvent| {
+//!         if let ProgressEvent::BytesReceived { received, total: Some(total) } = event {
+//!             eprint!("\r{}%", received * 100 / total);
+//!         }
+//!     }),
+//!     ..Default::default()
+//! };
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A step in acquiring the PostgreSQL binaries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// A download request was answered and the body is about to be read.
+    ///
+    /// Emitted again for every retry or mirror; `resumed_from` is non-zero
+    /// when an interrupted transfer is resumed.
+    DownloadStarted {
+        /// The artifact URL.
+        url: String,
+        /// Size of the complete bundle in bytes, if the server reported it.
+        content_length: Option<u64>,
+        /// Bytes already received before this request.
+        resumed_from: u64,
+    },
+    /// A chunk of the bundle was received.
+    BytesReceived {
+        /// Bytes received so far, including earlier attempts that were
+        /// resumed.
+        received: u64,
+        /// Size of the complete bundle in bytes, if known.
+        total: Option<u64>,
+    },
+    /// The bundle was downloaded completely.  Verification follows.
+    DownloadFinished {
+        /// Size of the bundle in bytes.
+        bytes: u64,
+    },
+    /// Extraction of the bundle started.
+    UnpackStarted {
+        /// The archive being unpacked.
+        archive: PathBuf,
+    },
+    /// A file, directory or link was extracted.
+    EntryExtracted {
+        /// Path of the entry inside the archive.
+        path: PathBuf,
+        /// Number of entries extracted so far, including this one.
+        entries: u64,
+    },
+    /// Extraction finished.
+    UnpackFinished {
+        /// Total number of entries extracted.
+        entries: u64,
+    },
+}
+
+/// Receives [`ProgressEvent`]s.
+///
+/// Called synchronously from the download loop and from the blocking unpack
+/// thread, so implementations should return quickly.
+pub trait ProgressHandler: Send + Sync {
+    /// Handles one event.
+    fn on_event(&self, event: &ProgressEvent);
+}
+
+impl<F> ProgressHandler for F
+where
+    F: Fn(&ProgressEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &ProgressEvent) {
+        self(event)
+    }
+}
+
+impl ProgressHandler for tokio::sync::mpsc::UnboundedSender<ProgressEvent> {
+    fn on_event(&self, event: &ProgressEvent) {
+        // A dropped receiver only means nobody is listening any more.
+        let _ = self.send(event.clone());
+    }
+}
+
+/// An optional, cheaply clonable [`ProgressHandler`].
+///
+/// The default reports nothing.
+#[derive(Clone, Default)]
+pub struct Progress(Option<Arc<dyn ProgressHandler>>);
+
+impl Progress {
+    /// Reports events to `handler`.
+    pub fn new(handler: impl ProgressHandler + 'static) -> Self {
+        Progress(Some(Arc::new(handler)))
+    }
+
+    /// Returns `true` if a handler is set.
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Passes `event` to the handler, if any.
+    pub(crate) fn emit(&self, event: ProgressEvent) {
+        if let Some(handler) = &self.0 {
+            handler.on_event(&event);
+        }
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Progress")
+            .field(&if self.is_enabled() { "<handler>" } else { "none" })
+            .finish()
+    }
+}
@@ -0,0 +1,475 @@
+//! Resolution of PostgreSQL version requirements against Maven metadata.
+//!
+//! The `PG_Vxx` constants in [`crate::pg_fetch`] only change with a crate
+//! release.  [`resolve_version`] instead reads the `maven-metadata.xml`
+//! published for the platform's artifact on [`PgFetchSettings::hosts`] and
+//! picks the newest version that satisfies a [`VersionRequirement`] such as
+//! `"17"` or `"latest"`.
+//!
+//! Every successfully fetched version list is stored under
+//! `{cache_root}/metadata/` (see [`PgAccess::cache_root`]).  When the
+//! repository cannot be reached, the stored list is used instead, so a
+//! requirement that resolved once keeps resolving offline.  In offline mode
+//! (see [`PgFetchSettings::offline`]) only the stored list is used.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pg_access::PgAccess;
+use crate::pg_errors::{Error, Result};
+use crate::pg_fetch::{PgFetchSettings, PostgresVersion};
+
+/// A constraint on the PostgreSQL version to use.
+///
+/// Parsed from a string with [`FromStr`]:
+///
+/// | Input                   | Requirement                |
+/// |-------------------------|----------------------------|
+/// | `latest`                | [`Self::Latest`]           |
+/// | `17`, `17.x`, `17.*`    | [`Self::Major`]            |
+/// | `16.4`, `16.4.x`        | [`Self::Minor`]            |
+/// | `16.4.0`                | [`Self::Exact`]            |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionRequirement {
+    /// The newest published version.
+    Latest,
+    /// The newest version with the given major version.
+    Major(u32),
+    /// The newest version with the given major and minor version.
+    Minor(u32, u32),
+    /// Exactly this version.
+    ///
+    /// Resolved without network access; whether the version is actually
+    /// published is only checked when it is downloaded.
+    Exact(PostgresVersion),
+}
+
+impl VersionRequirement {
+    /// Returns `true` if `version` satisfies this requirement.
+    pub fn matches(&self, version: &PostgresVersion) -> bool {
+        match self {
+            VersionRequirement::Latest => true,
+            VersionRequirement::Major(major) => version.major() == *major,
+            VersionRequirement::Minor(major, minor) => {
+                version.major() == *major && version.minor() == *minor
+            }
+            VersionRequirement::Exact(exact) => version == exact,
+        }
+    }
+}
+
+impl FromStr for VersionRequirement {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let trimmed = s.trim();
+        if trimmed.eq_ignore_ascii_case("latest") {
+            return Ok(VersionRequirement::Latest);
+        }
+        let invalid = || Error::InvalidVersionRequirement(s.to_string());
+        let mut parts: Vec<&str> = trimmed.split('.').collect();
+        if parts.len() > 1 && matches!(parts.last(), Some(&"x") | Some(&"*")) {
+            parts.pop();
+            if parts.len() > 2 {
+                return Err(invalid());
+            }
+        }
+        let numbers = parts
+            .iter()
+            .map(|p| p.parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>>>()?;
+        match numbers.as_slice() {
+            [major] => Ok(VersionRequirement::Major(*major)),
+            [major, minor] => Ok(VersionRequirement::Minor(*major, *minor)),
+            [major, minor, patch] => Ok(VersionRequirement::Exact(PostgresVersion::new(
+                *major, *minor, *patch,
+            ))),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for VersionRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionRequirement::Latest => write!(f, "latest"),
+            VersionRequirement::Major(major) => write!(f, "{}.x", major),
+            VersionRequirement::Minor(major, minor) => write!(f, "{}.{}.x", major, minor),
+            VersionRequirement::Exact(exact) => write!(f, "{}", exact),
+        }
+    }
+}
+
+/// Version list of one artifact, as stored in the on-disk metadata cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedVersions {
+    /// Repository the list was fetched from.
+    host: String,
+    /// Maven platform classifier, e.g. `linux-amd64`.
+    platform: String,
+    /// Every version listed in `maven-metadata.xml`.
+    versions: Vec<String>,
+}
+
+/// Resolves `requirement` to a concrete version for the platform of
+/// `fetch_settings`.
+///
+/// [`VersionRequirement::Exact`] is returned as is.  Otherwise the artifact's
+/// `maven-metadata.xml` is fetched from [`PgFetchSettings::hosts`], stored in
+/// the metadata cache, and the newest matching version is returned.  If the
+/// fetch fails, the cached list from an earlier run is used.
+///
+/// # Arguments
+///
+/// * `fetch_settings` — Supplies the repository host and the platform.
+/// * `requirement` — The version constraint to satisfy.
+///
+/// # Returns
+///
+/// The resolved version, ready to be assigned to
+/// [`PgFetchSettings::version`].
+///
+/// # Errors
+///
+/// Returns [`Error::VersionNotFound`] if no published version matches, or if
+/// the metadata can neither be fetched nor read from the cache.
+/// Returns [`Error::InvalidPgUrl`] if the OS cache directory cannot be
+/// resolved.
+/// Returns [`Error::InvalidHttpConfig`] if [`PgFetchSettings::http`] is
+/// invalid.
+pub async fn resolve_version(
+    fetch_settings: &PgFetchSettings,
+    requirement: &VersionRequirement,
+) -> Result<PostgresVersion> {
+    if let VersionRequirement::Exact(exact) = requirement {
+        return Ok(*exact);
+    }
+    let cache_file = PgAccess::cache_root(fetch_settings)?
+        .join("metadata")
+        .join(format!("{}.json", fetch_settings.platform()));
+    resolve_with_cache(fetch_settings, requirement, &cache_file).await
+}
+
+/// Returns the URL of `maven-metadata.xml` for the platform's artifact on
+/// `host`.
+#[cfg(feature = "download")]
+pub(crate) fn metadata_url(fetch_settings: &PgFetchSettings, host: &str) -> String {
+    format!(
+        "{}/maven2/io/zonky/test/postgres/embedded-postgres-binaries-{}/maven-metadata.xml",
+        host,
+        fetch_settings.platform()
+    )
+}
+
+/// Implementation of [`resolve_version`] with an explicit cache file.
+async fn resolve_with_cache(
+    fetch_settings: &PgFetchSettings,
+    requirement: &VersionRequirement,
+    cache_file: &Path,
+) -> Result<PostgresVersion> {
+    let versions = match fetch_versions(fetch_settings).await {
+        Ok((host, versions)) => {
+            let cached = CachedVersions {
+                host,
+                platform: fetch_settings.platform(),
+                versions: versions.clone(),
+            };
+            if let Err(e) = write_cache(cache_file, &cached).await {
+                log::warn!("Could not cache PostgreSQL version list: {}", e);
+            }
+            versions
+        }
+        Err(e) if fetch_settings.downloads_disabled().is_some() => {
+            log::debug!("{}; using cached version list", e);
+            read_cache(cache_file, fetch_settings).await?.ok_or_else(|| {
+                Error::VersionNotFound(format!(
+                    "{} for {}: no cached version list and {}",
+                    requirement,
+                    fetch_settings.platform(),
+                    e
+                ))
+            })?
+        }
+        Err(e) => {
+            log::warn!(
+                "Could not fetch maven-metadata.xml: {}; using cached version list",
+                e
+            );
+            read_cache(cache_file, fetch_settings).await?.ok_or_else(|| {
+                Error::VersionNotFound(format!(
+                    "{} for {}: version list unavailable ({})",
+                    requirement,
+                    fetch_settings.platform(),
+                    e
+                ))
+            })?
+        }
+    };
+
+    let resolved = select(&versions, requirement).ok_or_else(|| {
+        Error::VersionNotFound(format!(
+            "{} for {} (available: {})",
+            requirement,
+            fetch_settings.platform(),
+            versions.join(", ")
+        ))
+    })?;
+    log::debug!("Resolved PostgreSQL {} to {}", requirement, resolved);
+    Ok(resolved)
+}
+
+/// Fetches and parses the artifact's `maven-metadata.xml`, trying each of
+/// [`PgFetchSettings::hosts`] in turn.
+///
+/// # Returns
+///
+/// The host that answered and the versions it lists.
+///
+/// # Errors
+///
+/// Returns [`Error::DownloadFailure`] without any request if downloads are
+/// disabled (see [`PgFetchSettings::offline`]).
+#[cfg(feature = "download")]
+async fn fetch_versions(fetch_settings: &PgFetchSettings) -> Result<(String, Vec<String>)> {
+    if let Some(reason) = fetch_settings.downloads_disabled() {
+        return Err(Error::DownloadFailure(reason.to_string()));
+    }
+    let client = fetch_settings.http.build_client()?;
+    let mut last_error = Error::DownloadFailure("no download hosts configured".into());
+    for host in &fetch_settings.hosts {
+        match fetch_versions_from(fetch_settings, &client, host).await {
+            Ok(versions) => return Ok((host.clone(), versions)),
+            Err(e) => {
+                log::debug!("No version list from {}: {}", host, e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Stand-in for [`fetch_versions`] without the `download` feature: only the
+/// cached version list can be used.
+#[cfg(not(feature = "download"))]
+async fn fetch_versions(fetch_settings: &PgFetchSettings) -> Result<(String, Vec<String>)> {
+    let reason = fetch_settings.downloads_disabled().unwrap_or_default();
+    Err(Error::DownloadFailure(reason.to_string()))
+}
+
+/// Fetches and parses `maven-metadata.xml` from `host`.
+#[cfg(feature = "download")]
+async fn fetch_versions_from(
+    fetch_settings: &PgFetchSettings,
+    client: &reqwest::Client,
+    host: &str,
+) -> Result<Vec<String>> {
+    let url = metadata_url(fetch_settings, host);
+    let response = fetch_settings.get_with_retry(client, &url).await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::DownloadFailure(format!("HTTP {status} fetching {url}")));
+    }
+    let body = response
+        .text()
+        .await
+        .map_err(|e| Error::ConversionFailure(e.to_string()))?;
+    let versions = parse_metadata(&body);
+    if versions.is_empty() {
+        return Err(Error::DownloadFailure(format!("{url} lists no versions")));
+    }
+    Ok(versions)
+}
+
+/// Extracts the contents of every `<version>` element from `maven-metadata.xml`.
+///
+/// The document has a fixed, flat structure, so a full XML parser is not
+/// needed.
+#[cfg(feature = "download")]
+fn parse_metadata(xml: &str) -> Vec<String> {
+    const OPEN: &str = "<version>";
+    const CLOSE: &str = "</version>";
+    let mut versions = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(OPEN) {
+        rest = &rest[start + OPEN.len()..];
+        let Some(end) = rest.find(CLOSE) else {
+            break;
+        };
+        versions.push(rest[..end].trim().to_string());
+        rest = &rest[end + CLOSE.len()..];
+    }
+    versions
+}
+
+/// Returns the newest version in `versions` that satisfies `requirement`.
+///
+/// Versions that do not parse as [`PostgresVersion`], such as the pre-release
+/// `10.0.0-beta`, are skipped.
+fn select(versions: &[String], requirement: &VersionRequirement) -> Option<PostgresVersion> {
+    versions
+        .iter()
+        .filter_map(|v| v.parse::<PostgresVersion>().ok())
+        .filter(|v| requirement.matches(v))
+        .max()
+}
+
+/// Reads the cached version list, ignoring it if it was fetched from a
+/// repository not in [`PgFetchSettings::hosts`] or for another platform.
+async fn read_cache(
+    cache_file: &Path,
+    fetch_settings: &PgFetchSettings,
+) -> Result<Option<Vec<String>>> {
+    let content = match tokio::fs::read(cache_file).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::ReadFileError(e.to_string())),
+    };
+    let cached: CachedVersions = match serde_json::from_slice(&content) {
+        Ok(cached) => cached,
+        Err(e) => {
+            log::warn!("Ignoring corrupt {}: {}", cache_file.display(), e);
+            return Ok(None);
+        }
+    };
+    if !fetch_settings.hosts.contains(&cached.host) || cached.platform != fetch_settings.platform() {
+        return Ok(None);
+    }
+    Ok(Some(cached.versions))
+}
+
+/// Writes the version list to `cache_file` via a temporary file and rename.
+async fn write_cache(cache_file: &Path, cached: &CachedVersions) -> Result<()> {
+    if let Some(parent) = cache_file.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| Error::DirCreationError(e.to_string()))?;
+    }
+    let content =
+        serde_json::to_vec_pretty(cached).map_err(|e| Error::WriteFileError(e.to_string()))?;
+    let mut partial = PathBuf::from(cache_file).into_os_string();
+    partial.push(format!(".{}", std::process::id()));
+    let partial = PathBuf::from(partial);
+    tokio::fs::write(&partial, content)
+        .await
+        .map_err(|e| Error::WriteFileError(e.to_string()))?;
+    tokio::fs::rename(&partial, cache_file)
+        .await
+        .map_err(|e| Error::WriteFileError(e.to_string()))
+}
+
+#[cfg(all(test, feature = "download"))]
+mod tests {
+    use super::*;
+    use crate::test_http::{self, Response};
+
+    const METADATA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>io.zonky.test.postgres</groupId>
+  <artifactId>embedded-postgres-binaries-linux-amd64</artifactId>
+  <versioning>
+    <latest>17.2.0</latest>
+    <versions>
+      <version>10.0.0-beta</version>
+      <version>16.4.0</version>
+      <version>16.10.0</version>
+      <version>17.0.0</version>
+      <version>17.2.0</version>
+    </versions>
+  </versioning>
+</metadata>"#;
+
+    #[test]
+    fn test_parse_requirement() {
+        assert_eq!("latest".parse(), Ok(VersionRequirement::Latest));
+        assert_eq!("17".parse(), Ok(VersionRequirement::Major(17)));
+        assert_eq!("17.x".parse(), Ok(VersionRequirement::Major(17)));
+        assert_eq!("16.4.*".parse(), Ok(VersionRequirement::Minor(16, 4)));
+        assert_eq!(
+            "16.4.0".parse(),
+            Ok(VersionRequirement::Exact(PostgresVersion::new(16, 4, 0)))
+        );
+        assert!("17.x.1".parse::<VersionRequirement>().is_err());
+        assert!("seventeen".parse::<VersionRequirement>().is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let versions = parse_metadata(METADATA);
+        assert_eq!(versions.len(), 5);
+        let select = |requirement| select(&versions, &requirement).map(|v| v.to_string());
+        assert_eq!(select(VersionRequirement::Latest).as_deref(), Some("17.2.0"));
+        assert_eq!(select(VersionRequirement::Major(16)).as_deref(), Some("16.10.0"));
+        assert_eq!(select(VersionRequirement::Minor(16, 4)).as_deref(), Some("16.4.0"));
+        assert_eq!(select(VersionRequirement::Major(10)), None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_uses_cache_when_offline() -> Result<()> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache_file = dir.path().join("metadata/linux-amd64.json");
+        let server = test_http::serve(|_, _| Response::ok(METADATA)).await;
+        let fetch_settings = PgFetchSettings {
+            hosts: vec![server.url.clone()],
+            retry_policy: crate::pg_fetch::RetryPolicy {
+                max_attempts: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let resolved =
+            resolve_with_cache(&fetch_settings, &VersionRequirement::Major(17), &cache_file)
+                .await?;
+        assert_eq!(resolved, PostgresVersion::new(17, 2, 0));
+        assert!(cache_file.exists());
+
+        drop(server);
+        let resolved =
+            resolve_with_cache(&fetch_settings, &VersionRequirement::Major(16), &cache_file)
+                .await?;
+        assert_eq!(resolved, PostgresVersion::new(16, 10, 0));
+
+        let other_host = PgFetchSettings {
+            hosts: vec!["http://127.0.0.1:1".to_string()],
+            ..fetch_settings.clone()
+        };
+        assert!(matches!(
+            resolve_with_cache(&other_host, &VersionRequirement::Latest, &cache_file).await,
+            Err(Error::VersionNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_only_reads_cache() -> Result<()> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache_file = dir.path().join("metadata/linux-amd64.json");
+        let server = test_http::serve(|_, _| Response::ok(METADATA)).await;
+        let fetch_settings = PgFetchSettings {
+            hosts: vec![server.url.clone()],
+            offline: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            resolve_with_cache(&fetch_settings, &VersionRequirement::Latest, &cache_file).await,
+            Err(Error::VersionNotFound(_))
+        ));
+        assert!(server.requests().is_empty());
+
+        let online = PgFetchSettings {
+            offline: false,
+            ..fetch_settings.clone()
+        };
+        resolve_with_cache(&online, &VersionRequirement::Latest, &cache_file).await?;
+        let resolved =
+            resolve_with_cache(&fetch_settings, &VersionRequirement::Major(16), &cache_file)
+                .await?;
+        assert_eq!(resolved, PostgresVersion::new(16, 10, 0));
+        assert_eq!(server.requests().len(), 1);
+        Ok(())
+    }
+}
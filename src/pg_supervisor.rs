@@ -0,0 +1,504 @@
+//! Supervision of a `postgres` server process started directly.
+//!
+//! With [`StartMode::Supervised`](crate::pg_enums::StartMode::Supervised),
+//! [`PgEmbed::start_db`](crate::postgres::PgEmbed::start_db) spawns
+//! `bin/postgres -D {database_dir}` as a child process instead of running
+//! `pg_ctl start`.  pg-embed then holds the postmaster's PID and its output:
+//!
+//! - every output line is logged and the last [`LOG_TAIL_LINES`] are kept,
+//! - the server counts as ready once it logs that it accepts connections,
+//!   `postmaster.pid` reports `ready`, or — before PostgreSQL 10, which has
+//!   no status in `postmaster.pid` — a connection to it succeeds,
+//! - a background task waits for the process.  If it exits without
+//!   [`PgEmbed::stop_db`](crate::postgres::PgEmbed::stop_db), the server
+//!   status becomes [`PgServerStatus::Failure`] and the exit code and last
+//!   log lines are kept as an [`ExitInfo`].
+//!
+//! Stopping still goes through `pg_ctl stop`, which finds the server through
+//! `postmaster.pid`.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+
+use crate::pg_enums::PgServerStatus;
+use crate::pg_errors::{Error, Result};
+use crate::pg_fetch::PostgresVersion;
+
+/// Number of output lines of the server kept for [`ExitInfo::log_tail`].
+pub const LOG_TAIL_LINES: usize = 50;
+
+/// Line the postmaster logs once it accepts connections.
+const READY_MESSAGE: &str = "database system is ready to accept connections";
+
+/// Status `postmaster.pid` reports once the server accepts connections.
+const PID_FILE_READY: &str = "ready";
+
+/// Interval between readiness checks.
+const PROBE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a server that did not become ready gets to shut down after
+/// `SIGINT` before it is killed.
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// How a supervised server process ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExitInfo {
+    /// Exit code, or `None` if the process was killed by a signal.
+    pub code: Option<i32>,
+    /// Signal that killed the process (Unix only).
+    pub signal: Option<i32>,
+    /// The last output lines of the server, oldest first.
+    pub log_tail: Vec<String>,
+}
+
+impl fmt::Display for ExitInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.code, self.signal) {
+            (Some(code), _) => write!(f, "postgres exited with code {}", code)?,
+            (None, Some(signal)) => write!(f, "postgres was killed by signal {}", signal)?,
+            (None, None) => write!(f, "postgres exited")?,
+        }
+        for line in &self.log_tail {
+            write!(f, "\n  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// What [`Supervisor::start`] needs to start and probe the server.
+pub(crate) struct SupervisedStart<'a> {
+    /// The `postgres` executable.
+    pub postgres_exe: &'a Path,
+    /// The cluster directory.
+    pub database_dir: &'a Path,
+    /// Port the server listens on.
+    pub port: u16,
+    /// Socket directory, if the server listens on a socket.
+    pub socket_dir: Option<&'a Path>,
+    /// Whether the server listens on TCP/IP.
+    pub listen_tcp: bool,
+    /// Version of the binaries; selects the readiness checks.
+    pub version: PostgresVersion,
+    /// Maximum time to wait for readiness.
+    pub timeout: Option<Duration>,
+}
+
+/// A running, supervised `postgres` process.
+pub(crate) struct Supervisor {
+    pid: Option<u32>,
+    log_tail: Arc<std::sync::Mutex<VecDeque<String>>>,
+    exit: Arc<std::sync::Mutex<Option<ExitInfo>>>,
+    stopping: Arc<AtomicBool>,
+    kill: Option<oneshot::Sender<()>>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    /// Spawns `postgres` and waits until it accepts connections.
+    ///
+    /// If the process exits later without [`Self::begin_stop`], the watcher
+    /// task sets `server_status` to [`PgServerStatus::Failure`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PgStartFailure`] if the process cannot be spawned.
+    /// Returns [`Error::PgExited`] if it exits before it is ready.
+    /// Returns [`Error::PgTimedOutError`] if it is not ready within
+    /// [`SupervisedStart::timeout`]; the process is shut down, and killed if
+    /// it does not exit within a grace period.
+    pub(crate) async fn start(
+        target: SupervisedStart<'_>,
+        server_status: Arc<Mutex<PgServerStatus>>,
+    ) -> Result<Supervisor> {
+        let mut child = tokio::process::Command::new(target.postgres_exe)
+            .arg("-D")
+            .arg(target.database_dir)
+            .args(["-F", "-p", &target.port.to_string()])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                log::error!("Could not spawn {}: {}", target.postgres_exe.display(), e);
+                Error::PgStartFailure
+            })?;
+        let pid = child.id();
+
+        let log_tail = Arc::new(std::sync::Mutex::new(VecDeque::with_capacity(LOG_TAIL_LINES)));
+        let ready = Arc::new(AtomicBool::new(false));
+        let mut readers = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(tokio::spawn(read_output(stdout, log_tail.clone(), ready.clone())));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(tokio::spawn(read_output(stderr, log_tail.clone(), ready.clone())));
+        }
+
+        let exit = Arc::new(std::sync::Mutex::new(None));
+        let stopping = Arc::new(AtomicBool::new(false));
+        let (kill, kill_requested) = oneshot::channel();
+        let watcher = tokio::spawn(watch(
+            child,
+            kill_requested,
+            readers,
+            log_tail.clone(),
+            exit.clone(),
+            stopping.clone(),
+            server_status,
+        ));
+        let mut supervisor = Supervisor {
+            pid,
+            log_tail,
+            exit,
+            stopping,
+            kill: Some(kill),
+            watcher: Some(watcher),
+        };
+
+        let deadline = target.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if ready.load(Ordering::Acquire) || is_ready(&target, pid).await {
+                return Ok(supervisor);
+            }
+            if let Some(info) = supervisor.exit_info() {
+                supervisor.wait().await;
+                return Err(Error::PgExited {
+                    code: info.code,
+                    log_tail: info.log_tail.join("\n"),
+                });
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                supervisor.begin_stop();
+                if let Some(kill) = supervisor.kill.take() {
+                    let _ = kill.send(());
+                }
+                supervisor.wait().await;
+                return Err(Error::PgTimedOutError);
+            }
+            tokio::time::sleep(PROBE_INTERVAL).await;
+        }
+    }
+
+    /// Returns the PID of the postmaster.
+    pub(crate) fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// Returns how the process ended, if it has.
+    pub(crate) fn exit_info(&self) -> Option<ExitInfo> {
+        self.exit.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Returns the last output lines of the server, oldest first.
+    pub(crate) fn log_tail(&self) -> Vec<String> {
+        let tail = self.log_tail.lock().unwrap_or_else(|e| e.into_inner());
+        tail.iter().cloned().collect()
+    }
+
+    /// Marks the coming exit as requested, so it does not count as a
+    /// failure.
+    pub(crate) fn begin_stop(&self) {
+        self.stopping.store(true, Ordering::Release);
+    }
+
+    /// Waits until the process has exited and its output is drained.
+    pub(crate) async fn wait(&mut self) {
+        if let Some(watcher) = self.watcher.take() {
+            let _ = watcher.await;
+        }
+    }
+}
+
+/// Stops `child` with a fast shutdown (`SIGINT`) and kills it only if it is
+/// still running after [`STOP_GRACE_PERIOD`], so that the postmaster can
+/// stop its children and remove its shared memory and `postmaster.pid`.
+/// Windows has no fast shutdown signal, so the process is killed at once.
+async fn shut_down(
+    child: &mut tokio::process::Child,
+) -> std::io::Result<std::process::ExitStatus> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        let interrupted = tokio::process::Command::new("kill")
+            .args(["-INT", &pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        if interrupted.is_ok_and(|status| status.success()) {
+            let deadline = Instant::now() + STOP_GRACE_PERIOD;
+            while Instant::now() < deadline {
+                if let Some(status) = child.try_wait()? {
+                    return Ok(status);
+                }
+                tokio::time::sleep(PROBE_INTERVAL).await;
+            }
+            log::warn!(
+                "postgres did not shut down within {:?}; killing it",
+                STOP_GRACE_PERIOD
+            );
+        }
+    }
+    let _ = child.start_kill();
+    child.wait().await
+}
+
+/// Waits for `child` to exit, or shuts it down on request, and records the
+/// exit.
+async fn watch(
+    mut child: tokio::process::Child,
+    kill_requested: oneshot::Receiver<()>,
+    readers: Vec<JoinHandle<()>>,
+    log_tail: Arc<std::sync::Mutex<VecDeque<String>>>,
+    exit: Arc<std::sync::Mutex<Option<ExitInfo>>>,
+    stopping: Arc<AtomicBool>,
+    server_status: Arc<Mutex<PgServerStatus>>,
+) {
+    let status = tokio::select! {
+        status = child.wait() => status,
+        Ok(()) = kill_requested => shut_down(&mut child).await,
+    };
+    // The output pipes close when the server and its children are gone.
+    for reader in readers {
+        let _ = reader.await;
+    }
+    let (code, signal) = match &status {
+        Ok(status) => (status.code(), exit_signal(status)),
+        Err(e) => {
+            log::error!("Could not wait for postgres: {}", e);
+            (None, None)
+        }
+    };
+    let info = ExitInfo {
+        code,
+        signal,
+        log_tail: log_tail
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect(),
+    };
+    if !stopping.load(Ordering::Acquire) {
+        log::error!("{}", info);
+        *server_status.lock().await = PgServerStatus::Failure;
+    }
+    *exit.lock().unwrap_or_else(|e| e.into_inner()) = Some(info);
+}
+
+/// Logs each line of `output`, keeps the last [`LOG_TAIL_LINES`] in
+/// `log_tail` and sets `ready` once the server reports readiness.
+async fn read_output<R: AsyncRead + Unpin>(
+    output: R,
+    log_tail: Arc<std::sync::Mutex<VecDeque<String>>>,
+    ready: Arc<AtomicBool>,
+) {
+    let mut lines = BufReader::new(output).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                log::info!("{}", line);
+                if line.contains(READY_MESSAGE) {
+                    ready.store(true, Ordering::Release);
+                }
+                let mut tail = log_tail.lock().unwrap_or_else(|e| e.into_inner());
+                if tail.len() == LOG_TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("Error reading postgres output: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Returns `true` if `postmaster.pid` belongs to `pid` and reports `ready`,
+/// or, before PostgreSQL 10, if a connection to the server succeeds.
+async fn is_ready(target: &SupervisedStart<'_>, pid: Option<u32>) -> bool {
+    if target.version.major() < 10 {
+        return accepts_connections(target).await;
+    }
+    let Ok(contents) = tokio::fs::read_to_string(target.database_dir.join("postmaster.pid")).await
+    else {
+        return false;
+    };
+    pid_file_ready(&contents, pid)
+}
+
+/// Parses `postmaster.pid`: the first line is the PID, the eighth the
+/// status.
+fn pid_file_ready(contents: &str, pid: Option<u32>) -> bool {
+    let lines: Vec<&str> = contents.lines().collect();
+    let owner = lines.first().and_then(|line| line.trim().parse::<u32>().ok());
+    let status = lines.get(7).map(|line| line.trim());
+    owner.is_some() && owner == pid && status == Some(PID_FILE_READY)
+}
+
+/// Returns `true` if the server accepts a TCP/IP or socket connection.
+async fn accepts_connections(target: &SupervisedStart<'_>) -> bool {
+    if target.listen_tcp
+        && tokio::net::TcpStream::connect(("127.0.0.1", target.port)).await.is_ok()
+    {
+        return true;
+    }
+    #[cfg(unix)]
+    if let Some(dir) = target.socket_dir {
+        let socket: PathBuf = dir.join(format!(".s.PGSQL.{}", target.port));
+        return tokio::net::UnixStream::connect(socket).await.is_ok();
+    }
+    false
+}
+
+/// Returns the signal that terminated the process (Unix only).
+fn exit_signal(status: &std::process::ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_ready() {
+        let pid_file = |status: &str| {
+            format!(
+                "4242\n/data/db\n1700000000\n5432\n/tmp\nlocalhost\n  5432001    32768\n{}\n",
+                status
+            )
+        };
+        assert!(pid_file_ready(&pid_file("ready   "), Some(4242)));
+        assert!(!pid_file_ready(&pid_file("starting"), Some(4242)));
+        // A stale file from an earlier server.
+        assert!(!pid_file_ready(&pid_file("ready   "), Some(1)));
+        assert!(!pid_file_ready("4242\n/data/db\n", Some(4242)));
+        assert!(!pid_file_ready("", None));
+    }
+
+    #[test]
+    fn test_exit_info_display() {
+        let info = ExitInfo {
+            code: Some(1),
+            signal: None,
+            log_tail: vec!["FATAL:  lock file \"postmaster.pid\" already exists".to_string()],
+        };
+        assert_eq!(
+            info.to_string(),
+            "postgres exited with code 1\n  FATAL:  lock file \"postmaster.pid\" already exists"
+        );
+        let killed = ExitInfo {
+            code: None,
+            signal: Some(9),
+            log_tail: Vec::new(),
+        };
+        assert_eq!(killed.to_string(), "postgres was killed by signal 9");
+    }
+
+    /// Writes an executable shell script standing in for `postgres`.
+    #[cfg(unix)]
+    fn fake_postgres(dir: &Path, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let script = dir.join("postgres");
+        std::fs::write(&script, format!("#!/bin/sh\n{}", body)).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unexpected_exit_is_a_failure() -> Result<()> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = fake_postgres(dir.path(), "echo \"FATAL:  no luck\" >&2\nexit 3\n");
+        let status = Arc::new(Mutex::new(PgServerStatus::Starting));
+        let target = SupervisedStart {
+            postgres_exe: &script,
+            database_dir: dir.path(),
+            port: 5432,
+            socket_dir: None,
+            listen_tcp: true,
+            version: crate::pg_fetch::PG_V17,
+            timeout: Some(Duration::from_secs(10)),
+        };
+        let result = Supervisor::start(target, status.clone()).await;
+        assert_eq!(
+            result.err(),
+            Some(Error::PgExited {
+                code: Some(3),
+                log_tail: "FATAL:  no luck".to_string(),
+            })
+        );
+        assert_eq!(*status.lock().await, PgServerStatus::Failure);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timeout_kills_the_process() -> Result<()> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = fake_postgres(dir.path(), "exec sleep 30\n");
+        let status = Arc::new(Mutex::new(PgServerStatus::Starting));
+        let target = SupervisedStart {
+            postgres_exe: &script,
+            database_dir: dir.path(),
+            port: 5432,
+            socket_dir: None,
+            listen_tcp: true,
+            version: crate::pg_fetch::PG_V17,
+            timeout: Some(Duration::from_millis(200)),
+        };
+        let started = Instant::now();
+        let result = Supervisor::start(target, status.clone()).await;
+        assert_eq!(result.err(), Some(Error::PgTimedOutError));
+        assert_eq!(*status.lock().await, PgServerStatus::Starting);
+        // Interrupted, not left to the grace period.
+        assert!(
+            started.elapsed() < STOP_GRACE_PERIOD,
+            "{:?}",
+            started.elapsed()
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timeout_kills_a_process_ignoring_sigint() -> Result<()> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = fake_postgres(dir.path(), "trap '' INT\nexec sleep 30\n");
+        let status = Arc::new(Mutex::new(PgServerStatus::Starting));
+        let target = SupervisedStart {
+            postgres_exe: &script,
+            database_dir: dir.path(),
+            port: 5432,
+            socket_dir: None,
+            listen_tcp: true,
+            version: crate::pg_fetch::PG_V17,
+            timeout: Some(Duration::from_millis(200)),
+        };
+        let started = Instant::now();
+        let result = Supervisor::start(target, status.clone()).await;
+        assert_eq!(result.err(), Some(Error::PgTimedOutError));
+        assert!(started.elapsed() >= STOP_GRACE_PERIOD);
+        assert!(started.elapsed() < Duration::from_secs(30));
+        Ok(())
+    }
+}
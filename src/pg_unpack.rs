@@ -4,26 +4,335 @@
 //! [zonkyio/embedded-postgres-binaries](https://github.com/zonkyio/embedded-postgres-binaries)
 //! contains a single `.txz`-compressed tarball (a tar archive compressed with
 //! XZ/LZMA2, sometimes also named `.tar.xz`).  [`unpack_postgres`] locates
-//! that entry, decompresses it with [`lzma_rs`], and extracts the resulting
-//! tar archive into `cache_dir`.
+//! that entry, decompresses it with [`lzma_rust2`], and extracts the resulting
+//! tar archive into `cache_dir`.  Plain `.tar.gz`, `.tar.xz` and `.tar.zst`
+//! bundles are supported as well; see [`BundleFormat`].
+//!
+//! The ZIP entry, the decoder and the tar extractor are chained as readers,
+//! so the archive is never held in memory: besides small I/O buffers, memory
+//! use is bounded by the decoder's window (8 MiB for the zonky bundles).
 //!
 //! All I/O runs inside [`tokio::task::spawn_blocking`] so it does not block
-//! the async executor.
+//! the async executor.  Progress can be observed through
+//! [`UnpackOptions::progress`].  Every tar entry is validated against an
+//! [`ExtractionPolicy`] before it is written.
 
+use std::ffi::OsString;
 use std::fs;
-use std::io::{Cursor, Read};
-use std::path::Path;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
 
-use tar::Archive;
+use lzma_rust2::XzReader;
+use tar::{Archive, Entry, EntryType};
 use zip::ZipArchive;
 
 use crate::pg_errors::{Error, Result};
+use crate::pg_progress::{Progress, ProgressEvent};
+
+/// Buffer between the ZIP entry and the XZ decoder.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Symlinks followed while resolving one link target, like Linux's
+/// `MAXSYMLINKS`.
+const MAX_LINK_HOPS: usize = 40;
+
+/// Checks applied to every tar entry before it is extracted.
+///
+/// Bundles may come from arbitrary mirrors, so by default every entry is
+/// validated and the first offending one aborts extraction with an error
+/// naming it:
+///
+/// | Entry | Error |
+/// |-------|-------|
+/// | Absolute path or `..` component, or replacing an extracted symlink | [`Error::ArchivePathTraversal`] |
+/// | Symlink or hardlink resolving outside the destination | [`Error::ArchiveLinkEscape`] |
+/// | Block or character device, FIFO | [`Error::ArchiveSpecialFile`] |
+/// | Larger than [`Self::max_entry_size`] | [`Error::ArchiveEntryTooLarge`] |
+/// | Beyond [`Self::max_entries`] | [`Error::ArchiveTooManyEntries`] |
+///
+/// Link targets are resolved the way the file system will resolve them,
+/// following the symlinks extracted before, so a chain of links that each
+/// look harmless cannot lead outside the destination either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractionPolicy {
+    /// Run the checks above.  When `false`, only the `tar` crate's own
+    /// protections apply.  Defaults to `true`.
+    pub hardened: bool,
+    /// Maximum size of a single entry in bytes.  Defaults to 1 GiB.
+    pub max_entry_size: u64,
+    /// Maximum number of entries.  Defaults to 100 000.
+    pub max_entries: u64,
+}
+
+impl Default for ExtractionPolicy {
+    fn default() -> Self {
+        ExtractionPolicy {
+            hardened: true,
+            max_entry_size: 1024 * 1024 * 1024,
+            max_entries: 100_000,
+        }
+    }
+}
+
+impl ExtractionPolicy {
+    /// Validates the `index`-th (1-based) entry of an archive.
+    ///
+    /// `path` is where the entry will be written, relative to `dest`;
+    /// symlink targets are resolved against its parent.  Does nothing unless
+    /// [`Self::hardened`] is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error listed in the table on [`ExtractionPolicy`] for the
+    /// first check the entry fails.
+    pub(crate) fn check<R: Read>(
+        &self,
+        entry: &Entry<R>,
+        dest: &Path,
+        path: &Path,
+        index: u64,
+    ) -> Result<()> {
+        if !self.hardened {
+            return Ok(());
+        }
+        let name = || path.display().to_string();
+        if index > self.max_entries {
+            return Err(Error::ArchiveTooManyEntries {
+                entry: name(),
+                limit: self.max_entries,
+            });
+        }
+        if resolve_inside(Path::new(""), path).is_none() {
+            return Err(Error::ArchivePathTraversal(name()));
+        }
+        // Links are only checked when they are written; replacing one later
+        // would change what the links checked since resolve to.
+        if dest
+            .join(path)
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            return Err(Error::ArchivePathTraversal(name()));
+        }
+        let header = entry.header();
+        let size = header.size().map_err(|_| Error::UnpackFailure)?;
+        if size > self.max_entry_size {
+            return Err(Error::ArchiveEntryTooLarge {
+                entry: name(),
+                size,
+                limit: self.max_entry_size,
+            });
+        }
+        let base = match header.entry_type() {
+            EntryType::Block | EntryType::Char | EntryType::Fifo => {
+                return Err(Error::ArchiveSpecialFile(name()));
+            }
+            // Symlink targets are relative to the link's directory, hardlink
+            // targets to the archive root.
+            EntryType::Symlink => path.parent().unwrap_or(Path::new("")),
+            EntryType::Link => Path::new(""),
+            _ => return Ok(()),
+        };
+        let target = entry
+            .link_name()
+            .map_err(|_| Error::UnpackFailure)?
+            .ok_or_else(|| Error::ArchiveLinkEscape {
+                entry: name(),
+                target: String::new(),
+            })?;
+        if resolve_on_disk(dest, base, &target).is_none() {
+            return Err(Error::ArchiveLinkEscape {
+                entry: name(),
+                target: target.display().to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Lexically joins relative `path` onto relative `base`.
+///
+/// Returns `None` if `path` is absolute or its `..` components climb above
+/// the root `base` is relative to.
+fn resolve_inside(base: &Path, path: &Path) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    for component in base.components().chain(path.components()) {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(resolved)
+}
+
+/// One component of a path walked by [`resolve_on_disk`].
+enum Step {
+    Name(OsString),
+    Parent,
+}
+
+impl Step {
+    /// Splits relative `path` into steps; `None` if it is absolute.
+    fn split(path: &Path) -> Option<Vec<Step>> {
+        let mut steps = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => steps.push(Step::Name(part.to_os_string())),
+                Component::ParentDir => steps.push(Step::Parent),
+                Component::CurDir => {}
+                Component::RootDir | Component::Prefix(_) => return None,
+            }
+        }
+        Some(steps)
+    }
+}
+
+/// Joins relative `path` onto relative `base` below `dest` the way the file
+/// system will, following the symlinks that already exist there.
+///
+/// Components of `base` that do not exist yet are taken to be directories,
+/// as they are created before the entry is written.  Returns `None` if the
+/// result would lie outside `dest`, a link is absolute or loops, or `..`
+/// follows a component of `path` that does not exist yet or is no
+/// directory, because a later entry could still turn it into a symlink.
+fn resolve_on_disk(dest: &Path, base: &Path, path: &Path) -> Option<PathBuf> {
+    // A stack of steps still to walk, each marked with whether it belongs
+    // to `base`.
+    let mut pending: Vec<(Step, bool)> = Step::split(path)?
+        .into_iter()
+        .rev()
+        .map(|step| (step, false))
+        .chain(Step::split(base)?.into_iter().rev().map(|step| (step, true)))
+        .collect();
+    let mut resolved = PathBuf::new();
+    let mut unsettled = false;
+    let mut hops = 0;
+    while let Some((step, in_base)) = pending.pop() {
+        let name = match step {
+            Step::Parent => {
+                if unsettled || !resolved.pop() {
+                    return None;
+                }
+                continue;
+            }
+            Step::Name(name) => name,
+        };
+        let candidate = resolved.join(&name);
+        if unsettled {
+            resolved = candidate;
+            continue;
+        }
+        match dest.join(&candidate).symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                hops += 1;
+                if hops > MAX_LINK_HOPS {
+                    return None;
+                }
+                let target = fs::read_link(dest.join(&candidate)).ok()?;
+                pending.extend(
+                    Step::split(&target)?
+                        .into_iter()
+                        .rev()
+                        .map(|step| (step, in_base)),
+                );
+            }
+            Ok(metadata) => {
+                unsettled = !metadata.is_dir() && !in_base;
+                resolved = candidate;
+            }
+            Err(_) => {
+                unsettled = !in_base;
+                resolved = candidate;
+            }
+        }
+    }
+    Some(resolved)
+}
+
+/// Archive formats [`unpack_postgres_with`] understands.
+///
+/// Every format wraps a tar archive of the PostgreSQL installation (`bin/`,
+/// `lib/`, `share/`, …).  Unless a format is configured through
+/// [`UnpackOptions::format`] it is detected from the file's first bytes with
+/// [`BundleFormat::detect`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BundleFormat {
+    /// A ZIP archive holding a `.txz`/`.xz` tarball, as published by
+    /// [zonkyio/embedded-postgres-binaries](https://github.com/zonkyio/embedded-postgres-binaries).
+    ZonkyJar,
+    /// A gzip-compressed tarball (`.tar.gz`, `.tgz`), e.g. the
+    /// [theseus-rs/postgresql-binaries](https://github.com/theseus-rs/postgresql-binaries)
+    /// releases.
+    TarGz,
+    /// An XZ-compressed tarball (`.tar.xz`, `.txz`).
+    TarXz,
+    /// A Zstandard-compressed tarball (`.tar.zst`).
+    TarZst,
+}
+
+impl BundleFormat {
+    /// Detects the format from the first bytes of a file.
+    ///
+    /// # Returns
+    ///
+    /// The format whose magic number `head` starts with, or `None`.
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        const MAGIC: [(&[u8], BundleFormat); 4] = [
+            (b"PK\x03\x04", BundleFormat::ZonkyJar),
+            (b"\x1f\x8b", BundleFormat::TarGz),
+            (b"\xfd7zXZ\x00", BundleFormat::TarXz),
+            (b"\x28\xb5\x2f\xfd", BundleFormat::TarZst),
+        ];
+        MAGIC
+            .iter()
+            .find(|(magic, _)| head.starts_with(magic))
+            .map(|(_, format)| *format)
+    }
+
+    /// Detects the format of the file at `path`.
+    fn detect_file(path: &Path) -> Result<Self> {
+        let mut head = Vec::with_capacity(8);
+        fs::File::open(path)
+            .and_then(|file| file.take(8).read_to_end(&mut head))
+            .map_err(|e| Error::ReadFileError(e.to_string()))?;
+        Self::detect(&head).ok_or_else(|| {
+            log::error!("{} is not a supported PostgreSQL bundle", path.display());
+            Error::InvalidPgPackage
+        })
+    }
+}
+
+/// Options for [`unpack_postgres_with`].
+#[derive(Debug, Clone, Default)]
+pub struct UnpackOptions {
+    /// Receives [`ProgressEvent::UnpackStarted`], one
+    /// [`ProgressEvent::EntryExtracted`] per tar entry, and
+    /// [`ProgressEvent::UnpackFinished`].
+    pub progress: Progress,
+    /// Checks applied to every tar entry.  Hardened by default.
+    pub extraction: ExtractionPolicy,
+    /// Format of the archive.  `None` detects it from the file's content.
+    pub format: Option<BundleFormat>,
+    /// Leading path components to remove from every tar entry, like
+    /// `tar --strip-components`.  Entries with no components left are
+    /// skipped.  Defaults to `0`.
+    pub strip_components: usize,
+}
 
 /// Unpacks the PostgreSQL binaries ZIP/JAR into `cache_dir`.
 ///
 /// Spawns a blocking task that opens `zip_file_path`, finds the `.txz` entry
-/// (XZ-compressed tarball), decompresses it, and extracts the tar archive into
-/// `cache_dir`.
+/// (XZ-compressed tarball), and streams it through the XZ decoder into the
+/// tar extractor writing to `cache_dir`.
+///
+/// Equivalent to [`unpack_postgres_with`] with default [`UnpackOptions`],
+/// so other [`BundleFormat`]s are accepted as well.
 ///
 /// # Arguments
 ///
@@ -37,42 +346,216 @@ use crate::pg_errors::{Error, Result};
 /// entry cannot be read.
 /// Returns [`Error::UnpackFailure`] if XZ decompression or tar extraction
 /// fails.
+/// Returns the errors listed on [`ExtractionPolicy`] for unsafe entries.
 /// Returns [`Error::PgError`] if the blocking task panics or cannot be joined.
 pub async fn unpack_postgres(zip_file_path: &Path, cache_dir: &Path) -> Result<()> {
+    unpack_postgres_with(zip_file_path, cache_dir, &UnpackOptions::default()).await
+}
+
+/// Unpacks a PostgreSQL bundle in any [`BundleFormat`] into `cache_dir`
+/// with `options`.
+///
+/// The file is streamed through the matching decoder into the tar
+/// extractor; see [`unpack_postgres`].
+///
+/// # Errors
+///
+/// The same as [`unpack_postgres`].  [`Error::InvalidPgPackage`] is also
+/// returned if the format cannot be detected.
+pub async fn unpack_postgres_with(
+    zip_file_path: &Path,
+    cache_dir: &Path,
+    options: &UnpackOptions,
+) -> Result<()> {
     let zip_file_path = zip_file_path.to_path_buf();
     let cache_dir = cache_dir.to_path_buf();
-    tokio::task::spawn_blocking(move || unpack_postgres_blocking(&zip_file_path, &cache_dir))
-        .await
-        .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))?
+    let options = options.clone();
+    tokio::task::spawn_blocking(move || {
+        unpack_postgres_blocking(&zip_file_path, &cache_dir, &options)
+    })
+    .await
+    .map_err(|e| Error::PgError(e.to_string(), "spawn_blocking join error".into()))?
 }
 
 /// Blocking implementation of the unpack logic.
-fn unpack_postgres_blocking(zip_file_path: &Path, cache_dir: &Path) -> Result<()> {
-    let zip_file =
-        fs::File::open(zip_file_path).map_err(|e| Error::ReadFileError(e.to_string()))?;
-    let mut jar_archive =
-        ZipArchive::new(zip_file).map_err(|_| Error::InvalidPgPackage)?;
-
-    for i in 0..jar_archive.len() {
-        let mut file = jar_archive
-            .by_index(i)
-            .map_err(|_| Error::InvalidPgPackage)?;
-
-        if file.name().ends_with(".txz") || file.name().ends_with(".xz") {
-            let mut xz_content = Vec::with_capacity(file.compressed_size() as usize);
-            file.read_to_end(&mut xz_content)
-                .map_err(|e| Error::ReadFileError(e.to_string()))?;
-
-            let mut tar_content = Vec::new();
-            lzma_rs::xz_decompress(&mut Cursor::new(&xz_content), &mut tar_content)
-                .map_err(|_| Error::UnpackFailure)?;
+fn unpack_postgres_blocking(
+    zip_file_path: &Path,
+    cache_dir: &Path,
+    options: &UnpackOptions,
+) -> Result<()> {
+    let format = match options.format {
+        Some(format) => format,
+        None => BundleFormat::detect_file(zip_file_path)?,
+    };
+    let file = fs::File::open(zip_file_path).map_err(|e| Error::ReadFileError(e.to_string()))?;
+    let buffered = || BufReader::with_capacity(READ_BUFFER_SIZE, file);
 
-            Archive::new(Cursor::new(tar_content))
-                .unpack(cache_dir)
+    options.progress.emit(ProgressEvent::UnpackStarted {
+        archive: zip_file_path.to_path_buf(),
+    });
+    fs::create_dir_all(cache_dir).map_err(|e| Error::DirCreationError(e.to_string()))?;
+    let mut entries = 0u64;
+    match format {
+        BundleFormat::ZonkyJar => {
+            let mut jar_archive =
+                ZipArchive::new(buffered()).map_err(|_| Error::InvalidPgPackage)?;
+            for i in 0..jar_archive.len() {
+                let file = jar_archive
+                    .by_index(i)
+                    .map_err(|_| Error::InvalidPgPackage)?;
+                if file.name().ends_with(".txz") || file.name().ends_with(".xz") {
+                    let xz_reader =
+                        XzReader::new(BufReader::with_capacity(READ_BUFFER_SIZE, file), false);
+                    extract_tar(xz_reader, cache_dir, options, &mut entries)?;
+                }
+            }
+        }
+        BundleFormat::TarGz => {
+            let reader = flate2::bufread::MultiGzDecoder::new(buffered());
+            extract_tar(reader, cache_dir, options, &mut entries)?;
+        }
+        BundleFormat::TarXz => {
+            extract_tar(XzReader::new(buffered(), true), cache_dir, options, &mut entries)?;
+        }
+        BundleFormat::TarZst => {
+            let reader = zstd::stream::read::Decoder::with_buffer(buffered())
                 .map_err(|_| Error::UnpackFailure)?;
+            extract_tar(reader, cache_dir, options, &mut entries)?;
         }
     }
+    options
+        .progress
+        .emit(ProgressEvent::UnpackFinished { entries });
+
+    Ok(())
+}
 
+/// Extracts the tar stream `reader` into `cache_dir`, counting extracted
+/// entries in `entries`.
+fn extract_tar<R: Read>(
+    reader: R,
+    cache_dir: &Path,
+    options: &UnpackOptions,
+    entries: &mut u64,
+) -> Result<()> {
+    let canonical_dir = fs::canonicalize(cache_dir).map_err(|e| Error::ReadFileError(e.to_string()))?;
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries().map_err(|_| Error::UnpackFailure)? {
+        let mut entry = entry.map_err(|_| Error::UnpackFailure)?;
+        let path = entry.path().map_err(|_| Error::UnpackFailure)?;
+        let Some(path) = strip_components(&path, options.strip_components) else {
+            continue;
+        };
+        *entries += 1;
+        options.extraction.check(&entry, cache_dir, &path, *entries)?;
+        if options.strip_components == 0 {
+            entry.unpack_in(cache_dir).map_err(|_| Error::UnpackFailure)?;
+        } else {
+            unpack_stripped(&mut entry, cache_dir, &canonical_dir, &path, options.strip_components)?;
+        }
+        options.progress.emit(ProgressEvent::EntryExtracted {
+            path,
+            entries: *entries,
+        });
+    }
+    // The tar reader stops at the end-of-archive marker; read the rest of
+    // the compressed stream so its trailing checksum is still verified.
+    io::copy(&mut archive.into_inner(), &mut io::sink()).map_err(|_| Error::UnpackFailure)?;
+    Ok(())
+}
+
+/// Removes the first `count` components of `path`, ignoring `.`.
+///
+/// Returns `None` if nothing is left.  A path whose removed part is not made
+/// of plain names (e.g. `/` or `..`) is returned unchanged so that it is
+/// still rejected.
+fn strip_components(path: &Path, count: usize) -> Option<PathBuf> {
+    let mut components = path
+        .components()
+        .filter(|component| *component != Component::CurDir);
+    let stripped: Vec<_> = components.by_ref().take(count).collect();
+    if !stripped.iter().all(|component| matches!(component, Component::Normal(_))) {
+        return Some(path.to_path_buf());
+    }
+    let rest: PathBuf = components.collect();
+    (!rest.as_os_str().is_empty()).then_some(rest)
+}
+
+/// Extracts `entry` to `path` below `cache_dir` when its name differs from
+/// the archive's (stripped components, or a bundle prefix), with the same
+/// protections as [`Entry::unpack_in`]: no `..`, no creating directories or
+/// writing through links leading outside `cache_dir`, and hardlink targets
+/// resolved (and stripped) inside it.  An entry replacing an extracted
+/// symlink is rejected as well.
+pub(crate) fn unpack_stripped<R: Read>(
+    entry: &mut Entry<R>,
+    cache_dir: &Path,
+    canonical_dir: &Path,
+    path: &Path,
+    strip: usize,
+) -> Result<()> {
+    if !path.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(Error::UnpackFailure);
+    }
+    let target = cache_dir.join(path);
+    if let Some(parent) = target.parent() {
+        create_dir_inside(canonical_dir, parent)?;
+    }
+    if target
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+    {
+        log::error!("{} would replace an extracted symlink", path.display());
+        return Err(Error::UnpackFailure);
+    }
+    if entry.header().entry_type() == EntryType::Link {
+        let link = entry
+            .link_name()
+            .map_err(|_| Error::UnpackFailure)?
+            .and_then(|link| strip_components(&link, strip))
+            .filter(|link| link.components().all(|c| matches!(c, Component::Normal(_))))
+            .ok_or(Error::UnpackFailure)?;
+        fs::hard_link(cache_dir.join(link), &target).map_err(|_| Error::UnpackFailure)?;
+    } else {
+        entry.unpack(&target).map_err(|_| Error::UnpackFailure)?;
+    }
+    Ok(())
+}
+
+/// Creates `dir` and its missing ancestors, checking before each step that
+/// the directory it is created in resolves inside `canonical_dir`, so that
+/// symlinks extracted earlier cannot redirect it.
+fn create_dir_inside(canonical_dir: &Path, dir: &Path) -> Result<()> {
+    let mut missing = Vec::new();
+    let mut ancestor = dir;
+    while ancestor.symlink_metadata().is_err() {
+        missing.push(ancestor);
+        match ancestor.parent() {
+            Some(parent) => ancestor = parent,
+            None => break,
+        }
+    }
+    for ancestor in missing.into_iter().rev() {
+        if let Some(parent) = ancestor.parent() {
+            ensure_inside(canonical_dir, parent)?;
+        }
+        fs::create_dir(ancestor).map_err(|e| Error::DirCreationError(e.to_string()))?;
+    }
+    ensure_inside(canonical_dir, dir)
+}
+
+/// Fails unless `path` resolves to `canonical_dir` or a path below it.
+fn ensure_inside(canonical_dir: &Path, path: &Path) -> Result<()> {
+    let canonical = fs::canonicalize(path).map_err(|e| Error::ReadFileError(e.to_string()))?;
+    if !canonical.starts_with(canonical_dir) {
+        log::error!(
+            "{} resolves to {}, outside {}",
+            path.display(),
+            canonical.display(),
+            canonical_dir.display()
+        );
+        return Err(Error::UnpackFailure);
+    }
     Ok(())
 }
 
@@ -80,7 +563,7 @@ fn unpack_postgres_blocking(zip_file_path: &Path, cache_dir: &Path) -> Result<()
 mod tests {
     use super::*;
     use std::fs::File;
-    use std::io::Write;
+    use std::io::{Cursor, Write};
     use tempfile::tempdir;
     use zip::write::{SimpleFileOptions, ZipWriter};
 
@@ -124,6 +607,291 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_unpack_reports_progress() -> Result<()> {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let cache_dir = temp_dir.path().join("cache");
+        let zip_file_path = temp_dir.path().join("test_archive.zip");
+        write_jar(&zip_file_path, &create_dummy_tar_content());
+
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let options = UnpackOptions {
+            progress: Progress::new(move |event: &ProgressEvent| {
+                recorded.lock().unwrap().push(event.clone())
+            }),
+            ..Default::default()
+        };
+        unpack_postgres_with(&zip_file_path, &cache_dir, &options).await?;
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ProgressEvent::UnpackStarted {
+                    archive: zip_file_path.clone()
+                },
+                ProgressEvent::EntryExtracted {
+                    path: "dummy_file.txt".into(),
+                    entries: 1
+                },
+                ProgressEvent::UnpackFinished { entries: 1 },
+            ]
+        );
+        Ok(())
+    }
+
+    type RawEntry<'a> = (EntryType, &'a str, &'a str, &'a [u8]);
+
+    /// Unpacks a bundle made of `entries` with `extraction` below `temp_dir`.
+    async fn unpack_raw(
+        temp_dir: &Path,
+        entries: &[RawEntry<'_>],
+        extraction: ExtractionPolicy,
+    ) -> Result<PathBuf> {
+        let zip_file_path = temp_dir.join("test_archive.zip");
+        write_jar(&zip_file_path, &raw_tar_content(entries));
+        let cache_dir = temp_dir.join("cache");
+        let options = UnpackOptions {
+            extraction,
+            ..Default::default()
+        };
+        unpack_postgres_with(&zip_file_path, &cache_dir, &options).await?;
+        Ok(cache_dir)
+    }
+
+    #[tokio::test]
+    async fn test_hardened_extraction_rejects_unsafe_entries() {
+        use EntryType::{Char, Link, Regular, Symlink};
+        let policy = ExtractionPolicy {
+            max_entry_size: 8,
+            max_entries: 3,
+            ..Default::default()
+        };
+        let cases: Vec<(Vec<RawEntry>, Error)> = vec![
+            (
+                vec![(Regular, "/etc/passwd", "", b"x")],
+                Error::ArchivePathTraversal("/etc/passwd".into()),
+            ),
+            (
+                vec![(Regular, "bin/../../escape", "", b"x")],
+                Error::ArchivePathTraversal("bin/../../escape".into()),
+            ),
+            (
+                vec![(Symlink, "lib/evil", "../../etc", b"")],
+                Error::ArchiveLinkEscape {
+                    entry: "lib/evil".into(),
+                    target: "../../etc".into(),
+                },
+            ),
+            (
+                vec![(Symlink, "lib/absolute", "/etc/passwd", b"")],
+                Error::ArchiveLinkEscape {
+                    entry: "lib/absolute".into(),
+                    target: "/etc/passwd".into(),
+                },
+            ),
+            (
+                // Each target stays inside on its own, but `b` is the
+                // destination itself, so `b/..` is its parent.
+                vec![
+                    (Symlink, "b", ".", b""),
+                    (Symlink, "a1", "b/..", b""),
+                    (Symlink, "a2", "a1/..", b""),
+                    (Symlink, "a3", "a2/..", b""),
+                    (Symlink, "a4", "a3/..", b""),
+                    (Symlink, "a5", "a4/..", b""),
+                    (Symlink, "a6", "a5/..", b""),
+                ],
+                Error::ArchiveLinkEscape {
+                    entry: "a1".into(),
+                    target: "b/..".into(),
+                },
+            ),
+            (
+                // `d` could still become a symlink to `.`.
+                vec![(Symlink, "x", "d/..", b"")],
+                Error::ArchiveLinkEscape {
+                    entry: "x".into(),
+                    target: "d/..".into(),
+                },
+            ),
+            (
+                vec![
+                    (Symlink, "lib/x", "libpq.so", b""),
+                    (Symlink, "lib/x", "..", b""),
+                ],
+                Error::ArchivePathTraversal("lib/x".into()),
+            ),
+            (
+                vec![(Link, "bin/hard", "../outside", b"")],
+                Error::ArchiveLinkEscape {
+                    entry: "bin/hard".into(),
+                    target: "../outside".into(),
+                },
+            ),
+            (
+                vec![(Char, "dev/null", "", b"")],
+                Error::ArchiveSpecialFile("dev/null".into()),
+            ),
+            (
+                vec![(Regular, "bin/big", "", b"0123456789")],
+                Error::ArchiveEntryTooLarge {
+                    entry: "bin/big".into(),
+                    size: 10,
+                    limit: 8,
+                },
+            ),
+            (
+                vec![
+                    (Regular, "a", "", b""),
+                    (Regular, "b", "", b""),
+                    (Regular, "c", "", b""),
+                    (Regular, "d", "", b""),
+                ],
+                Error::ArchiveTooManyEntries {
+                    entry: "d".into(),
+                    limit: 3,
+                },
+            ),
+        ];
+        for (entries, expected) in cases {
+            let temp_dir = tempdir().expect("Failed to create temp dir");
+            assert_eq!(
+                unpack_raw(temp_dir.path(), &entries, policy).await,
+                Err(expected)
+            );
+        }
+
+        let relaxed = ExtractionPolicy {
+            hardened: false,
+            ..policy
+        };
+        let big = [(Regular, "bin/big", "", &b"0123456789"[..])];
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        assert!(unpack_raw(temp_dir.path(), &big, relaxed).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_hardened_extraction_accepts_relative_links() -> Result<()> {
+        use EntryType::{Directory, Link, Regular, Symlink};
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let cache_dir = unpack_raw(
+            temp_dir.path(),
+            &[
+                (Directory, "lib/", "", b""),
+                (Regular, "lib/libpq.so.5", "", b"elf"),
+                (Symlink, "lib/libpq.so", "libpq.so.5", b""),
+                (Directory, "bin/", "", b""),
+                (Symlink, "bin/libpq", "../lib/./libpq.so.5", b""),
+                (Link, "bin/libpq-hard", "lib/libpq.so.5", b""),
+                (Symlink, "lib/self", ".", b""),
+                (Symlink, "bin/through", "../lib/self/../lib/libpq.so", b""),
+            ],
+            ExtractionPolicy::default(),
+        )
+        .await?;
+        assert_eq!(std::fs::read(cache_dir.join("lib/libpq.so")).unwrap(), b"elf");
+        assert_eq!(std::fs::read(cache_dir.join("bin/libpq")).unwrap(), b"elf");
+        assert_eq!(std::fs::read(cache_dir.join("bin/libpq-hard")).unwrap(), b"elf");
+        assert_eq!(std::fs::read(cache_dir.join("bin/through")).unwrap(), b"elf");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unpack_tarball_formats() -> Result<()> {
+        use EntryType::{Directory, Link, Regular, Symlink};
+        let tar_content = raw_tar_content(&[
+            (Directory, "postgresql-16.4.0/", "", b""),
+            (Directory, "postgresql-16.4.0/bin/", "", b""),
+            (Regular, "postgresql-16.4.0/bin/initdb", "", b"elf"),
+            (Link, "postgresql-16.4.0/bin/initdb-hard", "postgresql-16.4.0/bin/initdb", b""),
+            (Symlink, "postgresql-16.4.0/bin/initdb-link", "initdb", b""),
+        ]);
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&tar_content).unwrap();
+        let bundles = [
+            ("pg.tar.gz", gz.finish().unwrap(), BundleFormat::TarGz),
+            ("pg.tar.xz", compress_with_xz(&tar_content), BundleFormat::TarXz),
+            (
+                "pg.tar.zst",
+                zstd::encode_all(Cursor::new(&tar_content), 3).unwrap(),
+                BundleFormat::TarZst,
+            ),
+        ];
+        for (name, content, format) in bundles {
+            assert_eq!(BundleFormat::detect(&content), Some(format));
+            let temp_dir = tempdir().expect("Failed to create temp dir");
+            let archive = temp_dir.path().join(name);
+            fs::write(&archive, &content).unwrap();
+            let cache_dir = temp_dir.path().join("cache");
+            let options = UnpackOptions {
+                strip_components: 1,
+                ..Default::default()
+            };
+            unpack_postgres_with(&archive, &cache_dir, &options).await?;
+            for file in ["bin/initdb", "bin/initdb-hard", "bin/initdb-link"] {
+                assert_eq!(fs::read(cache_dir.join(file)).unwrap(), b"elf", "{}", name);
+            }
+            assert!(!cache_dir.join("postgresql-16.4.0").exists());
+        }
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let archive = temp_dir.path().join("pg.tar.bz2");
+        fs::write(&archive, b"BZh91AY&SY").unwrap();
+        assert_eq!(
+            unpack_postgres(&archive, &temp_dir.path().join("cache")).await,
+            Err(Error::InvalidPgPackage)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_components() {
+        assert_eq!(
+            strip_components(Path::new("./top/bin/initdb"), 1),
+            Some(PathBuf::from("bin/initdb"))
+        );
+        assert_eq!(strip_components(Path::new("top/"), 1), None);
+        assert_eq!(
+            strip_components(Path::new("../bin/initdb"), 1),
+            Some(PathBuf::from("../bin/initdb"))
+        );
+    }
+
+    /// Writes a zonky-style JAR with a single `.txz` entry holding `tar_content`.
+    fn write_jar(zip_file_path: &Path, tar_content: &[u8]) {
+        let zip_file = File::create(zip_file_path).expect("Failed to create zip file");
+        let mut zip_writer = ZipWriter::new(zip_file);
+        zip_writer
+            .start_file("postgres-test.txz", SimpleFileOptions::default())
+            .expect("Failed to start zip entry");
+        zip_writer
+            .write_all(&compress_with_xz(tar_content))
+            .expect("Failed to write compressed content to zip file");
+        zip_writer.finish().expect("Failed to finish zip file");
+    }
+
+    /// Create a tar archive from raw `(type, path, link target, data)`
+    /// entries, bypassing the path checks of [`tar::Builder`].
+    fn raw_tar_content(entries: &[RawEntry<'_>]) -> Vec<u8> {
+        let mut tar_data = Vec::new();
+        {
+            let mut ar = tar::Builder::new(&mut tar_data);
+            for (entry_type, path, link, data) in entries {
+                let mut header = tar::Header::new_gnu();
+                let old = header.as_old_mut();
+                old.name[..path.len()].copy_from_slice(path.as_bytes());
+                old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+                header.set_entry_type(*entry_type);
+                header.set_size(data.len() as u64);
+                header.set_mode(0o755);
+                header.set_cksum();
+                ar.append(&header, *data).expect("Failed to add entry to tar");
+            }
+        }
+        tar_data
+    }
+
     /// Create a minimal tar archive containing a single dummy file
     fn create_dummy_tar_content() -> Vec<u8> {
         let mut tar_data = Vec::new();
@@ -139,11 +907,11 @@ mod tests {
         tar_data
     }
 
-    /// Compress `data` using XZ (LZMA2) via lzma-rs
+    /// Compress `data` using XZ (LZMA2) via lzma-rust2
     fn compress_with_xz(data: &[u8]) -> Vec<u8> {
-        let mut compressed = Vec::new();
-        lzma_rs::xz_compress(&mut Cursor::new(data), &mut compressed)
-            .expect("Failed to compress data with xz");
-        compressed
+        let mut writer = lzma_rust2::XzWriter::new(Vec::new(), lzma_rust2::XzOptions::default())
+            .expect("Failed to create xz writer");
+        std::io::copy(&mut Cursor::new(data), &mut writer).expect("Failed to compress data with xz");
+        writer.finish().expect("Failed to finish xz stream")
     }
 }
@@ -0,0 +1,169 @@
+//! Minimal HTTP/1.1 stand-in for exercising download code without network
+//! access.
+//!
+//! [`serve`] binds an ephemeral port on `127.0.0.1` and answers every
+//! request with whatever the handler returns.  Each connection serves exactly
+//! one request and is then closed.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A request received by the stand-in.
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    /// Request path, e.g. `/maven2/…/x.jar`.
+    pub path: String,
+    /// Header names (lowercased) and values.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Returns the value of header `name` (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the start offset of a `Range: bytes=N-` header.
+    pub fn range_start(&self) -> Option<u64> {
+        self.header("range")?
+            .strip_prefix("bytes=")?
+            .trim_end_matches('-')
+            .parse()
+            .ok()
+    }
+}
+
+/// A response to send back.
+#[derive(Debug, Clone)]
+pub(crate) struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    truncate_at: Option<usize>,
+}
+
+impl Response {
+    /// A response with `status` and an empty body.
+    pub fn status(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            truncate_at: None,
+        }
+    }
+
+    /// A `200 OK` response with `body`.
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::status(200).body(body)
+    }
+
+    /// Replaces the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Adds a header.
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Announces the full body length but closes the connection after
+    /// `len` bytes, simulating an interrupted transfer.
+    pub fn truncated(mut self, len: usize) -> Self {
+        self.truncate_at = Some(len);
+        self
+    }
+}
+
+/// A running stand-in server.  Stops when dropped.
+pub(crate) struct StubServer {
+    /// Base URL, e.g. `http://127.0.0.1:40123`.
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl StubServer {
+    /// Returns every request received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Starts a stand-in that answers each request with `handler(request, n)`,
+/// where `n` counts the requests received so far, starting at 0.
+pub(crate) async fn serve<F>(handler: F) -> StubServer
+where
+    F: Fn(&Request, usize) -> Response + Send + Sync + 'static,
+{
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    let handle = tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let Some(request) = read_request(&mut stream).await else {
+                continue;
+            };
+            let n = {
+                let mut received = received.lock().unwrap();
+                received.push(request.clone());
+                received.len() - 1
+            };
+            let response = handler(&request, n);
+            let mut head = format!(
+                "HTTP/1.1 {} Stub\r\nContent-Length: {}\r\nConnection: close\r\n",
+                response.status,
+                response.body.len()
+            );
+            for (name, value) in &response.headers {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            head.push_str("\r\n");
+            let body_len = response.truncate_at.unwrap_or(response.body.len());
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(&response.body[..body_len]).await;
+            let _ = stream.shutdown().await;
+        }
+    });
+    StubServer {
+        url,
+        requests,
+        handle,
+    }
+}
+
+/// Reads a request head (GET requests have no body).
+async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+    let head = String::from_utf8_lossy(&buffer);
+    let mut lines = head.split("\r\n");
+    let path = lines.next()?.split_whitespace().nth(1)?.to_string();
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    Some(Request { path, headers })
+}
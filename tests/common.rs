@@ -7,17 +7,17 @@ use tempfile::TempDir;
 use pg_embed::pg_enums::PgAuthMethod;
 use pg_embed::pg_errors::{Error, Result};
 use pg_embed::pg_fetch::{PgFetchSettings, PG_V17};
-use pg_embed::postgres::{PgEmbed, PgSettings};
+use pg_embed::postgres::{AUTO_PORT, PgEmbed, PgSettings};
 
 /// Sets up a [`PgEmbed`] instance against `database_dir`.
 ///
 /// Initialises logging, constructs [`PgSettings`] and [`PgFetchSettings`] with
-/// sensible defaults (PG 17, MD5 auth, 10-second timeout), creates the
+/// sensible defaults (PG 17, MD5 auth, 10-second timeout, automatic port so
+/// tests can run in parallel), creates the
 /// [`PgEmbed`] instance, and runs [`PgEmbed::setup`].
 ///
 /// # Arguments
 ///
-/// * `port` — TCP port the PostgreSQL server will listen on.
 /// * `database_dir` — Directory for the cluster data files.
 /// * `persistent` — If `false`, the cluster is deleted when [`PgEmbed`] is
 ///   dropped.
@@ -27,7 +27,6 @@ use pg_embed::postgres::{PgEmbed, PgSettings};
 ///
 /// Returns any error from [`PgEmbed::new`] or [`PgEmbed::setup`].
 pub async fn setup(
-    port: u16,
     database_dir: PathBuf,
     persistent: bool,
     migration_dir: Option<PathBuf>,
@@ -37,13 +36,14 @@ pub async fn setup(
         .try_init();
     let pg_settings = PgSettings {
         database_dir,
-        port,
+        port: AUTO_PORT,
         user: "postgres".to_string(),
         password: "password".to_string(),
         auth_method: PgAuthMethod::MD5,
         persistent,
         timeout: Some(Duration::from_secs(10)),
         migration_dir,
+        ..Default::default()
     };
     let fetch_settings = PgFetchSettings {
         version: PG_V17,
@@ -71,7 +71,6 @@ pub async fn setup(
 ///
 /// # Arguments
 ///
-/// * `port` — TCP port the PostgreSQL server will listen on.
 /// * `persistent` — If `false`, the cluster is deleted when [`PgEmbed`] is
 ///   dropped (in addition to the [`TempDir`] cleanup).
 /// * `migration_dir` — Optional path containing `.sql` migration files.
@@ -81,11 +80,10 @@ pub async fn setup(
 /// Returns [`Error::DirCreationError`] if the temporary directory cannot be
 /// created, or any error from [`setup`].
 pub async fn setup_with_tempdir(
-    port: u16,
     persistent: bool,
     migration_dir: Option<PathBuf>,
 ) -> Result<(TempDir, PgEmbed)> {
     let dir = TempDir::new().map_err(|e| Error::DirCreationError(e.to_string()))?;
-    let pg = setup(port, dir.path().join("db"), persistent, migration_dir).await?;
+    let pg = setup(dir.path().join("db"), persistent, migration_dir).await?;
     Ok((dir, pg))
 }
@@ -1,6 +1,5 @@
 use std::time::Duration;
 
-use serial_test::file_serial;
 use tempfile::TempDir;
 
 use pg_embed::pg_enums::PgAuthMethod;
@@ -12,9 +11,8 @@ use pg_embed::postgres::{PgEmbed, PgSettings};
 mod common;
 
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn create_database() -> Result<()> {
-    let (_dir, mut pg) = common::setup_with_tempdir(5432, false, None).await?;
+    let (_dir, mut pg) = common::setup_with_tempdir(false, None).await?;
     pg.start_db().await?;
 
     pg.create_database("test").await?;
@@ -23,9 +21,8 @@ async fn create_database() -> Result<()> {
 }
 
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn drop_database() -> Result<()> {
-    let (_dir, mut pg) = common::setup_with_tempdir(5432, false, None).await?;
+    let (_dir, mut pg) = common::setup_with_tempdir(false, None).await?;
     pg.start_db().await?;
 
     pg.create_database("test").await?;
@@ -38,9 +35,8 @@ async fn drop_database() -> Result<()> {
 
 /// Verify that `database_exists` returns `false` for a database that was never created.
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn database_exists_false() -> Result<()> {
-    let (_dir, mut pg) = common::setup_with_tempdir(5432, false, None).await?;
+    let (_dir, mut pg) = common::setup_with_tempdir(false, None).await?;
     pg.start_db().await?;
     assert!(!pg.database_exists("nonexistent_db_xyz").await?);
     Ok(())
@@ -48,9 +44,8 @@ async fn database_exists_false() -> Result<()> {
 
 /// Verify that creating the same database twice returns an error.
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn create_duplicate() -> Result<()> {
-    let (_dir, mut pg) = common::setup_with_tempdir(5432, false, None).await?;
+    let (_dir, mut pg) = common::setup_with_tempdir(false, None).await?;
     pg.start_db().await?;
     pg.create_database("dup_test").await?;
     let result = pg.create_database("dup_test").await;
@@ -63,9 +58,8 @@ async fn create_duplicate() -> Result<()> {
 /// sqlx's `Postgres::drop_database` uses `DROP DATABASE IF EXISTS` semantics,
 /// so dropping a non-existent database succeeds silently.
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn drop_nonexistent() -> Result<()> {
-    let (_dir, mut pg) = common::setup_with_tempdir(5432, false, None).await?;
+    let (_dir, mut pg) = common::setup_with_tempdir(false, None).await?;
     pg.start_db().await?;
     pg.drop_database("this_db_does_not_exist_xyz").await?;
     Ok(())
@@ -84,6 +78,7 @@ async fn full_uri_format() -> Result<()> {
         persistent: false,
         timeout: Some(Duration::from_secs(10)),
         migration_dir: None,
+        ..Default::default()
     };
     let fetch_settings = PgFetchSettings { version: PG_V17, ..Default::default() };
     let pg = PgEmbed::new(pg_settings, fetch_settings).await?;
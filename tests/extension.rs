@@ -1,4 +1,3 @@
-use serial_test::file_serial;
 use tempfile::TempDir;
 
 use pg_embed::pg_errors::{Error, Result};
@@ -14,9 +13,8 @@ mod common;
 /// `CREATE EXTENSION`.  The extension's function is called to confirm it is
 /// fully operational.
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn install_and_use() -> Result<()> {
-    let (_dir, mut pg) = common::setup_with_tempdir(5432, false, None).await?;
+    let (_dir, mut pg) = common::setup_with_tempdir(false, None).await?;
 
     let ext_dir = TempDir::new().map_err(|e| Error::DirCreationError(e.to_string()))?;
     std::fs::write(
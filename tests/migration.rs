@@ -1,6 +1,5 @@
 use std::path::PathBuf;
 
-use serial_test::file_serial;
 use tempfile::TempDir;
 
 use pg_embed::pg_errors::{Error, Result};
@@ -10,10 +9,8 @@ use sqlx::{Connection, PgConnection};
 mod common;
 
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn migration() -> Result<()> {
     let (_dir, mut pg) = common::setup_with_tempdir(
-        5432,
         false,
         Some(PathBuf::from("migration_test")),
     )
@@ -42,9 +39,8 @@ async fn migration() -> Result<()> {
 
 /// Verify that `migrate()` is a no-op (returns `Ok`) when `migration_dir` is `None`.
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn migrate_no_dir() -> Result<()> {
-    let (_dir, mut pg) = common::setup_with_tempdir(5432, false, None).await?;
+    let (_dir, mut pg) = common::setup_with_tempdir(false, None).await?;
     pg.start_db().await?;
     pg.create_database("test_nodir").await?;
     pg.migrate("test_nodir").await?;
@@ -53,10 +49,8 @@ async fn migrate_no_dir() -> Result<()> {
 
 /// Verify that `migrate()` returns `SqlQueryError` when the target database does not exist.
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn migrate_nonexistent_database() -> Result<()> {
     let (_dir, mut pg) = common::setup_with_tempdir(
-        5432,
         false,
         Some(PathBuf::from("migration_test")),
     )
@@ -70,7 +64,6 @@ async fn migrate_nonexistent_database() -> Result<()> {
 
 /// Verify that a migration file containing invalid SQL returns `MigrationError`.
 #[tokio::test]
-#[file_serial(pg_port_5432)]
 async fn migration_invalid_sql() -> Result<()> {
     // migration_dir declared first so it outlives pg
     let migration_dir = TempDir::new().map_err(|e| Error::DirCreationError(e.to_string()))?;
@@ -81,7 +74,6 @@ async fn migration_invalid_sql() -> Result<()> {
     .map_err(|e| Error::WriteFileError(e.to_string()))?;
 
     let (_dir, mut pg) = common::setup_with_tempdir(
-        5432,
         false,
         Some(migration_dir.path().to_path_buf()),
     )
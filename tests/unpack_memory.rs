@@ -0,0 +1,126 @@
+//! Peak-memory check for `pg_unpack`.
+//!
+//! A counting global allocator tracks the bytes live at any moment.  The test
+//! builds a bundle whose tarball is far larger than the allowed budget and
+//! asserts that unpacking it never holds more than the budget at once.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lzma_rust2::{XzOptions, XzWriter};
+use tempfile::TempDir;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+use pg_embed::pg_errors::Result;
+use pg_embed::pg_unpack;
+
+/// Size of each file in the synthetic tarball.
+const FILE_SIZE: u64 = 16 * 1024 * 1024;
+/// Number of files in the synthetic tarball.
+const FILE_COUNT: u64 = 4;
+/// Allowed growth of live heap memory while unpacking.
+///
+/// Covers the 8 MiB XZ dictionary of the default preset plus I/O buffers,
+/// and is well below the 64 MiB the tarball expands to.
+const MEMORY_BUDGET: usize = 24 * 1024 * 1024;
+
+struct CountingAllocator;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Pseudo-random bytes that still compress well, so the bundle is quick to
+/// build but the decoder cannot shortcut long runs.
+struct Pattern(u64);
+
+impl Read for Pattern {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for chunk in buf.chunks_mut(64) {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);
+            chunk.fill((self.0 >> 56) as u8);
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Writes a zonky-style JAR with a `.txz` entry holding `FILE_COUNT` files of
+/// `FILE_SIZE` bytes, streaming every stage so the test itself stays small.
+fn write_large_bundle(jar: &std::path::Path) {
+    let mut zip = ZipWriter::new(File::create(jar).unwrap());
+    zip.start_file(
+        "postgres-linux-x86_64.txz",
+        SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored),
+    )
+    .unwrap();
+    {
+        // The fastest preset keeps the test quick; the dictionary matches the
+        // default preset so the decoder allocates what it would for zonky.
+        let mut options = XzOptions::with_preset(0);
+        options.lzma_options.dict_size = 8 * 1024 * 1024;
+        let xz = XzWriter::new(&mut zip, options).unwrap();
+        let mut tar = tar::Builder::new(xz);
+        for i in 0..FILE_COUNT {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(FILE_SIZE);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(
+                &mut header,
+                format!("share/blob-{}", i),
+                Pattern(i).take(FILE_SIZE),
+            )
+            .unwrap();
+        }
+        let xz = tar.into_inner().unwrap();
+        xz.finish().unwrap().flush().unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+#[tokio::test]
+async fn unpack_memory_is_bounded() -> Result<()> {
+    let dir = TempDir::new().unwrap();
+    let jar = dir.path().join("postgres.jar");
+    let out = dir.path().join("out");
+    write_large_bundle(&jar);
+
+    let baseline = LIVE.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    pg_unpack::unpack_postgres(&jar, &out).await?;
+    let growth = PEAK.load(Ordering::Relaxed).saturating_sub(baseline);
+
+    for i in 0..FILE_COUNT {
+        let len = std::fs::metadata(out.join(format!("share/blob-{}", i)))
+            .unwrap()
+            .len();
+        assert_eq!(len, FILE_SIZE);
+    }
+    assert!(
+        growth < MEMORY_BUDGET,
+        "unpacking {} MiB held {} MiB at peak",
+        FILE_SIZE * FILE_COUNT / (1024 * 1024),
+        growth / (1024 * 1024)
+    );
+    Ok(())
+}